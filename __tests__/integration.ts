@@ -97,7 +97,7 @@ async function performCalculation(contractAddress: string, secretjs: SecretNetwo
         sentFunds: [],
       });
       break;
-    default:
+    case Operations.DIV:
       addMsg = new MsgExecuteContract({
         sender: accounts[0].address,
         contract: contractAddress,
@@ -105,6 +105,15 @@ async function performCalculation(contractAddress: string, secretjs: SecretNetwo
         msg: { [operation]: [num1, num2] },
         sentFunds: [],
       });
+      break;
+    default:
+      addMsg = new MsgExecuteContract({
+        sender: accounts[0].address,
+        contract: contractAddress,
+        // codeHash, // Test MsgExecuteContract without codeHash
+        msg: { [operation]: { operands: [num1, num2] } },
+        sentFunds: [],
+      });
   }
 
   const tx = await secretjs.tx.broadcast([addMsg], {
@@ -3,7 +3,7 @@ use std::fs::create_dir_all;
 
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
-use simplecalculator::msg::{HandleAnswer, HandleMsg, InitMsg, QueryAnswer, QueryMsg};
+use simplecalculator::msg::{HandleAnswer, HandleMsg, InitMsg, MigrateMsg, QueryAnswer, QueryMsg};
 use simplecalculator::state::State;
 
 fn main() {
@@ -15,6 +15,7 @@ fn main() {
     export_schema(&schema_for!(InitMsg), &out_dir);
     export_schema(&schema_for!(HandleMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
+    export_schema(&schema_for!(MigrateMsg), &out_dir);
     export_schema(&schema_for!(State), &out_dir);
     export_schema(&schema_for!(HandleAnswer), &out_dir);
     export_schema(&schema_for!(QueryAnswer), &out_dir);
@@ -0,0 +1,52 @@
+// The code generated by `construct_uint!` trips a number of clippy lints
+#![allow(clippy::all)]
+
+use uint::construct_uint;
+
+construct_uint! {
+    /// A 256-bit unsigned integer, used for intermediate results that may not fit in 128 bits
+    pub struct U256(4);
+}
+
+construct_uint! {
+    /// A 512-bit unsigned integer, used for intermediate results that may not fit in 256 bits
+    pub struct U512(8);
+}
+
+construct_uint! {
+    /// A 1024-bit unsigned integer, used for products of big integers
+    pub struct U1024(16);
+}
+
+impl From<U256> for U512 {
+    fn from(n: U256) -> Self {
+        let mut limbs = [0u64; 8];
+        limbs[..4].copy_from_slice(&n.0);
+        U512(limbs)
+    }
+}
+
+impl From<U512> for U1024 {
+    fn from(n: U512) -> Self {
+        let mut limbs = [0u64; 16];
+        limbs[..8].copy_from_slice(&n.0);
+        U1024(limbs)
+    }
+}
+
+impl U1024 {
+    /// Returns the number as a U512, if it fits in one
+    pub fn to_u512(self) -> Option<U512> {
+        if self.bits() > 512 {
+            return None;
+        }
+        Some(self.low_u512())
+    }
+
+    /// Returns the lowest 512 bits of the number
+    pub fn low_u512(self) -> U512 {
+        let mut limbs = [0u64; 8];
+        limbs.copy_from_slice(&self.0[..8]);
+        U512(limbs)
+    }
+}
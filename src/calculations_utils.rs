@@ -1,35 +1,73 @@
-use cosmwasm_std::{StdError, StdResult, Uint128};
-pub type ArithmeticCalculation = fn(n1: Uint128, n2: Uint128) -> StdResult<Uint128>;
+use crate::big_uint::{U1024, U256, U512};
+use crate::decimal::{validate_decimal_places, BigDecimal, Decimal, RoundingMode};
+use crate::error::{
+    CalculatorError, CalculatorResult, DivideByZero, InvalidInput, Overflow, Underflow,
+};
+use crate::int128::Int128;
+use crate::overflow::OverflowMode;
+use crate::state::{Factor, Statistics};
+use crate::uint512::{Uint512, MAX_UINT512_DIGITS};
+use cosmwasm_std::Uint128;
+use snafu::{ensure, OptionExt};
+use std::convert::TryFrom;
+use std::fmt::Display;
+pub type ArithmeticCalculation = fn(n1: Uint128, n2: Uint128) -> CalculatorResult<Uint128>;
+pub type OverflowingCalculation =
+    fn(n1: Uint128, n2: Uint128, overflow_mode: OverflowMode) -> CalculatorResult<Uint128>;
+pub type DecimalCalculation = fn(
+    n1: Decimal,
+    n2: Decimal,
+    decimal_places: u32,
+    rounding: RoundingMode,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Decimal>;
 
-pub fn calculate_add(n1: Uint128, n2: Uint128) -> StdResult<Uint128> {
+const NEGATIVE_RESULT: &str =
+    "The second argument is larger than the first, cannot calculate negative results";
+const DIVISION_BY_ZERO: &str = "Cannot divide by zero!";
+const MODULO_ZERO: &str = "Cannot calculate modulo zero!";
+pub(crate) const RESULT_TOO_LARGE: &str = "The result is too large";
+
+pub fn calculate_add(n1: Uint128, n2: Uint128) -> CalculatorResult<Uint128> {
     n1.u128()
         .checked_add(n2.u128())
-        .ok_or_else(|| StdError::generic_err("Invalid input: The input numbers are too large"))
+        .context(Overflow {
+            message: "The input numbers are too large",
+        })
         .map(Uint128)
 }
 
-pub fn calculate_sub(n1: Uint128, n2: Uint128) -> StdResult<Uint128> {
+pub fn calculate_sub(n1: Uint128, n2: Uint128) -> CalculatorResult<Uint128> {
     n1.u128()
-    .checked_sub(n2.u128())
-    .ok_or_else(|| StdError::generic_err("Invalid input: The second argument is larger than the first, cannot calculate negative results"))
-    .map(Uint128)
+        .checked_sub(n2.u128())
+        .context(Underflow {
+            message: NEGATIVE_RESULT,
+        })
+        .map(Uint128)
 }
 
-pub fn calculate_mul(n1: Uint128, n2: Uint128) -> StdResult<Uint128> {
+pub fn calculate_mul(n1: Uint128, n2: Uint128) -> CalculatorResult<Uint128> {
     n1.u128()
-    .checked_mul(n2.u128())
-    .ok_or_else(|| StdError::generic_err("Invalid input: The multiplication is too large. Cannot calculate results larger than"))
-    .map(Uint128)
+        .checked_mul(n2.u128())
+        .context(Overflow {
+            message: format!(
+                "The multiplication is too large. Cannot calculate results larger than {}",
+                u128::MAX
+            ),
+        })
+        .map(Uint128)
 }
 
-pub fn calculate_div(n1: Uint128, n2: Uint128) -> StdResult<Uint128> {
+pub fn calculate_div(n1: Uint128, n2: Uint128) -> CalculatorResult<Uint128> {
     n1.u128()
         .checked_div(n2.u128())
-        .ok_or_else(|| StdError::generic_err("Invalid input: Cannot devide by zero!"))
+        .context(DivideByZero {
+            message: DIVISION_BY_ZERO,
+        })
         .map(Uint128)
 }
 
-pub fn calculate_sqrt(n1: Uint128, _n2: Uint128) -> StdResult<Uint128> {
+pub fn calculate_sqrt(n1: Uint128, _n2: Uint128) -> CalculatorResult<Uint128> {
     let n1_u128 = n1.u128();
     let mut left: u128 = 0;
     let mut right: u128 = n1_u128;
@@ -72,19 +110,1067 @@ pub fn calculate_sqrt(n1: Uint128, _n2: Uint128) -> StdResult<Uint128> {
     Ok(Uint128::from(left))
 }
 
-pub fn get_calculation_string(
+/// The nth root of value, rounded down, along with the remainder `value - root^n`, which is zero
+/// exactly when the root is exact. e.g. the cube root of 30 is 3, with a remainder of 3.
+pub fn calculate_nth_root_with_remainder(
+    value: Uint128,
+    n: u32,
+) -> CalculatorResult<(Uint128, Uint128)> {
+    ensure!(
+        n != 0,
+        InvalidInput {
+            message: "Cannot calculate the 0th root",
+        }
+    );
+
+    // Binary search for the largest root whose nth power doesn't exceed the value. The root of a
+    // 128 bit number has at most ceil(128 / n) bits, computed without overflowing for large n.
+    let value = value.u128();
+    let mut low: u128 = 0;
+    let mut high: u128 = if n == 1 {
+        value
+    } else {
+        value.min(1 << (128 / n + u32::from(128 % n != 0)))
+    };
+    while low < high {
+        // Rounds up, without overflowing when the range spans all of u128
+        let middle = low + (high - low) / 2 + (high - low) % 2;
+        match middle.checked_pow(n) {
+            Some(pow) if pow <= value => low = middle,
+            _ => high = middle - 1,
+        }
+    }
+
+    Ok((Uint128(low), Uint128(value - low.pow(n))))
+}
+
+pub fn calculate_nth_root(value: Uint128, n: Uint128) -> CalculatorResult<Uint128> {
+    let n = u32::try_from(n.u128()).ok().context(InvalidInput {
+        message: "The degree of the root is too large",
+    })?;
+    calculate_nth_root_with_remainder(value, n).map(|(root, _)| root)
+}
+
+pub fn calculate_pow(n1: Uint128, n2: Uint128) -> CalculatorResult<Uint128> {
+    // Powers of 0 and 1 never overflow, however large the exponent
+    match (n1.u128(), n2.u128()) {
+        (_, 0) | (1, _) => return Ok(Uint128(1)),
+        (0, _) => return Ok(Uint128::zero()),
+        _ => {}
+    }
+    u32::try_from(n2.u128())
+        .ok()
+        .and_then(|exponent| n1.u128().checked_pow(exponent))
+        .context(Overflow {
+            message: "The power is too large",
+        })
+        .map(Uint128)
+}
+
+pub fn calculate_mod(n1: Uint128, n2: Uint128) -> CalculatorResult<Uint128> {
+    n1.u128()
+        .checked_rem(n2.u128())
+        .context(DivideByZero {
+            message: MODULO_ZERO,
+        })
+        .map(Uint128)
+}
+
+/// The integer logarithm of n1 in base 2, rounded down. e.g. log2(10) = 3.
+pub fn calculate_log2(n1: Uint128, _n2: Uint128) -> CalculatorResult<Uint128> {
+    calculate_log(n1, Uint128(2))
+}
+
+/// The integer logarithm of n1 in base 10, rounded down. e.g. log10(999) = 2.
+pub fn calculate_log10(n1: Uint128, _n2: Uint128) -> CalculatorResult<Uint128> {
+    calculate_log(n1, Uint128(10))
+}
+
+/// The integer logarithm of n1 in base n2, rounded down. e.g. log3(80) = 3.
+pub fn calculate_log(n1: Uint128, n2: Uint128) -> CalculatorResult<Uint128> {
+    ensure!(
+        n2.u128() >= 2,
+        InvalidInput {
+            message: "The base of a logarithm must be at least 2",
+        }
+    );
+    ensure!(
+        !n1.is_zero(),
+        InvalidInput {
+            message: LOG_OF_ZERO,
+        }
+    );
+
+    // Count how many times the value can be divided by the base before dropping below it
+    let base = n2.u128();
+    let mut value = n1.u128();
+    let mut log = 0u128;
+    while value >= base {
+        value /= base;
+        log += 1;
+    }
+    Ok(Uint128(log))
+}
+
+const LOG_OF_ZERO: &str = "The logarithm of zero is undefined";
+
+pub fn calculate_gcd(n1: Uint128, n2: Uint128) -> CalculatorResult<Uint128> {
+    Ok(Uint128(gcd(n1.u128(), n2.u128())))
+}
+
+/// The least common multiple of n1 and n2, which is 0 if either of them is 0
+pub fn calculate_lcm(n1: Uint128, n2: Uint128) -> CalculatorResult<Uint128> {
+    if n1.is_zero() || n2.is_zero() {
+        return Ok(Uint128::zero());
+    }
+    (n1.u128() / gcd(n1.u128(), n2.u128()))
+        .checked_mul(n2.u128())
+        .context(Overflow {
+            message: "The least common multiple is too large",
+        })
+        .map(Uint128)
+}
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+pub fn calculate_overflowing_add(
+    n1: Uint128,
+    n2: Uint128,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Uint128> {
+    let (a, b) = (n1.u128(), n2.u128());
+    overflow_mode.resolve(
+        calculate_add(n1, n2),
+        || Uint128(a.saturating_add(b)),
+        || Uint128(a.wrapping_add(b)),
+    )
+}
+
+/// Subtracts n2 from n1. A negative result saturates to 0
+pub fn calculate_overflowing_sub(
+    n1: Uint128,
+    n2: Uint128,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Uint128> {
+    let (a, b) = (n1.u128(), n2.u128());
+    overflow_mode.resolve(
+        calculate_sub(n1, n2),
+        || Uint128(a.saturating_sub(b)),
+        || Uint128(a.wrapping_sub(b)),
+    )
+}
+
+pub fn calculate_overflowing_mul(
     n1: Uint128,
     n2: Uint128,
-    operation: &str,
-    result: Uint128,
-) -> String {
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Uint128> {
+    let (a, b) = (n1.u128(), n2.u128());
+    overflow_mode.resolve(
+        calculate_mul(n1, n2),
+        || Uint128(a.saturating_mul(b)),
+        || Uint128(a.wrapping_mul(b)),
+    )
+}
+
+pub fn calculate_overflowing_pow(
+    n1: Uint128,
+    n2: Uint128,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Uint128> {
+    let (a, b) = (n1.u128(), n2.u128());
+    overflow_mode.resolve(
+        calculate_pow(n1, n2),
+        || Uint128(u128::MAX),
+        || Uint128(wrapping_pow(a, b)),
+    )
+}
+
+pub fn calculate_overflowing_lcm(
+    n1: Uint128,
+    n2: Uint128,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Uint128> {
+    let (a, b) = (n1.u128(), n2.u128());
+    overflow_mode.resolve(
+        calculate_lcm(n1, n2),
+        || Uint128(u128::MAX),
+        || Uint128((a / gcd(a, b)).wrapping_mul(b)),
+    )
+}
+
+/// base^exponent modulo 2^128, by exponentiation by squaring
+fn wrapping_pow(mut base: u128, mut exponent: u128) -> u128 {
+    let mut result: u128 = 1;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result.wrapping_mul(base);
+        }
+        base = base.wrapping_mul(base);
+        exponent >>= 1;
+    }
+    result
+}
+
+const SMALL_PRIMES: [u128; 20] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71,
+];
+
+/// The first 13 primes, up to 41, are proven to be enough Miller-Rabin witnesses for the test to
+/// be exact below this number (Sorenson and Webster, 2015)
+pub const MAX_PROVEN_PRIME: u128 = 3_317_044_064_679_887_385_961_981;
+
+/// Tests n for primality with the deterministic Miller-Rabin test. Numbers it shows to be
+/// composite are composite, but primality is only proven below `MAX_PROVEN_PRIME`, so probable
+/// primes above it are an error rather than an unproven answer.
+pub fn calculate_is_prime(n: Uint128) -> CalculatorResult<bool> {
+    let n = n.u128();
+    if n < 2 {
+        return Ok(false);
+    }
+    for &p in SMALL_PRIMES.iter() {
+        if n % p == 0 {
+            return Ok(n == p);
+        }
+    }
+
+    let is_probable_prime = is_strong_probable_prime(n);
+    ensure!(
+        !is_probable_prime || n < MAX_PROVEN_PRIME,
+        InvalidInput {
+            message: format!(
+                "Primality can only be proven below {}, and {} is probably prime",
+                MAX_PROVEN_PRIME, n
+            ),
+        }
+    );
+    Ok(is_probable_prime)
+}
+
+/// Runs the Miller-Rabin test on the odd number n, using the first 13 primes as witnesses
+fn is_strong_probable_prime(n: u128) -> bool {
+    let exponent = (n - 1).trailing_zeros();
+    let odd_part = (n - 1) >> exponent;
+    SMALL_PRIMES[..13].iter().all(|&witness| {
+        let mut x = pow_mod(witness, odd_part, n);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        for _ in 1..exponent {
+            x = mul_mod(x, x, n);
+            if x == n - 1 {
+                return true;
+            }
+        }
+        false
+    })
+}
+
+fn mul_mod(a: u128, b: u128, modulus: u128) -> u128 {
+    ((U256::from(a) * U256::from(b)) % U256::from(modulus)).as_u128()
+}
+
+fn pow_mod(mut base: u128, mut exponent: u128, modulus: u128) -> u128 {
+    let mut result = 1;
+    base %= modulus;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul_mod(result, base, modulus);
+        }
+        base = mul_mod(base, base, modulus);
+        exponent >>= 1;
+    }
+    result
+}
+
+/// Factors are looked for by trial division up to this divisor, and with Pollard's rho above it
+const MAX_TRIAL_DIVISOR: u128 = 10_000;
+/// The most steps Pollard's rho may take in total while factorizing a number, to keep the gas
+/// usage bounded. Each step costs about 5,000 native instructions, so the worst case, a product
+/// of two primes near 2^64, runs about 55 million instructions before giving up, which stays
+/// around 1.6 million gas even if Wasm needs three times as many instructions
+const MAX_POLLARD_RHO_STEPS: u32 = 10_000;
+
+/// Factorizes n into its prime factors, in increasing order
+pub fn calculate_factorize(n: Uint128) -> CalculatorResult<Vec<Factor>> {
+    let mut n = n.u128();
+    ensure!(
+        n != 0,
+        InvalidInput {
+            message: "Cannot factorize zero",
+        }
+    );
+
+    let mut primes = vec![];
+    let mut divisor = 2;
+    while divisor <= MAX_TRIAL_DIVISOR && divisor * divisor <= n {
+        while n % divisor == 0 {
+            primes.push(divisor);
+            n /= divisor;
+        }
+        divisor += if divisor == 2 { 1 } else { 2 };
+    }
+
+    let mut steps_left = MAX_POLLARD_RHO_STEPS;
+    let mut composites = vec![n];
+    while let Some(n) = composites.pop() {
+        if n == 1 {
+            continue;
+        }
+        if calculate_is_prime(Uint128(n))? {
+            primes.push(n);
+            continue;
+        }
+        let factor = pollard_rho(n, &mut steps_left)?;
+        composites.push(factor);
+        composites.push(n / factor);
+    }
+    primes.sort_unstable();
+
+    let mut factors: Vec<Factor> = vec![];
+    for prime in primes {
+        match factors.last_mut() {
+            Some(factor) if factor.prime.u128() == prime => factor.exponent += 1,
+            _ => factors.push(Factor {
+                prime: Uint128(prime),
+                exponent: 1,
+            }),
+        }
+    }
+    Ok(factors)
+}
+
+/// Finds a non-trivial factor of the odd composite number n
+fn pollard_rho(n: u128, steps_left: &mut u32) -> CalculatorResult<u128> {
+    for c in 1..n {
+        // x -> x^2 + c (mod n), where c < n
+        let next = |x: u128| {
+            let square = mul_mod(x, x, n);
+            if square >= n - c {
+                square - (n - c)
+            } else {
+                square + c
+            }
+        };
+
+        let (mut x, mut y, mut divisor) = (2, 2, 1);
+        while divisor == 1 {
+            ensure!(
+                *steps_left != 0,
+                InvalidInput {
+                    message: TOO_HARD_TO_FACTORIZE,
+                }
+            );
+            *steps_left -= 1;
+
+            x = next(x);
+            y = next(next(y));
+            divisor = gcd(if x > y { x - y } else { y - x }, n);
+        }
+        if divisor != n {
+            return Ok(divisor);
+        }
+    }
+    InvalidInput {
+        message: TOO_HARD_TO_FACTORIZE,
+    }
+    .fail()
+}
+
+const TOO_HARD_TO_FACTORIZE: &str = "The number is too hard to factorize";
+
+/// The number of basis points in 100%
+pub const BPS_DENOMINATOR: u128 = 10_000;
+
+/// Calculates `a * b / denom`, rounding the quotient. The product is kept in 256 bits, so this
+/// only overflows if the quotient itself doesn't fit in 128 bits
+pub fn calculate_mul_div(
+    a: Uint128,
+    b: Uint128,
+    denom: Uint128,
+    rounding: RoundingMode,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Uint128> {
+    ensure!(
+        !denom.is_zero(),
+        DivideByZero {
+            message: DIVISION_BY_ZERO,
+        }
+    );
+    let quotient = rounding.divide(
+        U256::from(a.u128()) * U256::from(b.u128()),
+        U256::from(denom.u128()),
+    );
+    overflow_mode.resolve(
+        u128::try_from(quotient)
+            .ok()
+            .context(Overflow {
+                message: RESULT_TOO_LARGE,
+            })
+            .map(Uint128),
+        || Uint128(u128::MAX),
+        || Uint128(quotient.low_u128()),
+    )
+}
+
+/// Calculates `bps` basis points of `value`
+pub fn calculate_percent(
+    value: Uint128,
+    bps: Uint128,
+    rounding: RoundingMode,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Uint128> {
+    calculate_mul_div(
+        value,
+        bps,
+        Uint128(BPS_DENOMINATOR),
+        rounding,
+        overflow_mode,
+    )
+}
+
+/// Deducts a fee of `fee_bps` basis points from `amount`. The rounding applies to the fee
+pub fn calculate_apply_fee(
+    amount: Uint128,
+    fee_bps: Uint128,
+    rounding: RoundingMode,
+) -> CalculatorResult<Uint128> {
+    ensure!(
+        fee_bps.u128() <= BPS_DENOMINATOR,
+        InvalidInput {
+            message: format!("Fees are limited to {} basis points", BPS_DENOMINATOR),
+        }
+    );
+    // A fee of at most 100% can't overflow
+    let fee = calculate_percent(amount, fee_bps, rounding, OverflowMode::Checked)?;
+    calculate_sub(amount, fee)
+}
+
+pub fn calculate_signed_add(n1: Int128, n2: Int128) -> CalculatorResult<Int128> {
+    n1.i128()
+        .checked_add(n2.i128())
+        .ok_or_else(|| out_of_range(n1.i128() < 0))
+        .map(Int128)
+}
+
+pub fn calculate_signed_sub(n1: Int128, n2: Int128) -> CalculatorResult<Int128> {
+    n1.i128()
+        .checked_sub(n2.i128())
+        .ok_or_else(|| out_of_range(n1.i128() < 0))
+        .map(Int128)
+}
+
+pub fn calculate_signed_mul(n1: Int128, n2: Int128) -> CalculatorResult<Int128> {
+    n1.i128()
+        .checked_mul(n2.i128())
+        .context(Overflow {
+            message: "The multiplication is too large",
+        })
+        .map(Int128)
+}
+
+/// Divides n1 by n2, rounding towards zero. e.g. -7 / 2 = -3.
+pub fn calculate_signed_div(n1: Int128, n2: Int128) -> CalculatorResult<Int128> {
+    ensure!(
+        !n2.is_zero(),
+        DivideByZero {
+            message: DIVISION_BY_ZERO,
+        }
+    );
+
+    // The only other case in which the division fails is i128::MIN / -1
+    n1.i128()
+        .checked_div(n2.i128())
+        .ok_or_else(|| out_of_range(false))
+        .map(Int128)
+}
+
+/// The error of a signed result that is out of range, below it if `negative`
+fn out_of_range(negative: bool) -> CalculatorError {
+    let message = "The result is out of range".to_string();
+    if negative {
+        CalculatorError::Underflow { message }
+    } else {
+        CalculatorError::Overflow { message }
+    }
+}
+
+pub fn calculate_overflowing_signed_add(
+    n1: Int128,
+    n2: Int128,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Int128> {
+    let (a, b) = (n1.i128(), n2.i128());
+    overflow_mode.resolve(
+        calculate_signed_add(n1, n2),
+        || Int128(a.saturating_add(b)),
+        || Int128(a.wrapping_add(b)),
+    )
+}
+
+pub fn calculate_overflowing_signed_sub(
+    n1: Int128,
+    n2: Int128,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Int128> {
+    let (a, b) = (n1.i128(), n2.i128());
+    overflow_mode.resolve(
+        calculate_signed_sub(n1, n2),
+        || Int128(a.saturating_sub(b)),
+        || Int128(a.wrapping_sub(b)),
+    )
+}
+
+pub fn calculate_overflowing_signed_mul(
+    n1: Int128,
+    n2: Int128,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Int128> {
+    let (a, b) = (n1.i128(), n2.i128());
+    overflow_mode.resolve(
+        calculate_signed_mul(n1, n2),
+        || Int128(a.saturating_mul(b)),
+        || Int128(a.wrapping_mul(b)),
+    )
+}
+
+/// Divides n1 by n2, rounding towards zero. Division by zero fails in every mode
+pub fn calculate_overflowing_signed_div(
+    n1: Int128,
+    n2: Int128,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Int128> {
+    if n2.is_zero() {
+        return calculate_signed_div(n1, n2);
+    }
+    let (a, b) = (n1.i128(), n2.i128());
+    overflow_mode.resolve(
+        calculate_signed_div(n1, n2),
+        || Int128(a.saturating_div(b)),
+        || Int128(a.wrapping_div(b)),
+    )
+}
+
+pub fn calculate_big_add(n1: Uint512, n2: Uint512) -> CalculatorResult<Uint512> {
+    n1.u512()
+        .checked_add(n2.u512())
+        .context(Overflow {
+            message: "The input numbers are too large",
+        })
+        .and_then(Uint512::new)
+}
+
+pub fn calculate_big_sub(n1: Uint512, n2: Uint512) -> CalculatorResult<Uint512> {
+    n1.u512()
+        .checked_sub(n2.u512())
+        .context(Underflow {
+            message: NEGATIVE_RESULT,
+        })
+        .and_then(Uint512::new)
+}
+
+pub fn calculate_big_mul(n1: Uint512, n2: Uint512) -> CalculatorResult<Uint512> {
+    n1.u512()
+        .checked_mul(n2.u512())
+        .context(Overflow {
+            message: "The multiplication is too large",
+        })
+        .and_then(Uint512::new)
+}
+
+pub fn calculate_big_div(n1: Uint512, n2: Uint512) -> CalculatorResult<Uint512> {
+    n1.u512()
+        .checked_div(n2.u512())
+        .context(DivideByZero {
+            message: DIVISION_BY_ZERO,
+        })
+        .and_then(Uint512::new)
+}
+
+pub fn calculate_big_sqrt(n1: Uint512, _n2: Uint512) -> CalculatorResult<Uint512> {
+    Uint512::new(n1.u512().integer_sqrt())
+}
+
+pub fn calculate_big_pow(n1: Uint512, n2: Uint512) -> CalculatorResult<Uint512> {
+    n1.u512()
+        .checked_pow(n2.u512())
+        .context(Overflow {
+            message: "The power is too large",
+        })
+        .and_then(Uint512::new)
+}
+
+pub fn calculate_big_mod(n1: Uint512, n2: Uint512) -> CalculatorResult<Uint512> {
+    n1.u512()
+        .checked_rem(n2.u512())
+        .context(DivideByZero {
+            message: MODULO_ZERO,
+        })
+        .and_then(Uint512::new)
+}
+
+/// Adds n1 and n2. The overflowing modes behave as if big integers were fixed-size integers of
+/// MAX_UINT512_DIGITS digits, saturating to Uint512::max() or wrapping around modulo 10^150, as do
+/// those of the other big integer operations
+pub fn calculate_overflowing_big_add(
+    n1: Uint512,
+    n2: Uint512,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Uint512> {
+    let (a, b) = (U1024::from(n1.u512()), U1024::from(n2.u512()));
+    overflow_mode.resolve(calculate_big_add(n1, n2), Uint512::max, || {
+        Uint512::wrapping(a + b)
+    })
+}
+
+/// Subtracts n2 from n1. A negative result saturates to 0
+pub fn calculate_overflowing_big_sub(
+    n1: Uint512,
+    n2: Uint512,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Uint512> {
+    let (a, b) = (U1024::from(n1.u512()), U1024::from(n2.u512()));
+    let modulus = U1024::from(U512::exp10(MAX_UINT512_DIGITS));
+    overflow_mode.resolve(calculate_big_sub(n1, n2), Uint512::zero, || {
+        Uint512::wrapping(a + modulus - b)
+    })
+}
+
+pub fn calculate_overflowing_big_mul(
+    n1: Uint512,
+    n2: Uint512,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Uint512> {
+    let (a, b) = (U1024::from(n1.u512()), U1024::from(n2.u512()));
+    overflow_mode.resolve(calculate_big_mul(n1, n2), Uint512::max, || {
+        Uint512::wrapping(a * b)
+    })
+}
+
+/// Raises n1 to the power of n2. Powers of 0 and 1 never overflow
+pub fn calculate_overflowing_big_pow(
+    n1: Uint512,
+    n2: Uint512,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Uint512> {
+    overflow_mode.resolve(calculate_big_pow(n1, n2), Uint512::max, || {
+        // Exponentiation by squaring modulo 10^150
+        let modulus = U1024::from(U512::exp10(MAX_UINT512_DIGITS));
+        let (mut base, mut exponent) = (U1024::from(n1.u512()), n2.u512());
+        let mut result = U1024::one();
+        while !exponent.is_zero() {
+            if exponent.bit(0) {
+                result = result * base % modulus;
+            }
+            base = base * base % modulus;
+            exponent >>= 1;
+        }
+        Uint512::wrapping(result)
+    })
+}
+
+pub fn calculate_overflowing_big_lcm(
+    n1: Uint512,
+    n2: Uint512,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Uint512> {
+    overflow_mode.resolve(calculate_big_lcm(n1, n2), Uint512::max, || {
+        let quotient = n1.u512() / big_gcd(n1.u512(), n2.u512());
+        Uint512::wrapping(U1024::from(quotient) * U1024::from(n2.u512()))
+    })
+}
+
+/// The n2th root of n1, rounded down
+pub fn calculate_big_nth_root(n1: Uint512, n2: Uint512) -> CalculatorResult<Uint512> {
+    let n = n2
+        .to_uint128()
+        .and_then(|n| u32::try_from(n.u128()).ok())
+        .context(InvalidInput {
+            message: "The degree of the root is too large",
+        })?;
+    ensure!(
+        n != 0,
+        InvalidInput {
+            message: "Cannot calculate the 0th root",
+        }
+    );
+
+    // Binary search for the largest root whose nth power doesn't exceed the value, which has at
+    // most ceil(bits / n) bits
+    let value = n1.u512();
+    let root_bits = (value.bits() as u64 + n as u64 - 1) / n as u64;
+    let mut low = U512::zero();
+    let mut high = value.min(U512::one() << root_bits as usize);
+    while low < high {
+        let middle = low + (high - low + 1) / 2;
+        match middle.checked_pow(U512::from(n)) {
+            Some(pow) if pow <= value => low = middle,
+            _ => high = middle - 1,
+        }
+    }
+    Uint512::new(low)
+}
+
+/// The integer logarithm of n1 in base 2, rounded down
+pub fn calculate_big_log2(n1: Uint512, _n2: Uint512) -> CalculatorResult<Uint512> {
+    calculate_big_log(n1, Uint512::from(2u128))
+}
+
+/// The integer logarithm of n1 in base 10, rounded down
+pub fn calculate_big_log10(n1: Uint512, _n2: Uint512) -> CalculatorResult<Uint512> {
+    calculate_big_log(n1, Uint512::from(10u128))
+}
+
+/// The integer logarithm of n1 in base n2, rounded down
+pub fn calculate_big_log(n1: Uint512, n2: Uint512) -> CalculatorResult<Uint512> {
+    ensure!(
+        n2.u512() >= U512::from(2),
+        InvalidInput {
+            message: "The base of a logarithm must be at least 2",
+        }
+    );
+    ensure!(
+        !n1.is_zero(),
+        InvalidInput {
+            message: LOG_OF_ZERO,
+        }
+    );
+
+    let base = n2.u512();
+    let mut value = n1.u512();
+    let mut log = 0u128;
+    while value >= base {
+        value /= base;
+        log += 1;
+    }
+    Ok(Uint512::from(log))
+}
+
+pub fn calculate_big_gcd(n1: Uint512, n2: Uint512) -> CalculatorResult<Uint512> {
+    Uint512::new(big_gcd(n1.u512(), n2.u512()))
+}
+
+/// The least common multiple of n1 and n2, which is 0 if either of them is 0
+pub fn calculate_big_lcm(n1: Uint512, n2: Uint512) -> CalculatorResult<Uint512> {
+    if n1.is_zero() || n2.is_zero() {
+        return Ok(Uint512::zero());
+    }
+    (n1.u512() / big_gcd(n1.u512(), n2.u512()))
+        .checked_mul(n2.u512())
+        .context(Overflow {
+            message: "The least common multiple is too large",
+        })
+        .and_then(Uint512::new)
+}
+
+fn big_gcd(mut a: U512, mut b: U512) -> U512 {
+    while !b.is_zero() {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Calculates `a * b / denom`, rounding the quotient. The product is kept in 1024 bits, so this
+/// only overflows if the quotient itself is too large
+pub fn calculate_big_mul_div(
+    a: Uint512,
+    b: Uint512,
+    denom: Uint512,
+    rounding: RoundingMode,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Uint512> {
+    ensure!(
+        !denom.is_zero(),
+        DivideByZero {
+            message: DIVISION_BY_ZERO,
+        }
+    );
+    let denom = U1024::from(denom.u512());
+    let (quotient, remainder) = (U1024::from(a.u512()) * U1024::from(b.u512())).div_mod(denom);
+    let quotient = if rounding.rounds_up(quotient.bit(0), remainder, denom) {
+        quotient + 1
+    } else {
+        quotient
+    };
+    overflow_mode.resolve(
+        quotient
+            .to_u512()
+            .context(Overflow {
+                message: RESULT_TOO_LARGE,
+            })
+            .and_then(Uint512::new),
+        Uint512::max,
+        || Uint512::wrapping(quotient),
+    )
+}
+
+/// The decimal operations compute the exact result, and then round it to `decimal_places`
+/// fractional digits using `rounding`.
+pub fn calculate_decimal_add(
+    n1: Decimal,
+    n2: Decimal,
+    decimal_places: u32,
+    rounding: RoundingMode,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Decimal> {
+    let places = n1.decimal_places().max(n2.decimal_places());
+    let sum = n1.scaled_to(places) + n2.scaled_to(places);
+    Decimal::from_scaled_overflowing(sum, places, decimal_places, rounding, overflow_mode)
+}
+
+/// Subtracts n2 from n1. A negative result saturates to 0
+pub fn calculate_decimal_sub(
+    n1: Decimal,
+    n2: Decimal,
+    decimal_places: u32,
+    rounding: RoundingMode,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Decimal> {
+    validate_decimal_places(decimal_places)?;
+    let places = n1.decimal_places().max(n2.decimal_places());
+    let (a, b) = (n1.scaled_to(places), n2.scaled_to(places));
+    match a.checked_sub(b) {
+        Some(difference) => Decimal::from_scaled_overflowing(
+            difference,
+            places,
+            decimal_places,
+            rounding,
+            overflow_mode,
+        ),
+        None => overflow_mode.resolve(
+            Underflow {
+                message: NEGATIVE_RESULT,
+            }
+            .fail(),
+            || Decimal::new(0, decimal_places),
+            || Decimal::from_negative_scaled_wrapping(b - a, places, decimal_places, rounding),
+        ),
+    }
+}
+
+pub fn calculate_decimal_mul(
+    n1: Decimal,
+    n2: Decimal,
+    decimal_places: u32,
+    rounding: RoundingMode,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Decimal> {
+    let product = U256::from(n1.atomics()) * U256::from(n2.atomics());
+    let places = n1.decimal_places() + n2.decimal_places();
+    Decimal::from_scaled_overflowing(product, places, decimal_places, rounding, overflow_mode)
+}
+
+pub fn calculate_decimal_div(
+    n1: Decimal,
+    n2: Decimal,
+    decimal_places: u32,
+    rounding: RoundingMode,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Decimal> {
+    validate_decimal_places(decimal_places)?;
+    ensure!(
+        !n2.is_zero(),
+        DivideByZero {
+            message: DIVISION_BY_ZERO,
+        }
+    );
+
+    // n1 / n2 * 10^decimal_places, with both n1 and n2 brought to integers
+    let numerator =
+        U256::from(n1.atomics()) * U256::exp10((decimal_places + n2.decimal_places()) as usize);
+    let denominator = U256::from(n2.atomics()) * U256::exp10(n1.decimal_places() as usize);
+    let quotient = rounding.divide(numerator, denominator);
+    Decimal::from_scaled_overflowing(
+        quotient,
+        decimal_places,
+        decimal_places,
+        rounding,
+        overflow_mode,
+    )
+}
+
+/// The square root of n1. Roots of 128-bit atomics fit in 128 bits at any number of decimal
+/// places, so this can't overflow
+pub fn calculate_decimal_sqrt(
+    n1: Decimal,
+    _n2: Decimal,
+    decimal_places: u32,
+    rounding: RoundingMode,
+    _overflow_mode: OverflowMode,
+) -> CalculatorResult<Decimal> {
+    validate_decimal_places(decimal_places)?;
+
+    // sqrt(n1) * 10^decimal_places = sqrt(n1 * 10^(2 * decimal_places)), where
+    // n1 * 10^(2 * decimal_places) = numerator / denominator
+    let numerator = U256::from(n1.atomics()) * U256::exp10(2 * decimal_places as usize);
+    let denominator = U256::exp10(n1.decimal_places() as usize);
+    let root = scaled_sqrt(numerator.into(), denominator.into(), rounding);
+    let root = u128::try_from(root).ok().context(Overflow {
+        message: RESULT_TOO_LARGE,
+    })?;
+    Decimal::from_scaled(U256::from(root), decimal_places, decimal_places, rounding)
+}
+
+/// Returns `sqrt(numerator / denominator)`, rounded to an integer
+fn scaled_sqrt(numerator: U512, denominator: U512, rounding: RoundingMode) -> U512 {
+    let (square, remainder) = numerator.div_mod(denominator);
+    let root = square.integer_sqrt();
+
+    let rounds_up = match rounding {
+        RoundingMode::Floor => false,
+        RoundingMode::Ceil => !remainder.is_zero() || root * root != square,
+        RoundingMode::HalfEven => {
+            // The exact root is at least root + 1/2 exactly when
+            // 4 * numerator / denominator >= (2 * root + 1)^2
+            let (quadruple, quadruple_remainder) = (numerator * U512::from(4)).div_mod(denominator);
+            let half_up = (root * U512::from(2) + U512::one()).pow(U512::from(2));
+            quadruple > half_up
+                || (quadruple == half_up && (!quadruple_remainder.is_zero() || root.bit(0)))
+        }
+    };
+
+    if rounds_up {
+        root + 1
+    } else {
+        root
+    }
+}
+
+/// Converts `numerator / denominator` to a BigDecimal with `decimal_places` fractional digits.
+/// The numerators of statistics are below 2^272, so scaling them by 10^18 can't overflow
+fn rational_to_big_decimal(
+    numerator: U512,
+    denominator: u128,
+    decimal_places: u32,
+    rounding: RoundingMode,
+) -> BigDecimal {
+    let scaled = numerator * U512::exp10(decimal_places as usize);
+    let atomics = rounding.divide_wide(scaled, U512::from(denominator));
+    BigDecimal::new(atomics, decimal_places)
+}
+
+/// The most numbers Stats accepts, to keep the gas usage bounded
+pub const MAX_STATS_VALUES: usize = 256;
+
+/// Calculates summary statistics of a list of numbers, rounding the fractional ones. Only the sum
+/// can overflow, and the other statistics are calculated from the exact sum in every mode
+pub fn calculate_stats(
+    values: &[Uint128],
+    decimal_places: u32,
+    rounding: RoundingMode,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Statistics> {
+    validate_decimal_places(decimal_places)?;
+    ensure!(
+        !values.is_empty() && values.len() <= MAX_STATS_VALUES,
+        InvalidInput {
+            message: format!(
+                "Statistics are calculated over 1 to {} numbers",
+                MAX_STATS_VALUES
+            ),
+        }
+    );
+
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    let count = sorted.len() as u128;
+
+    let sum = sorted.iter().try_fold(Uint128::zero(), |sum, n| {
+        calculate_overflowing_add(sum, *n, overflow_mode)
+    })?;
+    let exact_sum = sorted
+        .iter()
+        .fold(U512::zero(), |total, n| total + U512::from(n.u128()));
+    let mean = rational_to_big_decimal(exact_sum, count, decimal_places, rounding);
+
+    let middle = sorted.len() / 2;
+    let median = if sorted.len() % 2 == 1 {
+        U512::from(sorted[middle].u128()) * U512::from(2)
+    } else {
+        U512::from(sorted[middle - 1].u128()) + U512::from(sorted[middle].u128())
+    };
+    let median = rational_to_big_decimal(median, 2, decimal_places, rounding);
+
+    // The first of the longest runs of equal values in the sorted list
+    let mut mode = (sorted[0], 0);
+    let mut run = (sorted[0], 0);
+    for n in sorted.iter() {
+        run = if *n == run.0 {
+            (run.0, run.1 + 1)
+        } else {
+            (*n, 1)
+        };
+        if run.1 > mode.1 {
+            mode = run;
+        }
+    }
+
+    // variance = (count * sum of squares - sum^2) / count^2
+    let sum_of_squares = sorted.iter().fold(U512::zero(), |total, n| {
+        total + U512::from(n.u128()) * U512::from(n.u128())
+    });
+    let deviations = U512::from(count) * sum_of_squares - exact_sum * exact_sum;
+    let variance = rational_to_big_decimal(deviations, count * count, decimal_places, rounding);
+    let std_dev = scaled_sqrt(
+        deviations * U512::exp10(2 * decimal_places as usize),
+        U512::from(count * count),
+        rounding,
+    );
+    let std_dev = BigDecimal::new(std_dev, decimal_places);
+
+    Ok(Statistics {
+        count: count as u32,
+        sum,
+        min: sorted[0],
+        max: sorted[sorted.len() - 1],
+        mean,
+        median,
+        mode: mode.0,
+        variance,
+        std_dev,
+    })
+}
+
+pub fn get_calculation_string<T: Display>(n1: T, n2: T, operation: &str, result: T) -> String {
     if operation == "√" {
         return operation.to_string() + &n1.to_string() + " = " + &result.to_string();
     }
+    if ["log2", "log10", "isprime", "factorize"].contains(&operation) {
+        return format!("{}({}) = {}", operation, n1, result);
+    }
+    if operation == "gcd" || operation == "lcm" {
+        return format!("{}({}, {}) = {}", operation, n1, n2, result);
+    }
+    if operation == "bps of" {
+        return format!("{} bps of {} = {}", n2, n1, result);
+    }
+    if operation == "bps fee" {
+        return format!("{} - {} bps fee = {}", n1, n2, result);
+    }
+    if operation == "root" {
+        return format!("root{}({}) = {}", n2, n1, result);
+    }
+    if operation == "log" {
+        return format!("log{}({}) = {}", n2, n1, result);
+    }
 
     n1.to_string() + " " + operation + " " + &n2.to_string() + " = " + &result.to_string()
 }
 
+pub fn get_expression_string<T: Display>(expression: &str, result: T) -> String {
+    expression.trim().to_string() + " = " + &result.to_string()
+}
+
+#[cfg(test)]
+use crate::decimal::MAX_DECIMAL_PLACES;
+#[cfg(test)]
+use std::str::FromStr;
+
 #[test]
 fn test_calculate_add() {
     let n1: u128 = 100;
@@ -138,6 +1224,724 @@ fn test_calculate_sqrt() {
     assert_eq!(Uint128::from(expected), actual.unwrap());
 }
 
+#[test]
+fn test_calculate_nth_root() {
+    let actual = calculate_nth_root_with_remainder(Uint128::from(27u128), 3);
+    assert_eq!((Uint128::from(3u128), Uint128::zero()), actual.unwrap());
+    let actual = calculate_nth_root_with_remainder(Uint128::from(30u128), 3);
+    assert_eq!(
+        (Uint128::from(3u128), Uint128::from(3u128)),
+        actual.unwrap()
+    );
+    let actual = calculate_nth_root_with_remainder(Uint128::from(u128::MAX), 2);
+    assert_eq!(
+        (
+            Uint128::from(u64::MAX as u128),
+            Uint128::from(2 * u64::MAX as u128)
+        ),
+        actual.unwrap()
+    );
+    let actual = calculate_nth_root_with_remainder(Uint128::from(u128::MAX), 200);
+    assert_eq!(
+        (Uint128::from(1u128), Uint128::from(u128::MAX - 1)),
+        actual.unwrap()
+    );
+    let actual = calculate_nth_root(Uint128::from(7u128), Uint128::from(1u128));
+    assert_eq!(Uint128::from(7u128), actual.unwrap());
+    let actual = calculate_nth_root_with_remainder(Uint128::from(u128::MAX), 1);
+    assert_eq!((Uint128::from(u128::MAX), Uint128::zero()), actual.unwrap());
+    let actual = calculate_nth_root_with_remainder(Uint128::from(u128::MAX), 3);
+    assert_eq!(Uint128::from(6981463658331u128), actual.unwrap().0);
+
+    let actual = calculate_nth_root_with_remainder(Uint128::from(u128::MAX), u32::MAX);
+    assert_eq!(
+        (Uint128::from(1u128), Uint128::from(u128::MAX - 1)),
+        actual.unwrap()
+    );
+    let actual = calculate_nth_root(Uint128::zero(), Uint128::from(u32::MAX as u128));
+    assert_eq!(Uint128::zero(), actual.unwrap());
+
+    assert!(calculate_nth_root_with_remainder(Uint128::from(8u128), 0).is_err());
+}
+
+#[test]
+fn test_calculate_gcd_lcm() {
+    let actual = calculate_gcd(Uint128::from(84u128), Uint128::from(36u128));
+    assert_eq!(Uint128::from(12u128), actual.unwrap());
+    let actual = calculate_lcm(Uint128::from(4u128), Uint128::from(6u128));
+    assert_eq!(Uint128::from(12u128), actual.unwrap());
+    let actual = calculate_lcm(Uint128::zero(), Uint128::from(6u128));
+    assert_eq!(Uint128::zero(), actual.unwrap());
+
+    assert!(calculate_lcm(Uint128::from(u128::MAX), Uint128::from(2u128)).is_err());
+}
+
+#[test]
+fn test_calculate_is_prime() {
+    let is_prime = |n: u128| calculate_is_prime(Uint128::from(n)).unwrap();
+    assert!(!is_prime(0));
+    assert!(!is_prime(1));
+    assert!(is_prime(2));
+    assert!(is_prime(97));
+    assert!(!is_prime(561));
+    assert!(!is_prime(3_215_031_751));
+    assert!(is_prime(2_305_843_009_213_693_951));
+    assert!(is_prime(18_446_744_073_709_551_557));
+    // The strong pseudoprime to the first 12 prime bases
+    assert!(!is_prime(318_665_857_834_031_151_167_461));
+
+    // Above the proven bound, compositeness is still proven, but primality isn't. The bound
+    // itself is a composite number passing the test with all 13 witnesses
+    assert!(calculate_is_prime(Uint128::from(MAX_PROVEN_PRIME)).is_err());
+    assert!(!is_prime(u128::MAX));
+    assert!(!is_prime(
+        18_446_744_073_709_551_557 * 18_446_744_073_709_551_533
+    ));
+    assert!(calculate_is_prime(Uint128::from(u128::MAX - 158)).is_err());
+}
+
+#[test]
+fn test_calculate_factorize() {
+    let factorize = |n: u128| {
+        calculate_factorize(Uint128::from(n)).map(|factors| {
+            factors
+                .into_iter()
+                .map(|factor| (factor.prime.u128(), factor.exponent))
+                .collect::<Vec<_>>()
+        })
+    };
+    assert_eq!(factorize(1).unwrap(), vec![]);
+    assert_eq!(factorize(360).unwrap(), vec![(2, 3), (3, 2), (5, 1)]);
+    assert_eq!(
+        factorize(18_446_744_073_709_551_557 * 1_000_003 * 1_000_003).unwrap(),
+        vec![(1_000_003, 2), (18_446_744_073_709_551_557, 1)]
+    );
+    assert_eq!(
+        factorize(1_000_003 * 1_000_033).unwrap(),
+        vec![(1_000_003, 1), (1_000_033, 1)]
+    );
+
+    assert!(factorize(0).is_err());
+    // Factors this large take more steps than a transaction can afford
+    assert_eq!(
+        factorize(18_446_744_073_709_551_557 * 18_446_744_073_709_551_533).unwrap_err(),
+        CalculatorError::InvalidInput {
+            message: TOO_HARD_TO_FACTORIZE.to_string()
+        }
+    );
+}
+
+#[test]
+fn test_calculate_stats() {
+    let values: Vec<Uint128> = [7u128, 2, 1, 3, 2].iter().map(|n| Uint128(*n)).collect();
+    let stats = calculate_stats(&values, 4, RoundingMode::HalfEven, OverflowMode::Checked).unwrap();
+    assert_eq!(stats.count, 5);
+    assert_eq!(stats.sum, Uint128(15));
+    assert_eq!((stats.min, stats.max), (Uint128(1), Uint128(7)));
+    assert_eq!(stats.mean.to_string(), "3");
+    assert_eq!(stats.median.to_string(), "2");
+    assert_eq!(stats.mode, Uint128(2));
+    assert_eq!(stats.variance.to_string(), "4.4");
+    assert_eq!(stats.std_dev.to_string(), "2.0976");
+
+    let values: Vec<Uint128> = [4u128, 3, 2, 1].iter().map(|n| Uint128(*n)).collect();
+    let stats = calculate_stats(&values, 2, RoundingMode::Floor, OverflowMode::Checked).unwrap();
+    assert_eq!(stats.median.to_string(), "2.5");
+    assert_eq!(stats.mode, Uint128(1));
+    assert_eq!(stats.variance.to_string(), "1.25");
+    assert_eq!(stats.std_dev.to_string(), "1.11");
+
+    let values = vec![Uint128(u128::MAX), Uint128(u128::MAX)];
+    let stats =
+        calculate_stats(&values[..1], 0, RoundingMode::Floor, OverflowMode::Checked).unwrap();
+    assert_eq!(stats.std_dev.to_string(), "0");
+    assert!(calculate_stats(&values, 0, RoundingMode::Floor, OverflowMode::Checked).is_err());
+
+    // Token amounts with 18 decimals have variances far beyond 128 bits
+    let stats = calculate_stats(
+        &[Uint128(0), Uint128(40_000_000_000)],
+        18,
+        RoundingMode::Floor,
+        OverflowMode::Checked,
+    )
+    .unwrap();
+    assert_eq!(stats.variance.to_string(), "400000000000000000000");
+    assert_eq!(stats.std_dev.to_string(), "20000000000");
+    let tokens = 1_000_000_000_000_000_000u128;
+    let values = vec![
+        Uint128(1_500 * tokens),
+        Uint128(2_000 * tokens),
+        Uint128(2_501 * tokens),
+    ];
+    let stats =
+        calculate_stats(&values, 18, RoundingMode::HalfEven, OverflowMode::Checked).unwrap();
+    assert_eq!(
+        stats.mean.to_string(),
+        "2000333333333333333333.333333333333333333"
+    );
+    assert_eq!(stats.median.to_string(), "2000000000000000000000");
+    assert_eq!(
+        stats.variance.to_string(),
+        "167000222222222222222222222222222222222222.222222222222222222"
+    );
+    assert_eq!(
+        stats.std_dev.to_string(),
+        "408656606727729561886.284430917799406344"
+    );
+    let values = vec![Uint128(u128::MAX), Uint128(0)];
+    let stats = calculate_stats(&values, 18, RoundingMode::Ceil, OverflowMode::Checked).unwrap();
+    assert_eq!(
+        stats.variance.to_string(),
+        "28948022309329048855892746252171976963147354982949671778132708698262398304256.25"
+    );
+    assert!(calculate_stats(&[], 0, RoundingMode::Floor, OverflowMode::Checked).is_err());
+
+    // Only the sum overflows, the mean is computed from the exact sum
+    let values = vec![Uint128(u128::MAX), Uint128(1)];
+    assert!(calculate_stats(&values, 0, RoundingMode::Floor, OverflowMode::Checked).is_err());
+    let stats = calculate_stats(&values, 0, RoundingMode::Floor, OverflowMode::Saturating).unwrap();
+    assert_eq!(stats.sum, Uint128(u128::MAX));
+    assert_eq!(stats.mean.to_string(), (1u128 << 127).to_string());
+    let stats = calculate_stats(&values, 0, RoundingMode::Floor, OverflowMode::Wrapping).unwrap();
+    assert_eq!(stats.sum, Uint128(0));
+}
+
+#[test]
+fn test_calculate_mul_div() {
+    let actual = calculate_mul_div(
+        Uint128(u128::MAX),
+        Uint128(6),
+        Uint128(4),
+        RoundingMode::Floor,
+        OverflowMode::Checked,
+    );
+    assert!(actual.is_err());
+    let actual = calculate_mul_div(
+        Uint128(u128::MAX / 2),
+        Uint128(6),
+        Uint128(4),
+        RoundingMode::Floor,
+        OverflowMode::Checked,
+    );
+    assert_eq!(Uint128(u128::MAX / 4 * 3 + 1), actual.unwrap());
+    let actual = calculate_mul_div(
+        Uint128(10),
+        Uint128(2),
+        Uint128(3),
+        RoundingMode::Ceil,
+        OverflowMode::Checked,
+    );
+    assert_eq!(Uint128(7), actual.unwrap());
+    let actual = calculate_mul_div(
+        Uint128(10),
+        Uint128(2),
+        Uint128(3),
+        RoundingMode::Floor,
+        OverflowMode::Checked,
+    );
+    assert_eq!(Uint128(6), actual.unwrap());
+
+    assert!(calculate_mul_div(
+        Uint128(1),
+        Uint128(1),
+        Uint128(0),
+        RoundingMode::Floor,
+        OverflowMode::Checked
+    )
+    .is_err());
+}
+
+#[test]
+fn test_calculate_percent_and_fee() {
+    let actual = calculate_percent(
+        Uint128(200),
+        Uint128(2500),
+        RoundingMode::Floor,
+        OverflowMode::Checked,
+    );
+    assert_eq!(Uint128(50), actual.unwrap());
+    let actual = calculate_percent(
+        Uint128(999),
+        Uint128(1),
+        RoundingMode::Ceil,
+        OverflowMode::Checked,
+    );
+    assert_eq!(Uint128(1), actual.unwrap());
+
+    let actual = calculate_apply_fee(Uint128(1000), Uint128(30), RoundingMode::Floor);
+    assert_eq!(Uint128(997), actual.unwrap());
+    let actual = calculate_apply_fee(Uint128(999), Uint128(30), RoundingMode::Ceil);
+    assert_eq!(Uint128(996), actual.unwrap());
+    assert!(calculate_apply_fee(Uint128(1000), Uint128(10_001), RoundingMode::Floor).is_err());
+}
+
+#[test]
+fn test_calculate_overflowing() {
+    let max = Uint128(u128::MAX);
+    let add = |mode| calculate_overflowing_add(max, Uint128(2), mode);
+    assert!(add(OverflowMode::Checked).is_err());
+    assert_eq!(max, add(OverflowMode::Saturating).unwrap());
+    assert_eq!(Uint128(1), add(OverflowMode::Wrapping).unwrap());
+
+    let sub = |mode| calculate_overflowing_sub(Uint128(3), Uint128(5), mode);
+    assert!(sub(OverflowMode::Checked).is_err());
+    assert_eq!(Uint128::zero(), sub(OverflowMode::Saturating).unwrap());
+    assert_eq!(Uint128(u128::MAX - 1), sub(OverflowMode::Wrapping).unwrap());
+
+    let mul = |mode| calculate_overflowing_mul(max, Uint128(3), mode);
+    assert!(mul(OverflowMode::Checked).is_err());
+    assert_eq!(max, mul(OverflowMode::Saturating).unwrap());
+    assert_eq!(Uint128(u128::MAX - 2), mul(OverflowMode::Wrapping).unwrap());
+
+    let pow = |n1, n2, mode| calculate_overflowing_pow(Uint128(n1), Uint128(n2), mode);
+    assert!(pow(2, 128, OverflowMode::Checked).is_err());
+    assert_eq!(max, pow(2, 128, OverflowMode::Saturating).unwrap());
+    assert_eq!(
+        Uint128::zero(),
+        pow(2, 128, OverflowMode::Wrapping).unwrap()
+    );
+    assert_eq!(
+        Uint128(1),
+        pow(3, 1 << 126, OverflowMode::Wrapping).unwrap()
+    );
+    assert_eq!(
+        Uint128(1),
+        pow(1, 1 << 40, OverflowMode::Saturating).unwrap()
+    );
+    assert_eq!(Uint128(1024), pow(2, 10, OverflowMode::Wrapping).unwrap());
+
+    let lcm = |mode| calculate_overflowing_lcm(max, Uint128(2), mode);
+    assert!(lcm(OverflowMode::Checked).is_err());
+    assert_eq!(max, lcm(OverflowMode::Saturating).unwrap());
+    assert_eq!(Uint128(u128::MAX - 1), lcm(OverflowMode::Wrapping).unwrap());
+
+    let mul_div = |mode| calculate_mul_div(max, Uint128(6), Uint128(4), RoundingMode::Floor, mode);
+    assert!(mul_div(OverflowMode::Checked).is_err());
+    assert_eq!(max, mul_div(OverflowMode::Saturating).unwrap());
+    assert_eq!(
+        Uint128(u128::MAX / 2 - 1),
+        mul_div(OverflowMode::Wrapping).unwrap()
+    );
+}
+
+#[test]
+fn test_calculate_overflowing_signed() {
+    let min = Int128(i128::MIN);
+    let max = Int128(i128::MAX);
+    let add = |mode| calculate_overflowing_signed_add(max, Int128(1), mode);
+    assert!(add(OverflowMode::Checked).is_err());
+    assert_eq!(max, add(OverflowMode::Saturating).unwrap());
+    assert_eq!(min, add(OverflowMode::Wrapping).unwrap());
+
+    let sub = |mode| calculate_overflowing_signed_sub(min, Int128(1), mode);
+    assert!(sub(OverflowMode::Checked).is_err());
+    assert_eq!(min, sub(OverflowMode::Saturating).unwrap());
+    assert_eq!(max, sub(OverflowMode::Wrapping).unwrap());
+
+    let mul = |mode| calculate_overflowing_signed_mul(min, Int128(-1), mode);
+    assert!(mul(OverflowMode::Checked).is_err());
+    assert_eq!(max, mul(OverflowMode::Saturating).unwrap());
+    assert_eq!(min, mul(OverflowMode::Wrapping).unwrap());
+
+    let div = |mode| calculate_overflowing_signed_div(min, Int128(-1), mode);
+    assert!(div(OverflowMode::Checked).is_err());
+    assert_eq!(max, div(OverflowMode::Saturating).unwrap());
+    assert_eq!(min, div(OverflowMode::Wrapping).unwrap());
+    assert!(calculate_overflowing_signed_div(max, Int128(0), OverflowMode::Wrapping).is_err());
+}
+
+#[test]
+fn test_calculate_pow() {
+    let actual = calculate_pow(Uint128::from(2u128), Uint128::from(10u128));
+    assert_eq!(Uint128::from(1024u128), actual.unwrap());
+    let actual = calculate_pow(Uint128::zero(), Uint128::zero());
+    assert_eq!(Uint128::from(1u128), actual.unwrap());
+
+    assert!(calculate_pow(Uint128::from(2u128), Uint128::from(128u128)).is_err());
+    assert!(calculate_pow(Uint128::from(3u128), Uint128::from(u128::MAX)).is_err());
+
+    let actual = calculate_pow(Uint128::from(1u128), Uint128::from(1u128 << 33));
+    assert_eq!(Uint128::from(1u128), actual.unwrap());
+    let actual = calculate_pow(Uint128::zero(), Uint128::from(u128::MAX));
+    assert_eq!(Uint128::zero(), actual.unwrap());
+    let actual = calculate_pow(Uint128::from(u128::MAX), Uint128::zero());
+    assert_eq!(Uint128::from(1u128), actual.unwrap());
+}
+
+#[test]
+fn test_calculate_mod() {
+    let actual = calculate_mod(Uint128::from(17u128), Uint128::from(5u128));
+    assert_eq!(Uint128::from(2u128), actual.unwrap());
+    assert!(calculate_mod(Uint128::from(17u128), Uint128::zero()).is_err());
+}
+
+#[test]
+fn test_calculate_log() {
+    let actual = calculate_log2(Uint128::from(1024u128), Uint128::zero());
+    assert_eq!(Uint128::from(10u128), actual.unwrap());
+    let actual = calculate_log10(Uint128::from(999u128), Uint128::zero());
+    assert_eq!(Uint128::from(2u128), actual.unwrap());
+    let actual = calculate_log(Uint128::from(80u128), Uint128::from(3u128));
+    assert_eq!(Uint128::from(3u128), actual.unwrap());
+
+    assert!(calculate_log2(Uint128::zero(), Uint128::zero()).is_err());
+    assert!(calculate_log10(Uint128::zero(), Uint128::zero()).is_err());
+    assert!(calculate_log(Uint128::from(8u128), Uint128::from(1u128)).is_err());
+}
+
+#[test]
+fn test_calculate_log_edges() {
+    let log2 = |n: u128| calculate_log2(Uint128(n), Uint128::zero()).unwrap().u128();
+    let log10 = |n: u128| calculate_log10(Uint128(n), Uint128::zero()).unwrap().u128();
+    let log = |n: u128, base: u128| calculate_log(Uint128(n), Uint128(base)).unwrap().u128();
+
+    assert_eq!(log2(1), 0);
+    assert_eq!(log2(u128::MAX), 127);
+    assert_eq!(log2(1 << 127), 127);
+    assert_eq!(log2((1 << 127) - 1), 126);
+    assert_eq!(log10(1), 0);
+    assert_eq!(log10(u128::MAX), 38);
+    assert_eq!(log10(10u128.pow(38)), 38);
+    assert_eq!(log10(10u128.pow(38) - 1), 37);
+    assert_eq!(log(u128::MAX, u128::MAX), 1);
+    assert_eq!(log(u128::MAX - 1, u128::MAX), 0);
+    assert_eq!(log(u128::MAX, 1 << 64), 1);
+    assert_eq!(log(1, u128::MAX), 0);
+}
+
+#[test]
+fn test_calculate_signed_sub() {
+    let actual = calculate_signed_sub(Int128::from(3i128), Int128::from(5i128));
+    assert_eq!(Int128::from(-2i128), actual.unwrap());
+}
+
+#[test]
+fn test_calculate_signed_div() {
+    let actual = calculate_signed_div(Int128::from(-7i128), Int128::from(2i128));
+    assert_eq!(Int128::from(-3i128), actual.unwrap());
+
+    assert!(calculate_signed_div(Int128::from(1i128), Int128::zero()).is_err());
+    assert!(calculate_signed_div(Int128::from(i128::MIN), Int128::from(-1i128)).is_err());
+}
+
+#[test]
+fn test_calculate_signed_overflow() {
+    assert!(calculate_signed_add(Int128::from(i128::MAX), Int128::from(1i128)).is_err());
+    assert!(calculate_signed_sub(Int128::from(i128::MIN), Int128::from(1i128)).is_err());
+    assert!(calculate_signed_mul(Int128::from(i128::MIN), Int128::from(-1i128)).is_err());
+}
+
+#[test]
+fn test_calculate_big() {
+    let amount = Uint512::from_str("1000000000000000000000000000000").unwrap();
+    let actual = calculate_big_mul(amount, amount).unwrap();
+    assert_eq!(actual.to_string(), format!("1{}", "0".repeat(60)));
+    let actual = calculate_big_sqrt(actual, Uint512::zero()).unwrap();
+    assert_eq!(actual, amount);
+    let actual = calculate_big_pow(Uint512::from(2u128), Uint512::from(200u128)).unwrap();
+    assert_eq!(
+        actual.to_string(),
+        "1606938044258990275541962092341162602522202993782792835301376"
+    );
+    let actual = calculate_big_mod(actual, Uint512::from(1000u128)).unwrap();
+    assert_eq!(actual, Uint512::from(376u128));
+
+    let largest = Uint512::from_str(&"9".repeat(MAX_UINT512_DIGITS)).unwrap();
+    assert!(calculate_big_add(largest, Uint512::from(1u128)).is_err());
+    assert!(calculate_big_mul(largest, largest).is_err());
+    assert!(calculate_big_sub(Uint512::zero(), Uint512::from(1u128)).is_err());
+    assert!(calculate_big_div(largest, Uint512::zero()).is_err());
+    assert!(calculate_big_pow(Uint512::from(2u128), Uint512::from(600u128)).is_err());
+}
+
+#[test]
+fn test_calculate_overflowing_big() {
+    let big = |s: &str| Uint512::from_str(s).unwrap();
+    let largest = Uint512::max();
+    let (saturating, wrapping) = (OverflowMode::Saturating, OverflowMode::Wrapping);
+    assert_eq!(largest.to_string(), "9".repeat(MAX_UINT512_DIGITS));
+
+    let two = Uint512::from(2u128);
+    assert_eq!(
+        calculate_overflowing_big_add(largest, two, saturating),
+        Ok(largest)
+    );
+    assert_eq!(
+        calculate_overflowing_big_add(largest, two, wrapping),
+        Ok(big("1"))
+    );
+    assert_eq!(
+        calculate_overflowing_big_sub(big("1"), big("3"), saturating),
+        Ok(Uint512::zero())
+    );
+    let actual = calculate_overflowing_big_sub(big("1"), big("3"), wrapping).unwrap();
+    assert_eq!(
+        actual.to_string(),
+        format!("{}8", "9".repeat(MAX_UINT512_DIGITS - 1))
+    );
+    assert_eq!(
+        calculate_overflowing_big_mul(largest, largest, saturating),
+        Ok(largest)
+    );
+    assert_eq!(
+        calculate_overflowing_big_mul(largest, largest, wrapping),
+        Ok(big("1"))
+    );
+    let actual = calculate_overflowing_big_pow(big("7"), big("200"), saturating);
+    assert_eq!(actual, Ok(largest));
+    let actual = calculate_overflowing_big_pow(big("7"), big("200"), wrapping).unwrap();
+    assert_eq!(
+        actual.to_string(),
+        "18899611816813659819188550170233659950140084035125767424262251774382614909364050293065248252546314174063180343683591188150754267339816534637456120001"
+    );
+    let second_largest = calculate_big_sub(largest, big("1")).unwrap();
+    let actual = calculate_overflowing_big_lcm(largest, second_largest, saturating);
+    assert_eq!(actual, Ok(largest));
+    let actual = calculate_overflowing_big_lcm(largest, second_largest, wrapping);
+    assert_eq!(actual, Ok(two));
+    let actual = calculate_big_mul_div(largest, largest, big("1"), RoundingMode::Floor, wrapping);
+    assert_eq!(actual, Ok(big("1")));
+
+    // Results that fit are the same in every mode
+    let actual = calculate_overflowing_big_add(big("2"), big("3"), wrapping);
+    assert_eq!(actual, Ok(big("5")));
+    assert!(calculate_overflowing_big_add(largest, two, OverflowMode::Checked).is_err());
+}
+
+#[test]
+fn test_calculate_big_roots_and_logs() {
+    let big = |s: &str| Uint512::from_str(s).unwrap();
+    let largest = big(&"9".repeat(MAX_UINT512_DIGITS));
+    let power = big(&format!("1{}", "0".repeat(60)));
+
+    assert_eq!(
+        calculate_big_nth_root(power, Uint512::from(3u128)).unwrap(),
+        big(&format!("1{}", "0".repeat(20)))
+    );
+    assert_eq!(
+        calculate_big_nth_root(big(&"9".repeat(60)), Uint512::from(3u128)).unwrap(),
+        big(&"9".repeat(20))
+    );
+    assert_eq!(
+        calculate_big_nth_root(largest, Uint512::from(1u128)).unwrap(),
+        largest
+    );
+    assert_eq!(
+        calculate_big_nth_root(largest, Uint512::from(150u128)).unwrap(),
+        Uint512::from(9u128)
+    );
+    assert_eq!(
+        calculate_big_nth_root(largest, Uint512::from(u32::MAX as u128)).unwrap(),
+        Uint512::from(1u128)
+    );
+    assert!(calculate_big_nth_root(largest, Uint512::zero()).is_err());
+    assert!(calculate_big_nth_root(largest, Uint512::from(1u128 << 32)).is_err());
+
+    assert_eq!(
+        calculate_big_log10(power, Uint512::zero()).unwrap(),
+        Uint512::from(60u128)
+    );
+    assert_eq!(
+        calculate_big_log10(largest, Uint512::zero()).unwrap(),
+        Uint512::from(149u128)
+    );
+    assert_eq!(
+        calculate_big_log2(largest, Uint512::zero()).unwrap(),
+        Uint512::from(498u128)
+    );
+    assert_eq!(
+        calculate_big_log(power, big("1000")).unwrap(),
+        Uint512::from(20u128)
+    );
+    assert_eq!(
+        calculate_big_log(Uint512::from(1u128), largest).unwrap(),
+        Uint512::zero()
+    );
+    assert!(calculate_big_log(power, Uint512::from(1u128)).is_err());
+    assert!(calculate_big_log2(Uint512::zero(), Uint512::zero()).is_err());
+}
+
+#[test]
+fn test_calculate_big_gcd_lcm_mul_div() {
+    let big = |s: &str| Uint512::from_str(s).unwrap();
+    let largest = big(&"9".repeat(MAX_UINT512_DIGITS));
+    let power = big(&format!("1{}", "0".repeat(60)));
+
+    assert_eq!(calculate_big_gcd(power, big("24")).unwrap(), big("8"));
+    assert_eq!(
+        calculate_big_gcd(largest, Uint512::zero()).unwrap(),
+        largest
+    );
+    assert_eq!(
+        calculate_big_lcm(power, big("24")).unwrap(),
+        big(&format!("3{}", "0".repeat(60)))
+    );
+    assert_eq!(
+        calculate_big_lcm(largest, Uint512::zero()).unwrap(),
+        Uint512::zero()
+    );
+    assert!(calculate_big_lcm(largest, big(&"9".repeat(MAX_UINT512_DIGITS - 1))).is_err());
+
+    // The product of the largest big integers only fits in the intermediate 1024 bits
+    let mul_div =
+        |a, b, denom, rounding| calculate_big_mul_div(a, b, denom, rounding, OverflowMode::Checked);
+    assert_eq!(
+        mul_div(largest, largest, largest, RoundingMode::Floor).unwrap(),
+        largest
+    );
+    assert_eq!(
+        mul_div(power, big("2"), big("3"), RoundingMode::Floor).unwrap(),
+        big(&format!("{}6", "6".repeat(59)))
+    );
+    assert_eq!(
+        mul_div(power, big("2"), big("3"), RoundingMode::HalfEven).unwrap(),
+        big(&format!("{}7", "6".repeat(59)))
+    );
+    assert_eq!(
+        mul_div(big("5"), big("1"), big("2"), RoundingMode::HalfEven).unwrap(),
+        big("2")
+    );
+    assert_eq!(
+        mul_div(big("5"), big("1"), big("2"), RoundingMode::Ceil).unwrap(),
+        big("3")
+    );
+    assert!(mul_div(largest, largest, big("2"), RoundingMode::Floor).is_err());
+    assert!(mul_div(largest, largest, Uint512::zero(), RoundingMode::Floor).is_err());
+}
+
+#[test]
+fn test_calculate_decimal_div() {
+    let n1 = Decimal::from_str("100").unwrap();
+    let n2 = Decimal::from_str("3").unwrap();
+    let actual = calculate_decimal_div(n1, n2, 2, RoundingMode::Floor, OverflowMode::Checked);
+    assert_eq!("33.33", actual.unwrap().to_string());
+    let actual = calculate_decimal_div(n1, n2, 2, RoundingMode::Ceil, OverflowMode::Checked);
+    assert_eq!("33.34", actual.unwrap().to_string());
+
+    let n2 = Decimal::from_str("200").unwrap();
+    let actual = calculate_decimal_div(n1, n2, 18, RoundingMode::Floor, OverflowMode::Checked);
+    assert_eq!("0.5", actual.unwrap().to_string());
+
+    let n1 = Decimal::from_str("0.125").unwrap();
+    let n2 = Decimal::from_str("0.5").unwrap();
+    let actual = calculate_decimal_div(n1, n2, 1, RoundingMode::HalfEven, OverflowMode::Checked);
+    assert_eq!("0.2", actual.unwrap().to_string());
+
+    assert!(calculate_decimal_div(
+        n1,
+        Decimal::zero(),
+        2,
+        RoundingMode::Floor,
+        OverflowMode::Checked
+    )
+    .is_err());
+    assert!(calculate_decimal_div(
+        n1,
+        n2,
+        MAX_DECIMAL_PLACES + 1,
+        RoundingMode::Floor,
+        OverflowMode::Checked
+    )
+    .is_err());
+}
+
+#[test]
+fn test_calculate_decimal_add_sub_mul() {
+    let n1 = Decimal::from_str("1.25").unwrap();
+    let n2 = Decimal::from_str("0.5").unwrap();
+
+    let actual = calculate_decimal_add(n1, n2, 18, RoundingMode::Floor, OverflowMode::Checked);
+    assert_eq!("1.75", actual.unwrap().to_string());
+    let actual = calculate_decimal_sub(n1, n2, 18, RoundingMode::Floor, OverflowMode::Checked);
+    assert_eq!("0.75", actual.unwrap().to_string());
+    assert!(calculate_decimal_sub(n2, n1, 18, RoundingMode::Floor, OverflowMode::Checked).is_err());
+    let actual = calculate_decimal_mul(n1, n2, 1, RoundingMode::HalfEven, OverflowMode::Checked);
+    assert_eq!("0.6", actual.unwrap().to_string());
+
+    // The intermediate product of two 18 decimal numbers doesn't fit in 128 bits
+    let n1 = Decimal::from_str("123456789.123456789123456789").unwrap();
+    let actual = calculate_decimal_mul(n1, n1, 18, RoundingMode::Floor, OverflowMode::Checked);
+    assert_eq!(
+        "15241578780673678.54610577828105472",
+        actual.unwrap().to_string()
+    );
+}
+
+#[test]
+fn test_calculate_decimal_overflowing() {
+    let n1 = Decimal::from_str("0.5").unwrap();
+    let n2 = Decimal::from_str("1.25").unwrap();
+    let (saturating, wrapping) = (OverflowMode::Saturating, OverflowMode::Wrapping);
+
+    let actual = calculate_decimal_sub(n1, n2, 2, RoundingMode::Floor, saturating);
+    assert_eq!("0", actual.unwrap().to_string());
+    // -0.75 wraps around to 2^128 - 75 atomics
+    let actual = calculate_decimal_sub(n1, n2, 2, RoundingMode::Floor, wrapping);
+    assert_eq!(
+        "3402823669209384634633746074317682113.81",
+        actual.unwrap().to_string()
+    );
+
+    let max = Decimal::from_str(&u128::MAX.to_string()).unwrap();
+    let two = Decimal::from_str("2").unwrap();
+    let actual = calculate_decimal_mul(max, two, 0, RoundingMode::Floor, saturating);
+    assert_eq!(u128::MAX.to_string(), actual.unwrap().to_string());
+    let actual = calculate_decimal_mul(max, two, 0, RoundingMode::Floor, wrapping);
+    assert_eq!((u128::MAX - 1).to_string(), actual.unwrap().to_string());
+    let actual = calculate_decimal_add(max, two, 0, RoundingMode::Floor, wrapping);
+    assert_eq!("1", actual.unwrap().to_string());
+    assert!(
+        calculate_decimal_add(max, two, 0, RoundingMode::Floor, OverflowMode::Checked).is_err()
+    );
+}
+
+#[test]
+fn test_calculate_decimal_sqrt() {
+    let n1 = Decimal::from_str("2").unwrap();
+    let actual = calculate_decimal_sqrt(
+        n1,
+        Decimal::zero(),
+        18,
+        RoundingMode::Floor,
+        OverflowMode::Checked,
+    );
+    assert_eq!("1.414213562373095048", actual.unwrap().to_string());
+    let actual = calculate_decimal_sqrt(
+        n1,
+        Decimal::zero(),
+        3,
+        RoundingMode::Ceil,
+        OverflowMode::Checked,
+    );
+    assert_eq!("1.415", actual.unwrap().to_string());
+
+    let n1 = Decimal::from_str("0.25").unwrap();
+    let actual = calculate_decimal_sqrt(
+        n1,
+        Decimal::zero(),
+        0,
+        RoundingMode::HalfEven,
+        OverflowMode::Checked,
+    );
+    assert_eq!("0", actual.unwrap().to_string());
+    let actual = calculate_decimal_sqrt(
+        n1,
+        Decimal::zero(),
+        0,
+        RoundingMode::Ceil,
+        OverflowMode::Checked,
+    );
+    assert_eq!("1", actual.unwrap().to_string());
+    let actual = calculate_decimal_sqrt(
+        n1,
+        Decimal::zero(),
+        1,
+        RoundingMode::Ceil,
+        OverflowMode::Checked,
+    );
+    assert_eq!("0.5", actual.unwrap().to_string());
+}
+
 #[test]
 fn test_get_calculation_string() {
     let operation = String::from("+");
@@ -153,6 +1957,25 @@ fn test_get_calculation_string() {
     assert_eq!("2 + 5 = 7", calculation_string);
 }
 
+#[test]
+fn test_get_calculation_string_signed() {
+    let calculation_string = get_calculation_string(
+        Int128::from(3i128),
+        Int128::from(-5i128),
+        "*",
+        Int128::from(-15i128),
+    );
+    assert_eq!("3 * -5 = -15", calculation_string);
+}
+
+#[test]
+fn test_get_expression_string() {
+    let expression = String::from(" (3 + 5) * 2 ");
+    let result: u128 = 16;
+    let calculation_string = get_expression_string(&expression, Uint128::from(result));
+    assert_eq!("(3 + 5) * 2 = 16", calculation_string);
+}
+
 #[test]
 fn test_get_calculation_string_sqrt() {
     let operation = String::from("√");
@@ -167,3 +1990,63 @@ fn test_get_calculation_string_sqrt() {
     );
     assert_eq!("√5 = 2", calculation_string);
 }
+
+#[test]
+fn test_get_calculation_string_functions() {
+    let string = |n1: u128, n2: u128, operation: &str, result: u128| {
+        get_calculation_string(
+            Uint128::from(n1),
+            Uint128::from(n2),
+            operation,
+            Uint128::from(result),
+        )
+    };
+    assert_eq!("2 ^ 10 = 1024", string(2, 10, "^", 1024));
+    assert_eq!("17 mod 5 = 2", string(17, 5, "mod", 2));
+    assert_eq!("log2(1024) = 10", string(1024, 1024, "log2", 10));
+    assert_eq!("log3(80) = 3", string(80, 3, "log", 3));
+    assert_eq!("root3(27) = 3", string(27, 3, "root", 3));
+    assert_eq!("isprime(97) = 1", string(97, 97, "isprime", 1));
+    assert_eq!("gcd(84, 36) = 12", string(84, 36, "gcd", 12));
+}
+
+#[test]
+fn test_calculator_errors() {
+    use crate::error::ErrorCode;
+
+    let code = |result: CalculatorResult<Uint128>| result.unwrap_err().error_code();
+    assert_eq!(
+        ErrorCode::Overflow,
+        code(calculate_add(Uint128(u128::MAX), Uint128(1)))
+    );
+    assert_eq!(
+        ErrorCode::Underflow,
+        code(calculate_sub(Uint128(1), Uint128(2)))
+    );
+    assert_eq!(
+        ErrorCode::DivideByZero,
+        code(calculate_div(Uint128(1), Uint128(0)))
+    );
+    assert_eq!(
+        ErrorCode::DivideByZero,
+        code(calculate_mod(Uint128(1), Uint128(0)))
+    );
+    assert_eq!(
+        ErrorCode::InvalidInput,
+        code(calculate_log(Uint128(8), Uint128(1)))
+    );
+
+    let err = calculate_mul(Uint128(u128::MAX), Uint128(2)).unwrap_err();
+    assert_eq!(ErrorCode::Overflow, err.error_code());
+    assert_eq!(
+        format!(
+            "Invalid input: The multiplication is too large. Cannot calculate results larger than {}",
+            u128::MAX
+        ),
+        err.to_string()
+    );
+
+    let signed_add = |n1, n2| calculate_signed_add(Int128(n1), Int128(n2)).unwrap_err();
+    assert_eq!(ErrorCode::Overflow, signed_add(i128::MAX, 1).error_code());
+    assert_eq!(ErrorCode::Underflow, signed_add(i128::MIN, -1).error_code());
+}
@@ -1,44 +1,672 @@
 use crate::calculations_utils::{
-    calculate_add, calculate_div, calculate_mul, calculate_sqrt, calculate_sub,
-    get_calculation_string, ArithmeticCalculation,
+    calculate_add, calculate_apply_fee, calculate_big_div, calculate_big_gcd, calculate_big_log,
+    calculate_big_log10, calculate_big_log2, calculate_big_mod, calculate_big_mul_div,
+    calculate_big_nth_root, calculate_big_sqrt, calculate_decimal_add, calculate_decimal_div,
+    calculate_decimal_mul, calculate_decimal_sqrt, calculate_decimal_sub, calculate_div,
+    calculate_factorize, calculate_gcd, calculate_is_prime, calculate_log, calculate_log10,
+    calculate_log2, calculate_mod, calculate_mul_div, calculate_nth_root,
+    calculate_nth_root_with_remainder, calculate_overflowing_add, calculate_overflowing_big_add,
+    calculate_overflowing_big_lcm, calculate_overflowing_big_mul, calculate_overflowing_big_pow,
+    calculate_overflowing_big_sub, calculate_overflowing_lcm, calculate_overflowing_mul,
+    calculate_overflowing_pow, calculate_overflowing_signed_add, calculate_overflowing_signed_div,
+    calculate_overflowing_signed_mul, calculate_overflowing_signed_sub, calculate_overflowing_sub,
+    calculate_percent, calculate_sqrt, calculate_stats, calculate_sub, ArithmeticCalculation,
+    DecimalCalculation, OverflowingCalculation,
 };
-use crate::msg::{GetHistory, HandleAnswer, HandleMsg, InitMsg, QueryMsg, ResponseStatus::Success};
-use crate::state::{get_transfers, load, save, save_calculation, State, CONFIG_KEY};
+use crate::decimal::{validate_decimal_places, Decimal, RoundingMode, MAX_DECIMAL_PLACES};
+use crate::error::{CalculatorResult, ErrorCode, InvalidInput};
+use crate::expression::evaluate_expression;
+use crate::int128::Int128;
+use crate::msg::{
+    BatchMode, Callback, Compute, ContractStatus, ContractStatusLevel, GetCalculationRecords,
+    GetHistory, GetRegisters, HandleAnswer, HandleMsg, HistoryCursor, InitMsg, MigrateMsg,
+    Operation, OperationResult, QueryAnswer, QueryMsg, QueryWithPermit, ReceiverHandleMsg,
+    ResponseStatus::{Failure, Success},
+};
+use crate::operand::Operand;
+use crate::overflow::OverflowMode;
+use crate::state::{
+    clear_calculations, delete_calculation, get_calculation, get_history_counts, get_last_result,
+    get_transfers, load, load_registers, load_user_settings, save, save_calculation,
+    save_registers, save_user_settings, set_history_retention, CalculationOp, CalculationRecord,
+    Factor, LatestBlock, LegacyState, Number, Register, State, Statistics, CONFIG_KEY,
+    CONTRACT_VERSION, LATEST_BLOCK_KEY, MAX_REGISTERS, MAX_REGISTER_NAME_LENGTH,
+    PREFIX_REVOKED_PERMITS,
+};
+use crate::uint512::Uint512;
 
 use cosmwasm_std::{
-    to_binary, Api, Env, Extern, HandleResponse, HumanAddr, InitResponse, Querier, QueryResult,
-    StdError, StdResult, Storage, Uint128,
+    to_binary, Api, CanonicalAddr, Env, Extern, HandleResponse, HumanAddr, InitResponse,
+    MigrateResponse, MigrateResult, Querier, QueryResult, ReadonlyStorage, StdError, StdResult,
+    Storage, Uint128,
 };
 
-use crate::viewing_key::ViewingKey;
+use crate::viewing_key::{
+    ScopedViewingKey, ViewingKey, ViewingKeyInfo, ViewingKeyScope, MAX_SCOPED_VIEWING_KEYS,
+    MAX_VIEWING_KEY_LABEL_LENGTH,
+};
 use secret_toolkit::crypto::sha_256;
+use secret_toolkit::permit::{validate, Permission, Permit, RevokedPermits};
+use secret_toolkit::utils::HandleCallback;
+use snafu::OptionExt;
+use std::convert::TryFrom;
+
+/// A number type the calculator operates on, along with the answer its results are returned in
+trait CalculationValue: Copy + Into<Number> {
+    fn calculation_answer(
+        n: Option<Self>,
+        status: String,
+        error_code: Option<ErrorCode>,
+    ) -> HandleAnswer;
+}
+
+impl CalculationValue for Uint128 {
+    fn calculation_answer(
+        n: Option<Self>,
+        status: String,
+        error_code: Option<ErrorCode>,
+    ) -> HandleAnswer {
+        HandleAnswer::CalculationResult {
+            n,
+            status,
+            error_code,
+        }
+    }
+}
+
+impl CalculationValue for Int128 {
+    fn calculation_answer(
+        n: Option<Self>,
+        status: String,
+        error_code: Option<ErrorCode>,
+    ) -> HandleAnswer {
+        HandleAnswer::SignedCalculationResult {
+            n,
+            status,
+            error_code,
+        }
+    }
+}
+
+impl CalculationValue for Decimal {
+    fn calculation_answer(
+        n: Option<Self>,
+        status: String,
+        error_code: Option<ErrorCode>,
+    ) -> HandleAnswer {
+        HandleAnswer::DecimalCalculationResult {
+            n,
+            status,
+            error_code,
+        }
+    }
+}
+
+impl CalculationValue for Uint512 {
+    fn calculation_answer(
+        n: Option<Self>,
+        status: String,
+        error_code: Option<ErrorCode>,
+    ) -> HandleAnswer {
+        HandleAnswer::BigCalculationResult {
+            n,
+            status,
+            error_code,
+        }
+    }
+}
 
 pub fn init<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
-    _env: Env,
+    env: Env,
     msg: InitMsg,
 ) -> StdResult<InitResponse> {
+    let decimal_places = msg.decimal_places.unwrap_or(MAX_DECIMAL_PLACES);
+    validate_decimal_places(decimal_places)?;
+    let admin = msg.admin.unwrap_or(env.message.sender);
+    deps.api.canonical_address(&admin)?;
+
     let config = State {
         prng_seed: sha_256(base64::encode(msg.prng_seed).as_bytes()).to_vec(),
+        decimal_places,
+        contract_address: env.contract.address,
+        admin,
+        contract_status: ContractStatusLevel::Normal,
+        version: CONTRACT_VERSION,
     };
 
     save(&mut deps.storage, CONFIG_KEY, &config)?;
     Ok(InitResponse::default())
 }
 
+/// Upgrades the data of an earlier version of the contract. The histories of users are migrated
+/// lazily, a few entries each time they record a calculation
+pub fn migrate<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    msg: MigrateMsg,
+) -> MigrateResult {
+    let mut config = match load::<State, _>(&deps.storage, CONFIG_KEY) {
+        Ok(config) => config,
+        Err(_) => {
+            let legacy: LegacyState = load(&deps.storage, CONFIG_KEY)?;
+            let decimal_places = msg.decimal_places.unwrap_or(MAX_DECIMAL_PLACES);
+            validate_decimal_places(decimal_places)?;
+            let admin = msg.admin.unwrap_or(env.message.sender);
+            deps.api.canonical_address(&admin)?;
+            State {
+                prng_seed: legacy.prng_seed,
+                decimal_places,
+                contract_address: env.contract.address,
+                admin,
+                contract_status: ContractStatusLevel::Normal,
+                version: 1,
+            }
+        }
+    };
+    if config.version > CONTRACT_VERSION {
+        return Err(StdError::generic_err(format!(
+            "Cannot migrate from version {} to the older version {}",
+            config.version, CONTRACT_VERSION
+        )));
+    }
+
+    config.version = CONTRACT_VERSION;
+    save(&mut deps.storage, CONFIG_KEY, &config)?;
+    Ok(MigrateResponse::default())
+}
+
 pub fn handle<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
     msg: HandleMsg,
 ) -> StdResult<HandleResponse> {
+    let config: State = load(&deps.storage, CONFIG_KEY)?;
+    let is_calculation = !matches!(
+        msg,
+        HandleMsg::CreateViewingKey { .. }
+            | HandleMsg::SetViewingKey { .. }
+            | HandleMsg::RevokeViewingKey { .. }
+            | HandleMsg::SetRecordFailures { .. }
+            | HandleMsg::ClearHistory { .. }
+            | HandleMsg::DeleteHistoryEntry { .. }
+            | HandleMsg::SetRetention { .. }
+            | HandleMsg::MemoryClear { .. }
+            | HandleMsg::RevokePermit { .. }
+            | HandleMsg::ChangeAdmin { .. }
+            | HandleMsg::SetContractStatus { .. }
+    );
+    let allowed = match config.contract_status {
+        ContractStatusLevel::Normal => true,
+        ContractStatusLevel::StopCalculations => !is_calculation,
+        ContractStatusLevel::StopAll => matches!(
+            msg,
+            HandleMsg::ChangeAdmin { .. } | HandleMsg::SetContractStatus { .. }
+        ),
+    };
+    if !allowed {
+        return Err(StdError::generic_err(
+            "The contract is stopped, and this action is not allowed",
+        ));
+    }
+
+    // Queries check the expiry of viewing keys against the latest block. Only the messages that
+    // change keys and settings save it, so that calculations don't pay for another write
+    if !is_calculation {
+        let latest_block = LatestBlock {
+            height: env.block.height,
+            time: env.block.time,
+        };
+        save(&mut deps.storage, LATEST_BLOCK_KEY, &latest_block)?;
+    }
+
     match msg {
-        HandleMsg::Add((n1, n2)) => add(deps, env, n1, n2),
-        HandleMsg::Sub((n1, n2)) => sub(deps, env, n1, n2),
-        HandleMsg::Mul((n1, n2)) => mul(deps, env, n1, n2),
-        HandleMsg::Div((n1, n2)) => div(deps, env, n1, n2),
-        HandleMsg::Sqrt(n) => sqrt(deps, env, n),
-        HandleMsg::CreateViewingKey { entropy, .. } => create_viewing_key(deps, env, entropy),
-        HandleMsg::SetViewingKey { key, .. } => try_set_key(deps, env, key),
+        HandleMsg::Add {
+            operands,
+            overflow_mode,
+        } => operation(
+            deps,
+            env,
+            Operation::Add {
+                operands,
+                overflow_mode,
+            },
+        ),
+        HandleMsg::Sub {
+            operands,
+            overflow_mode,
+        } => operation(
+            deps,
+            env,
+            Operation::Sub {
+                operands,
+                overflow_mode,
+            },
+        ),
+        HandleMsg::Mul {
+            operands,
+            overflow_mode,
+        } => operation(
+            deps,
+            env,
+            Operation::Mul {
+                operands,
+                overflow_mode,
+            },
+        ),
+        HandleMsg::Div(operands) => operation(deps, env, Operation::Div(operands)),
+        HandleMsg::Sqrt(n) => operation(deps, env, Operation::Sqrt(n)),
+        HandleMsg::NthRoot { value, n } => nth_root(deps, env, value, n),
+        HandleMsg::Pow {
+            operands,
+            overflow_mode,
+        } => operation(
+            deps,
+            env,
+            Operation::Pow {
+                operands,
+                overflow_mode,
+            },
+        ),
+        HandleMsg::Mod(operands) => operation(deps, env, Operation::Mod(operands)),
+        HandleMsg::Log2(n) => operation(deps, env, Operation::Log2(n)),
+        HandleMsg::Log10(n) => operation(deps, env, Operation::Log10(n)),
+        HandleMsg::Log { value, base } => operation(deps, env, Operation::Log { value, base }),
+        HandleMsg::Gcd(operands) => operation(deps, env, Operation::Gcd(operands)),
+        HandleMsg::Lcm {
+            operands,
+            overflow_mode,
+        } => operation(
+            deps,
+            env,
+            Operation::Lcm {
+                operands,
+                overflow_mode,
+            },
+        ),
+        HandleMsg::Percent {
+            value,
+            bps,
+            rounding,
+            overflow_mode,
+        } => operation(
+            deps,
+            env,
+            Operation::Percent {
+                value,
+                bps,
+                rounding,
+                overflow_mode,
+            },
+        ),
+        HandleMsg::ApplyFee {
+            amount,
+            fee_bps,
+            rounding,
+        } => operation(
+            deps,
+            env,
+            Operation::ApplyFee {
+                amount,
+                fee_bps,
+                rounding,
+            },
+        ),
+        HandleMsg::MulDiv {
+            a,
+            b,
+            denom,
+            rounding,
+            overflow_mode,
+        } => operation(
+            deps,
+            env,
+            Operation::MulDiv {
+                a,
+                b,
+                denom,
+                rounding,
+                overflow_mode,
+            },
+        ),
+        HandleMsg::IsPrime(n) => is_prime(deps, env, n),
+        HandleMsg::Factorize(n) => factorize(deps, env, n),
+        HandleMsg::Stats {
+            values,
+            decimal_places,
+            rounding,
+            overflow_mode,
+        } => stats(deps, env, values, decimal_places, rounding, overflow_mode),
+        HandleMsg::SignedAdd {
+            operands: (n1, n2),
+            overflow_mode,
+        } => calculate(
+            deps,
+            env,
+            n1,
+            n2,
+            CalculationOp::Add,
+            overflow_mode,
+            |n1, n2| calculate_overflowing_signed_add(n1, n2, overflow_mode.unwrap_or_default()),
+        ),
+        HandleMsg::SignedSub {
+            operands: (n1, n2),
+            overflow_mode,
+        } => calculate(
+            deps,
+            env,
+            n1,
+            n2,
+            CalculationOp::Sub,
+            overflow_mode,
+            |n1, n2| calculate_overflowing_signed_sub(n1, n2, overflow_mode.unwrap_or_default()),
+        ),
+        HandleMsg::SignedMul {
+            operands: (n1, n2),
+            overflow_mode,
+        } => calculate(
+            deps,
+            env,
+            n1,
+            n2,
+            CalculationOp::Mul,
+            overflow_mode,
+            |n1, n2| calculate_overflowing_signed_mul(n1, n2, overflow_mode.unwrap_or_default()),
+        ),
+        HandleMsg::SignedDiv {
+            operands: (n1, n2),
+            overflow_mode,
+        } => calculate(
+            deps,
+            env,
+            n1,
+            n2,
+            CalculationOp::Div,
+            overflow_mode,
+            |n1, n2| calculate_overflowing_signed_div(n1, n2, overflow_mode.unwrap_or_default()),
+        ),
+        HandleMsg::BigAdd {
+            operands: (n1, n2),
+            overflow_mode,
+        } => calculate(
+            deps,
+            env,
+            n1,
+            n2,
+            CalculationOp::Add,
+            overflow_mode,
+            |n1, n2| calculate_overflowing_big_add(n1, n2, overflow_mode.unwrap_or_default()),
+        ),
+        HandleMsg::BigSub {
+            operands: (n1, n2),
+            overflow_mode,
+        } => calculate(
+            deps,
+            env,
+            n1,
+            n2,
+            CalculationOp::Sub,
+            overflow_mode,
+            |n1, n2| calculate_overflowing_big_sub(n1, n2, overflow_mode.unwrap_or_default()),
+        ),
+        HandleMsg::BigMul {
+            operands: (n1, n2),
+            overflow_mode,
+        } => calculate(
+            deps,
+            env,
+            n1,
+            n2,
+            CalculationOp::Mul,
+            overflow_mode,
+            |n1, n2| calculate_overflowing_big_mul(n1, n2, overflow_mode.unwrap_or_default()),
+        ),
+        HandleMsg::BigDiv((n1, n2)) => calculate(
+            deps,
+            env,
+            n1,
+            n2,
+            CalculationOp::Div,
+            None,
+            calculate_big_div,
+        ),
+        HandleMsg::BigSqrt(n) => calculate(
+            deps,
+            env,
+            n,
+            Uint512::zero(),
+            CalculationOp::Sqrt,
+            None,
+            calculate_big_sqrt,
+        ),
+        HandleMsg::BigPow {
+            operands: (n1, n2),
+            overflow_mode,
+        } => calculate(
+            deps,
+            env,
+            n1,
+            n2,
+            CalculationOp::Pow,
+            overflow_mode,
+            |n1, n2| calculate_overflowing_big_pow(n1, n2, overflow_mode.unwrap_or_default()),
+        ),
+        HandleMsg::BigMod((n1, n2)) => calculate(
+            deps,
+            env,
+            n1,
+            n2,
+            CalculationOp::Mod,
+            None,
+            calculate_big_mod,
+        ),
+        HandleMsg::BigNthRoot { value, n } => calculate(
+            deps,
+            env,
+            value,
+            Uint512::from(n as u128),
+            CalculationOp::NthRoot,
+            None,
+            calculate_big_nth_root,
+        ),
+        HandleMsg::BigLog2(n) => calculate(
+            deps,
+            env,
+            n,
+            Uint512::zero(),
+            CalculationOp::Log2,
+            None,
+            calculate_big_log2,
+        ),
+        HandleMsg::BigLog10(n) => calculate(
+            deps,
+            env,
+            n,
+            Uint512::zero(),
+            CalculationOp::Log10,
+            None,
+            calculate_big_log10,
+        ),
+        HandleMsg::BigLog { value, base } => calculate(
+            deps,
+            env,
+            value,
+            base,
+            CalculationOp::Log,
+            None,
+            calculate_big_log,
+        ),
+        HandleMsg::BigGcd((n1, n2)) => calculate(
+            deps,
+            env,
+            n1,
+            n2,
+            CalculationOp::Gcd,
+            None,
+            calculate_big_gcd,
+        ),
+        HandleMsg::BigLcm {
+            operands: (n1, n2),
+            overflow_mode,
+        } => calculate(
+            deps,
+            env,
+            n1,
+            n2,
+            CalculationOp::Lcm,
+            overflow_mode,
+            |n1, n2| calculate_overflowing_big_lcm(n1, n2, overflow_mode.unwrap_or_default()),
+        ),
+        HandleMsg::BigMulDiv {
+            a,
+            b,
+            denom,
+            rounding,
+            overflow_mode,
+        } => {
+            let outcome = calculate_big_mul_div(
+                a,
+                b,
+                denom,
+                rounding.unwrap_or(RoundingMode::Floor),
+                overflow_mode.unwrap_or_default(),
+            );
+            record_calculation(
+                deps,
+                env,
+                CalculationOp::MulDiv,
+                vec![a, b, denom],
+                overflow_mode,
+                outcome,
+            )
+        }
+        HandleMsg::DecimalAdd {
+            operands: (n1, n2),
+            decimal_places,
+            rounding,
+            overflow_mode,
+        } => decimal_calculation(
+            deps,
+            env,
+            n1,
+            n2,
+            CalculationOp::Add,
+            decimal_places,
+            rounding,
+            overflow_mode,
+            calculate_decimal_add,
+        ),
+        HandleMsg::DecimalSub {
+            operands: (n1, n2),
+            decimal_places,
+            rounding,
+            overflow_mode,
+        } => decimal_calculation(
+            deps,
+            env,
+            n1,
+            n2,
+            CalculationOp::Sub,
+            decimal_places,
+            rounding,
+            overflow_mode,
+            calculate_decimal_sub,
+        ),
+        HandleMsg::DecimalMul {
+            operands: (n1, n2),
+            decimal_places,
+            rounding,
+            overflow_mode,
+        } => decimal_calculation(
+            deps,
+            env,
+            n1,
+            n2,
+            CalculationOp::Mul,
+            decimal_places,
+            rounding,
+            overflow_mode,
+            calculate_decimal_mul,
+        ),
+        HandleMsg::DecimalDiv {
+            operands: (n1, n2),
+            decimal_places,
+            rounding,
+            overflow_mode,
+        } => decimal_calculation(
+            deps,
+            env,
+            n1,
+            n2,
+            CalculationOp::Div,
+            decimal_places,
+            rounding,
+            overflow_mode,
+            calculate_decimal_div,
+        ),
+        HandleMsg::DecimalSqrt {
+            value,
+            decimal_places,
+            rounding,
+        } => decimal_calculation(
+            deps,
+            env,
+            value,
+            Decimal::zero(),
+            CalculationOp::Sqrt,
+            decimal_places,
+            rounding,
+            None,
+            calculate_decimal_sqrt,
+        ),
+        HandleMsg::Batch { ops, mode } => {
+            batch(deps, env, ops, mode.unwrap_or(BatchMode::AllOrNothing))
+        }
+        HandleMsg::Evaluate {
+            expression,
+            overflow_mode,
+        } => evaluate(deps, env, expression, overflow_mode),
+        HandleMsg::Calculate { op, callback } => calculate_with_callback(deps, env, op, callback),
+        HandleMsg::CreateViewingKey {
+            entropy,
+            label,
+            expires_at_height,
+            expires_at_time,
+            scopes,
+            ..
+        } => {
+            let info = viewing_key_info(&env, label, expires_at_height, expires_at_time)?;
+            create_viewing_key(deps, env, entropy, info, scopes)
+        }
+        HandleMsg::SetViewingKey {
+            key,
+            label,
+            expires_at_height,
+            expires_at_time,
+            scopes,
+            ..
+        } => {
+            let info = viewing_key_info(&env, label, expires_at_height, expires_at_time)?;
+            try_set_key(deps, env, key, info, scopes)
+        }
+        HandleMsg::RevokeViewingKey { label, .. } => revoke_viewing_key(deps, env, label),
+        HandleMsg::SetRecordFailures { enabled } => set_record_failures(deps, env, enabled),
+        HandleMsg::ClearHistory {} => clear_history(deps, env),
+        HandleMsg::DeleteHistoryEntry { index } => delete_history_entry(deps, env, index),
+        HandleMsg::SetRetention { max_entries } => set_retention(deps, env, max_entries),
+        HandleMsg::MemoryStore { register, value } => {
+            update_register(deps, env, register, value, |_, value| Ok(value))
+        }
+        HandleMsg::MemoryAdd { register, value } => {
+            update_register(deps, env, register, value, calculate_add)
+        }
+        HandleMsg::MemorySub { register, value } => {
+            update_register(deps, env, register, value, calculate_sub)
+        }
+        HandleMsg::MemoryClear { register } => clear_register(deps, env, register),
+        HandleMsg::RevokePermit { permit_name, .. } => revoke_permit(deps, env, permit_name),
+        HandleMsg::ChangeAdmin { address, .. } => change_admin(deps, env, config, address),
+        HandleMsg::SetContractStatus { level, .. } => set_contract_status(deps, env, config, level),
     }
 }
 
@@ -46,15 +674,17 @@ pub fn create_viewing_key<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
     entropy: String,
+    info: ViewingKeyInfo,
+    scopes: Option<Vec<ViewingKeyScope>>,
 ) -> StdResult<HandleResponse> {
     let config: State = load(&deps.storage, CONFIG_KEY)?;
     let prng_seed = config.prng_seed;
 
-    let key = ViewingKey::new(&env, &prng_seed, (&entropy).as_ref());
+    let key = ViewingKey::new(&env, &prng_seed, entropy.as_ref());
 
     let message_sender = deps.api.canonical_address(&env.message.sender)?;
 
-    ViewingKey::write_viewing_key(&mut deps.storage, &message_sender, &key);
+    store_viewing_key(&mut deps.storage, &message_sender, &key, info, scopes)?;
 
     Ok(HandleResponse {
         messages: vec![],
@@ -67,11 +697,13 @@ pub fn try_set_key<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
     key: String,
+    info: ViewingKeyInfo,
+    scopes: Option<Vec<ViewingKeyScope>>,
 ) -> StdResult<HandleResponse> {
     let vk = ViewingKey(key);
 
     let message_sender = deps.api.canonical_address(&env.message.sender)?;
-    ViewingKey::write_viewing_key(&mut deps.storage, &message_sender, &vk);
+    store_viewing_key(&mut deps.storage, &message_sender, &vk, info, scopes)?;
 
     Ok(HandleResponse {
         messages: vec![],
@@ -80,241 +712,2661 @@ pub fn try_set_key<S: Storage, A: Api, Q: Querier>(
     })
 }
 
-fn insert_result<S: Storage, A: Api, Q: Querier>(
-    calculation_string: String,
-    deps: &mut Extern<S, A, Q>,
-    env: Env,
-    insertion_status: &mut String,
-) -> Result<(), StdError> {
-    let sender_address = env.message.sender;
-    let sender_canonical_address = deps.api.canonical_address(&sender_address)?;
+/// Stores the main viewing key of `owner`, or a scoped key under the label of `info` if it is
+/// given scopes
+fn store_viewing_key<S: Storage>(
+    storage: &mut S,
+    owner: &CanonicalAddr,
+    key: &ViewingKey,
+    info: ViewingKeyInfo,
+    scopes: Option<Vec<ViewingKeyScope>>,
+) -> StdResult<()> {
+    let scopes = match scopes {
+        Some(scopes) => scopes,
+        None => return ViewingKey::write_viewing_key(storage, owner, key, &info),
+    };
+    if scopes.is_empty() {
+        return Err(StdError::generic_err(
+            "Invalid input: A scoped viewing key needs at least one scope",
+        ));
+    }
+    if info.label.is_none() {
+        return Err(StdError::generic_err(
+            "Invalid input: A scoped viewing key needs a label",
+        ));
+    }
 
-    save_calculation(
-        &mut deps.storage,
-        sender_canonical_address.as_slice(),
-        &calculation_string,
-    )?;
-    insertion_status.push_str("Calculation performed and recorded!");
-    Ok(())
+    let mut scoped_keys = ViewingKey::read_scoped_viewing_keys(storage, owner)?;
+    scoped_keys.retain(|scoped_key| scoped_key.info.label != info.label);
+    if scoped_keys.len() >= MAX_SCOPED_VIEWING_KEYS {
+        return Err(StdError::generic_err(format!(
+            "Invalid input: An address can have at most {} scoped viewing keys",
+            MAX_SCOPED_VIEWING_KEYS
+        )));
+    }
+    scoped_keys.push(ScopedViewingKey {
+        hashed_key: key.to_hashed().to_vec(),
+        scopes,
+        info,
+    });
+    ViewingKey::write_scoped_viewing_keys(storage, owner, &scoped_keys)
 }
 
-fn calculate<S: Storage, A: Api, Q: Querier>(
+pub fn revoke_viewing_key<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    n1: Uint128,
-    n2: Uint128,
-    operation: String,
-    calculate: ArithmeticCalculation,
+    label: Option<String>,
 ) -> StdResult<HandleResponse> {
-    let mut result: Option<Uint128> = None;
-    let mut status = String::new();
-
-    match calculate(n1, n2) {
-        Ok(res) => {
-            result = Some(res);
-            let calculation_string = get_calculation_string(n1, n2, &operation, res);
-            insert_result(calculation_string, deps, env, &mut status)?;
-        }
-        Err(err) => {
-            status = err.to_string();
+    let message_sender = deps.api.canonical_address(&env.message.sender)?;
+    match label {
+        Some(label) => {
+            let mut scoped_keys =
+                ViewingKey::read_scoped_viewing_keys(&deps.storage, &message_sender)?;
+            let count = scoped_keys.len();
+            scoped_keys.retain(|scoped_key| scoped_key.info.label.as_ref() != Some(&label));
+            if scoped_keys.len() == count {
+                return Err(StdError::generic_err(format!(
+                    "Invalid input: There is no scoped viewing key labeled {}",
+                    label
+                )));
+            }
+            ViewingKey::write_scoped_viewing_keys(
+                &mut deps.storage,
+                &message_sender,
+                &scoped_keys,
+            )?;
         }
-    };
+        None => ViewingKey::remove_viewing_key(&mut deps.storage, &message_sender),
+    }
 
-    // Return a HandleResponse with the appropriate status message included in the data field
     Ok(HandleResponse {
         messages: vec![],
         log: vec![],
-        data: Some(to_binary(&HandleAnswer::CalculationResult {
-            n: result,
-            status,
+        data: Some(to_binary(&HandleAnswer::RevokeViewingKey {
+            status: Success,
         })?),
     })
 }
 
-fn add<S: Storage, A: Api, Q: Querier>(
+/// Validates the metadata of a viewing key set in the current block
+fn viewing_key_info(
+    env: &Env,
+    label: Option<String>,
+    expires_at_height: Option<u64>,
+    expires_at_time: Option<u64>,
+) -> StdResult<ViewingKeyInfo> {
+    if let Some(label) = &label {
+        if label.chars().count() > MAX_VIEWING_KEY_LABEL_LENGTH {
+            return Err(StdError::generic_err(format!(
+                "Invalid input: Viewing key labels are limited to {} characters",
+                MAX_VIEWING_KEY_LABEL_LENGTH
+            )));
+        }
+    }
+
+    let info = ViewingKeyInfo {
+        label,
+        created_at_height: env.block.height,
+        created_at_time: env.block.time,
+        expires_at_height,
+        expires_at_time,
+    };
+    if info.is_expired(env.block.height, env.block.time) {
+        return Err(StdError::generic_err(
+            "Invalid input: The viewing key would already be expired",
+        ));
+    }
+    Ok(info)
+}
+
+pub fn set_record_failures<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    n1: Uint128,
-    n2: Uint128,
+    enabled: bool,
 ) -> StdResult<HandleResponse> {
-    calculate(deps, env, n1, n2, String::from("+"), calculate_add)
+    let message_sender = deps.api.canonical_address(&env.message.sender)?;
+    let mut settings = load_user_settings(&deps.storage, message_sender.as_slice())?;
+    settings.record_failures = enabled;
+    save_user_settings(&mut deps.storage, message_sender.as_slice(), &settings)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::SetRecordFailures {
+            status: Success,
+        })?),
+    })
 }
 
-fn sub<S: Storage, A: Api, Q: Querier>(
+pub fn clear_history<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    n1: Uint128,
-    n2: Uint128,
 ) -> StdResult<HandleResponse> {
-    calculate(deps, env, n1, n2, String::from("-"), calculate_sub)
+    let message_sender = deps.api.canonical_address(&env.message.sender)?;
+    clear_calculations(&mut deps.storage, message_sender.as_slice())?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::ClearHistory { status: Success })?),
+    })
 }
 
-fn mul<S: Storage, A: Api, Q: Querier>(
+pub fn delete_history_entry<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    n1: Uint128,
-    n2: Uint128,
+    index: u32,
 ) -> StdResult<HandleResponse> {
-    calculate(deps, env, n1, n2, String::from("*"), calculate_mul)
+    let message_sender = deps.api.canonical_address(&env.message.sender)?;
+    if !delete_calculation(&mut deps.storage, message_sender.as_slice(), index)? {
+        return Err(StdError::generic_err(format!(
+            "Invalid input: There is no history entry {}",
+            index
+        )));
+    }
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::DeleteHistoryEntry {
+            status: Success,
+        })?),
+    })
 }
 
-fn div<S: Storage, A: Api, Q: Querier>(
+pub fn set_retention<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    n1: Uint128,
-    n2: Uint128,
+    max_entries: Option<u32>,
 ) -> StdResult<HandleResponse> {
-    calculate(deps, env, n1, n2, String::from("/"), calculate_div)
+    if max_entries == Some(0) {
+        return Err(StdError::generic_err(
+            "Invalid input: The history must retain at least one entry",
+        ));
+    }
+    let message_sender = deps.api.canonical_address(&env.message.sender)?;
+    set_history_retention(&mut deps.storage, message_sender.as_slice(), max_entries)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::SetRetention { status: Success })?),
+    })
 }
 
-fn sqrt<S: Storage, A: Api, Q: Querier>(
+pub fn revoke_permit<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    n: Uint128,
+    permit_name: String,
 ) -> StdResult<HandleResponse> {
-    calculate(
-        deps,
-        env,
-        n,
-        Uint128::zero(),
-        String::from("√"),
-        calculate_sqrt,
-    )
-}
+    RevokedPermits::revoke_permit(
+        &mut deps.storage,
+        PREFIX_REVOKED_PERMITS,
+        &env.message.sender,
+        &permit_name,
+    );
 
-pub fn query<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, msg: QueryMsg) -> QueryResult {
-    match msg {
-        QueryMsg::GetHistory { .. } => viewing_keys_queries(deps, msg),
-    }
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::RevokePermit { status: Success })?),
+    })
 }
 
-pub fn viewing_keys_queries<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
-    msg: QueryMsg,
-) -> QueryResult {
-    msg.authenticate(deps)?;
-
-    match msg {
-        QueryMsg::GetHistory {
-            address,
-            page,
-            page_size,
-            ..
-        } => to_binary(&may_get_history(
-            deps,
-            &address,
-            page.unwrap_or(0),
-            page_size,
-        )?),
+fn check_if_admin(config: &State, sender: &HumanAddr) -> StdResult<()> {
+    if &config.admin != sender {
+        return Err(StdError::generic_err(
+            "This is an admin command. Admin commands can only be run from admin address",
+        ));
     }
+    Ok(())
 }
 
-pub fn may_get_history<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
-    account: &HumanAddr,
-    page: u32,
-    page_size: u32,
-) -> StdResult<GetHistory> {
-    let address = deps.api.canonical_address(account)?;
-    let (history, status) = get_transfers(&deps.storage, &address, page, page_size)?;
+fn change_admin<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    mut config: State,
+    address: HumanAddr,
+) -> StdResult<HandleResponse> {
+    check_if_admin(&config, &env.message.sender)?;
 
-    let result = GetHistory { status, history };
-    Ok(result)
+    // An invalid address would lock every admin out of the contract
+    deps.api.canonical_address(&address)?;
+    config.admin = address;
+    save(&mut deps.storage, CONFIG_KEY, &config)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::ChangeAdmin { status: Success })?),
+    })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, MockApi, MockQuerier, MockStorage};
-    use cosmwasm_std::{coins, from_binary};
+fn set_contract_status<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    mut config: State,
+    level: ContractStatusLevel,
+) -> StdResult<HandleResponse> {
+    check_if_admin(&config, &env.message.sender)?;
 
-    fn init_helper() -> (
-        StdResult<InitResponse>,
-        Extern<MockStorage, MockApi, MockQuerier>,
-    ) {
-        let mut deps = mock_dependencies(20, &[]);
-        let env = mock_env("instantiator", &coins(1000, "token"));
+    config.contract_status = level;
+    save(&mut deps.storage, CONFIG_KEY, &config)?;
 
-        let init_msg = InitMsg {
-            prng_seed: String::from("waehfjklasd"),
-        };
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::SetContractStatus {
+            status: Success,
+        })?),
+    })
+}
 
-        (init(&mut deps, env, init_msg), deps)
+fn validate_register_name(name: &str) -> CalculatorResult<()> {
+    if name.is_empty() || name.chars().count() > MAX_REGISTER_NAME_LENGTH {
+        return InvalidInput {
+            message: format!(
+                "Register names must have 1 to {} characters",
+                MAX_REGISTER_NAME_LENGTH
+            ),
+        }
+        .fail();
     }
+    Ok(())
+}
 
-    fn create_viewing_key(
-        deps: &mut Extern<cosmwasm_std::MemoryStorage, MockApi, MockQuerier>,
-    ) -> ViewingKey {
-        let msg = HandleMsg::CreateViewingKey {
-            entropy: String::from("wefhjyr"),
-            padding: None,
-        };
-        let handle_result = handle(deps, mock_env("bob", &[]), msg);
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
-        );
+/// Sets one of the sender's memory registers to `update(content, value)`, where the content of
+/// an empty register is 0
+fn update_register<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    name: String,
+    value: Operand,
+    update: impl FnOnce(Uint128, Uint128) -> CalculatorResult<Uint128>,
+) -> StdResult<HandleResponse> {
+    let message_sender = deps.api.canonical_address(&env.message.sender)?;
+    let mut registers = load_registers(&deps.storage, message_sender.as_slice())?;
 
-        // Get the viewing key of the reply to HandleMsg::CreateViewingKey
-        let answer: HandleAnswer = from_binary(&handle_result.unwrap().data.unwrap()).unwrap();
-        let key = match answer {
-            HandleAnswer::CreateViewingKey { key } => key,
-            _ => panic!("NOPE"),
-        };
-        key
-    }
+    let value = resolve_operand(&deps.storage, &message_sender, value, None)?;
+    let outcome = validate_register_name(&name).and(value).and_then(|value| {
+        match registers.iter().position(|register| register.name == name) {
+            Some(index) => {
+                let register = &mut registers[index];
+                register.value = update(register.value, value)?;
+                Ok(register.value)
+            }
+            None if registers.len() >= MAX_REGISTERS => InvalidInput {
+                message: format!("Users are limited to {} registers", MAX_REGISTERS),
+            }
+            .fail(),
+            None => {
+                let value = update(Uint128::zero(), value)?;
+                registers.push(Register { name, value });
+                Ok(value)
+            }
+        }
+    });
 
-    fn query_history_wrong_vk(deps: Extern<cosmwasm_std::MemoryStorage, MockApi, MockQuerier>) {
+    let (n, status, error_code) = match outcome {
+        Ok(n) => {
+            save_registers(&mut deps.storage, message_sender.as_slice(), &registers)?;
+            (Some(n), "Register updated".to_string(), None)
+        }
+        Err(err) => (None, err.to_string(), Some(err.error_code())),
+    };
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::MemoryResult {
+            n,
+            status,
+            error_code,
+        })?),
+    })
+}
+
+pub fn clear_register<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    name: String,
+) -> StdResult<HandleResponse> {
+    let message_sender = deps.api.canonical_address(&env.message.sender)?;
+    let mut registers = load_registers(&deps.storage, message_sender.as_slice())?;
+    registers.retain(|register| register.name != name);
+    save_registers(&mut deps.storage, message_sender.as_slice(), &registers)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::MemoryClear { status: Success })?),
+    })
+}
+
+/// Records a successful calculation. The result is omitted when the operation holds it, like the
+/// factors of a factorization
+fn insert_result<S: Storage, A: Api, Q: Querier>(
+    op: CalculationOp,
+    operands: Vec<Number>,
+    overflow_mode: Option<OverflowMode>,
+    result: Option<Number>,
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    insertion_status: &mut String,
+) -> Result<(), StdError> {
+    let sender_address = env.message.sender;
+    let sender_canonical_address = deps.api.canonical_address(&sender_address)?;
+
+    let record = CalculationRecord {
+        op,
+        operands,
+        result,
+        block_height: env.block.height,
+        block_time: env.block.time,
+        status: Success,
+        error: None,
+        overflow_mode,
+        display: None,
+    };
+    save_calculation(
+        &mut deps.storage,
+        sender_canonical_address.as_slice(),
+        &record,
+    )?;
+    insertion_status.push_str("Calculation performed and recorded!");
+    Ok(())
+}
+
+/// Records a failed calculation, if the sender asked for failures to be recorded
+fn insert_failure<S: Storage, A: Api, Q: Querier>(
+    op: CalculationOp,
+    operands: Vec<Number>,
+    overflow_mode: Option<OverflowMode>,
+    error: String,
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> Result<(), StdError> {
+    let sender_address = env.message.sender;
+    let sender_canonical_address = deps.api.canonical_address(&sender_address)?;
+
+    let settings = load_user_settings(&deps.storage, sender_canonical_address.as_slice())?;
+    if !settings.record_failures {
+        return Ok(());
+    }
+
+    let record = CalculationRecord {
+        op,
+        operands,
+        result: None,
+        block_height: env.block.height,
+        block_time: env.block.time,
+        status: Failure,
+        error: Some(error),
+        overflow_mode,
+        display: None,
+    };
+    save_calculation(
+        &mut deps.storage,
+        sender_canonical_address.as_slice(),
+        &record,
+    )
+}
+
+fn calculate<S: Storage, A: Api, Q: Querier, T: CalculationValue>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    n1: T,
+    n2: T,
+    op: CalculationOp,
+    overflow_mode: Option<OverflowMode>,
+    calculate: impl FnOnce(T, T) -> CalculatorResult<T>,
+) -> StdResult<HandleResponse> {
+    let operands = match op {
+        CalculationOp::Sqrt | CalculationOp::Log2 | CalculationOp::Log10 => vec![n1],
+        _ => vec![n1, n2],
+    };
+    let outcome = calculate(n1, n2);
+    record_calculation(deps, env, op, operands, overflow_mode, outcome)
+}
+
+/// Records the outcome of a calculation on the operands in the sender's history, and returns it
+fn record_calculation<S: Storage, A: Api, Q: Querier, T: CalculationValue>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    op: CalculationOp,
+    operands: Vec<T>,
+    overflow_mode: Option<OverflowMode>,
+    outcome: CalculatorResult<T>,
+) -> StdResult<HandleResponse> {
+    let mut result: Option<T> = None;
+    let mut status = String::new();
+    let mut error_code = None;
+
+    let operands = operands.into_iter().map(Into::into).collect();
+
+    match outcome {
+        Ok(res) => {
+            result = Some(res);
+            insert_result(
+                op,
+                operands,
+                overflow_mode,
+                Some(res.into()),
+                deps,
+                env,
+                &mut status,
+            )?;
+        }
+        Err(err) => {
+            status = err.to_string();
+            error_code = Some(err.error_code());
+            insert_failure(op, operands, overflow_mode, status.clone(), deps, env)?;
+        }
+    };
+
+    calculation_response(result, status, error_code)
+}
+
+fn calculation_response<T: CalculationValue>(
+    result: Option<T>,
+    status: String,
+    error_code: Option<ErrorCode>,
+) -> StdResult<HandleResponse> {
+    // Return a HandleResponse with the appropriate status message included in the data field
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&T::calculation_answer(
+            result, status, error_code,
+        ))?),
+    })
+}
+
+/// Performs a decimal calculation, rounded to `decimal_places` or the contract's default number of
+/// decimal places. Results are rounded down unless another rounding mode is requested.
+#[allow(clippy::too_many_arguments)]
+fn decimal_calculation<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    n1: Decimal,
+    n2: Decimal,
+    op: CalculationOp,
+    decimal_places: Option<u32>,
+    rounding: Option<RoundingMode>,
+    overflow_mode: Option<OverflowMode>,
+    calculate_decimal: DecimalCalculation,
+) -> StdResult<HandleResponse> {
+    let config: State = load(&deps.storage, CONFIG_KEY)?;
+    let decimal_places = decimal_places.unwrap_or(config.decimal_places);
+    let rounding = rounding.unwrap_or(RoundingMode::Floor);
+
+    calculate(deps, env, n1, n2, op, overflow_mode, |n1, n2| {
+        calculate_decimal(
+            n1,
+            n2,
+            decimal_places,
+            rounding,
+            overflow_mode.unwrap_or_default(),
+        )
+    })
+}
+
+/// Resolves an operand to a number, looking up references to previous results in the history of
+/// `owner` and to registers in their memory. If given, `previous` is used as the latest result
+/// instead of the one in the history
+fn resolve_operand<S: ReadonlyStorage>(
+    storage: &S,
+    owner: &CanonicalAddr,
+    operand: Operand,
+    previous: Option<Uint128>,
+) -> StdResult<CalculatorResult<Uint128>> {
+    let number = match operand {
+        Operand::Value(n) => return Ok(Ok(n)),
+        Operand::Previous => match previous {
+            Some(n) => return Ok(Ok(n)),
+            None => get_last_result(storage, owner)?.context(InvalidInput {
+                message: "There is no previous result",
+            }),
+        },
+        Operand::HistoryIndex(index) => {
+            let record = match u32::try_from(index) {
+                Ok(index) => get_calculation(storage, owner, index)?,
+                Err(_) => None,
+            };
+            record
+                .and_then(|record| record.result)
+                .context(InvalidInput {
+                    message: format!("History entry {} has no result", index),
+                })
+        }
+        Operand::Register(name) => {
+            return Ok(load_registers(storage, owner.as_slice())?
+                .into_iter()
+                .find(|register| register.name == name)
+                .map(|register| register.value)
+                .context(InvalidInput {
+                    message: format!("Register '{}' is empty", name),
+                }))
+        }
+    };
+    Ok(number.and_then(|number| number.to_uint128()))
+}
+
+/// Runs an unsigned operation without recording it. Returns the operation, its operands, the
+/// overflow mode the sender chose and the outcome. An operand that can't be resolved fails the
+/// operation with an `InvalidInput` error, and leaves it without operands
+#[allow(clippy::type_complexity)]
+fn run_operation(
+    operation: Operation,
+    mut resolve: impl FnMut(Operand) -> StdResult<CalculatorResult<Uint128>>,
+) -> StdResult<(
+    CalculationOp,
+    Vec<Number>,
+    Option<OverflowMode>,
+    CalculatorResult<Uint128>,
+)> {
+    let (op, operands, overflow_mode, calculate): (_, _, _, OperandsCalculation) = match operation {
+        Operation::Add {
+            operands: (n1, n2),
+            overflow_mode: mode,
+        } => (
+            CalculationOp::Add,
+            vec![n1, n2],
+            mode,
+            overflowing(calculate_overflowing_add, mode),
+        ),
+        Operation::Sub {
+            operands: (n1, n2),
+            overflow_mode: mode,
+        } => (
+            CalculationOp::Sub,
+            vec![n1, n2],
+            mode,
+            overflowing(calculate_overflowing_sub, mode),
+        ),
+        Operation::Mul {
+            operands: (n1, n2),
+            overflow_mode: mode,
+        } => (
+            CalculationOp::Mul,
+            vec![n1, n2],
+            mode,
+            overflowing(calculate_overflowing_mul, mode),
+        ),
+        Operation::Div((n1, n2)) => (
+            CalculationOp::Div,
+            vec![n1, n2],
+            None,
+            on_operands(calculate_div),
+        ),
+        Operation::Sqrt(n) => (
+            CalculationOp::Sqrt,
+            vec![n],
+            None,
+            on_operands(calculate_sqrt),
+        ),
+        Operation::NthRoot { value, n } => (
+            CalculationOp::NthRoot,
+            vec![value, Uint128::from(n as u128).into()],
+            None,
+            on_operands(calculate_nth_root),
+        ),
+        Operation::Pow {
+            operands: (n1, n2),
+            overflow_mode: mode,
+        } => (
+            CalculationOp::Pow,
+            vec![n1, n2],
+            mode,
+            overflowing(calculate_overflowing_pow, mode),
+        ),
+        Operation::Mod((n1, n2)) => (
+            CalculationOp::Mod,
+            vec![n1, n2],
+            None,
+            on_operands(calculate_mod),
+        ),
+        Operation::Log2(n) => (
+            CalculationOp::Log2,
+            vec![n],
+            None,
+            on_operands(calculate_log2),
+        ),
+        Operation::Log10(n) => (
+            CalculationOp::Log10,
+            vec![n],
+            None,
+            on_operands(calculate_log10),
+        ),
+        Operation::Log { value, base } => (
+            CalculationOp::Log,
+            vec![value, base.into()],
+            None,
+            on_operands(calculate_log),
+        ),
+        Operation::Gcd((n1, n2)) => (
+            CalculationOp::Gcd,
+            vec![n1, n2],
+            None,
+            on_operands(calculate_gcd),
+        ),
+        Operation::Lcm {
+            operands: (n1, n2),
+            overflow_mode: mode,
+        } => (
+            CalculationOp::Lcm,
+            vec![n1, n2],
+            mode,
+            overflowing(calculate_overflowing_lcm, mode),
+        ),
+        Operation::Percent {
+            value,
+            bps,
+            rounding,
+            overflow_mode,
+        } => (
+            CalculationOp::Percent,
+            vec![value, Uint128::from(bps as u128).into()],
+            overflow_mode,
+            Box::new(move |operands| {
+                calculate_percent(
+                    operands[0],
+                    operands[1],
+                    rounding.unwrap_or(RoundingMode::Floor),
+                    overflow_mode.unwrap_or_default(),
+                )
+            }),
+        ),
+        Operation::ApplyFee {
+            amount,
+            fee_bps,
+            rounding,
+        } => (
+            CalculationOp::ApplyFee,
+            vec![amount, Uint128::from(fee_bps as u128).into()],
+            None,
+            Box::new(move |operands| {
+                calculate_apply_fee(
+                    operands[0],
+                    operands[1],
+                    rounding.unwrap_or(RoundingMode::Floor),
+                )
+            }),
+        ),
+        Operation::MulDiv {
+            a,
+            b,
+            denom,
+            rounding,
+            overflow_mode,
+        } => (
+            CalculationOp::MulDiv,
+            vec![a, b, denom],
+            overflow_mode,
+            Box::new(move |operands| {
+                calculate_mul_div(
+                    operands[0],
+                    operands[1],
+                    operands[2],
+                    rounding.unwrap_or(RoundingMode::Floor),
+                    overflow_mode.unwrap_or_default(),
+                )
+            }),
+        ),
+    };
+
+    let mut resolved = Vec::with_capacity(operands.len());
+    for operand in operands {
+        match resolve(operand)? {
+            Ok(n) => resolved.push(n),
+            Err(err) => return Ok((op, vec![], overflow_mode, Err(err))),
+        }
+    }
+    let operands = resolved;
+    let result = calculate(&operands);
+    let operands = operands.into_iter().map(Number::from).collect();
+
+    Ok((op, operands, overflow_mode, result))
+}
+
+/// A calculation on the resolved operands of an operation
+type OperandsCalculation = Box<dyn FnOnce(&[Uint128]) -> CalculatorResult<Uint128>>;
+
+/// Adapts a calculation on one or two numbers to the operands of an operation
+fn on_operands(calculate: ArithmeticCalculation) -> OperandsCalculation {
+    Box::new(move |operands| calculate(operands[0], operands.get(1).copied().unwrap_or_default()))
+}
+
+/// Adapts a calculation on two numbers that may overflow to the operands of an operation.
+/// Overflows fail unless another mode is given
+fn overflowing(
+    calculate: OverflowingCalculation,
+    overflow_mode: Option<OverflowMode>,
+) -> OperandsCalculation {
+    let overflow_mode = overflow_mode.unwrap_or_default();
+    Box::new(move |operands| calculate(operands[0], operands[1], overflow_mode))
+}
+
+fn operation<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    operation: Operation,
+) -> StdResult<HandleResponse> {
+    let (result, status, error_code) = record_operation(deps, env, operation)?;
+    calculation_response(result, status, error_code)
+}
+
+/// Performs an operation like any other, and sends its outcome to the callback contract, if any
+fn calculate_with_callback<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    operation: Operation,
+    callback: Option<Callback>,
+) -> StdResult<HandleResponse> {
+    let sender = env.message.sender.clone();
+    let (result, status, error_code) = record_operation(deps, env, operation)?;
+
+    let mut response = calculation_response(result, status.clone(), error_code)?;
+    if let Some(callback) = callback {
+        let msg = ReceiverHandleMsg::ReceiveCalculation {
+            sender,
+            n: result,
+            status,
+            error_code,
+            msg: callback.msg,
+        };
+        response.messages.push(msg.to_cosmos_msg(
+            callback.code_hash,
+            callback.contract_addr,
+            None,
+        )?);
+    }
+    Ok(response)
+}
+
+/// Performs an operation and records it in the sender's history. Returns the result, if the
+/// operation succeeded, the status and the error code, if it failed
+fn record_operation<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    operation: Operation,
+) -> StdResult<(Option<Uint128>, String, Option<ErrorCode>)> {
+    let sender = deps.api.canonical_address(&env.message.sender)?;
+    let outcome = run_operation(operation, |operand| {
+        resolve_operand(&deps.storage, &sender, operand, None)
+    })?;
+
+    let mut result: Option<Uint128> = None;
+    let mut status = String::new();
+    let mut error_code = None;
+
+    match outcome {
+        (op, operands, overflow_mode, Ok(res)) => {
+            result = Some(res);
+            insert_result(
+                op,
+                operands,
+                overflow_mode,
+                Some(res.into()),
+                deps,
+                env,
+                &mut status,
+            )?;
+        }
+        (op, operands, overflow_mode, Err(err)) => {
+            status = err.to_string();
+            error_code = Some(err.error_code());
+            insert_failure(op, operands, overflow_mode, status.clone(), deps, env)?;
+        }
+    };
+
+    Ok((result, status, error_code))
+}
+
+/// Calculates an nth root like any other operation, and also returns its remainder
+fn nth_root<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    value: Operand,
+    n: u32,
+) -> StdResult<HandleResponse> {
+    let sender = deps.api.canonical_address(&env.message.sender)?;
+    let mut root: Option<(Uint128, Uint128)> = None;
+    let mut status = String::new();
+    let mut error_code = None;
+
+    match resolve_operand(&deps.storage, &sender, value, None)? {
+        Ok(value) => {
+            let operands = vec![value.into(), Uint128::from(n as u128).into()];
+            match calculate_nth_root_with_remainder(value, n) {
+                Ok((res, remainder)) => {
+                    root = Some((res, remainder));
+                    insert_result(
+                        CalculationOp::NthRoot,
+                        operands,
+                        None,
+                        Some(res.into()),
+                        deps,
+                        env,
+                        &mut status,
+                    )?;
+                }
+                Err(err) => {
+                    status = err.to_string();
+                    error_code = Some(err.error_code());
+                    insert_failure(
+                        CalculationOp::NthRoot,
+                        operands,
+                        None,
+                        status.clone(),
+                        deps,
+                        env,
+                    )?;
+                }
+            }
+        }
+        Err(err) => {
+            status = err.to_string();
+            error_code = Some(err.error_code());
+            let op = CalculationOp::NthRoot;
+            insert_failure(op, vec![], None, status.clone(), deps, env)?;
+        }
+    };
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::NthRootResult {
+            n: root.map(|(root, _)| root),
+            exact: root.map(|(_, remainder)| remainder.is_zero()),
+            remainder: root.map(|(_, remainder)| remainder),
+            status,
+            error_code,
+        })?),
+    })
+}
+
+fn is_prime<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    value: Operand,
+) -> StdResult<HandleResponse> {
+    let sender = deps.api.canonical_address(&env.message.sender)?;
+    let mut is_prime: Option<bool> = None;
+    let mut status = String::new();
+    let mut error_code = None;
+
+    match resolve_operand(&deps.storage, &sender, value, None)? {
+        Ok(value) => match calculate_is_prime(value) {
+            Ok(res) => {
+                is_prime = Some(res);
+                insert_result(
+                    CalculationOp::IsPrime,
+                    vec![value.into()],
+                    None,
+                    Some(res.into()),
+                    deps,
+                    env,
+                    &mut status,
+                )?;
+            }
+            Err(err) => {
+                status = err.to_string();
+                error_code = Some(err.error_code());
+                let op = CalculationOp::IsPrime;
+                insert_failure(op, vec![value.into()], None, status.clone(), deps, env)?;
+            }
+        },
+        Err(err) => {
+            status = err.to_string();
+            error_code = Some(err.error_code());
+            let op = CalculationOp::IsPrime;
+            insert_failure(op, vec![], None, status.clone(), deps, env)?;
+        }
+    };
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::IsPrimeResult {
+            is_prime,
+            status,
+            error_code,
+        })?),
+    })
+}
+
+fn factorize<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    value: Operand,
+) -> StdResult<HandleResponse> {
+    let sender = deps.api.canonical_address(&env.message.sender)?;
+    let mut factors: Option<Vec<Factor>> = None;
+    let mut status = String::new();
+    let mut error_code = None;
+
+    match resolve_operand(&deps.storage, &sender, value, None)? {
+        Ok(value) => match calculate_factorize(value) {
+            Ok(res) => {
+                factors = Some(res.clone());
+                let op = CalculationOp::Factorize { factors: res };
+                insert_result(op, vec![value.into()], None, None, deps, env, &mut status)?;
+            }
+            Err(err) => {
+                status = err.to_string();
+                error_code = Some(err.error_code());
+                let op = CalculationOp::Factorize { factors: vec![] };
+                insert_failure(op, vec![value.into()], None, status.clone(), deps, env)?;
+            }
+        },
+        Err(err) => {
+            status = err.to_string();
+            error_code = Some(err.error_code());
+            let op = CalculationOp::Factorize { factors: vec![] };
+            insert_failure(op, vec![], None, status.clone(), deps, env)?;
+        }
+    };
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::FactorizeResult {
+            factors,
+            status,
+            error_code,
+        })?),
+    })
+}
+
+fn stats<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    values: Vec<Uint128>,
+    decimal_places: Option<u32>,
+    rounding: Option<RoundingMode>,
+    overflow_mode: Option<OverflowMode>,
+) -> StdResult<HandleResponse> {
+    let config: State = load(&deps.storage, CONFIG_KEY)?;
+    let decimal_places = decimal_places.unwrap_or(config.decimal_places);
+    let rounding = rounding.unwrap_or(RoundingMode::Floor);
+
+    let mut stats: Option<Box<Statistics>> = None;
+    let mut status = String::new();
+    let mut error_code = None;
+    let outcome = calculate_stats(
+        &values,
+        decimal_places,
+        rounding,
+        overflow_mode.unwrap_or_default(),
+    );
+    let operands = values.into_iter().map(Number::from).collect();
+
+    match outcome {
+        Ok(res) => {
+            let res = Box::new(res);
+            stats = Some(res.clone());
+            let op = CalculationOp::Stats {
+                statistics: Some(res),
+            };
+            insert_result(op, operands, overflow_mode, None, deps, env, &mut status)?;
+        }
+        Err(err) => {
+            status = err.to_string();
+            error_code = Some(err.error_code());
+            let op = CalculationOp::Stats { statistics: None };
+            insert_failure(op, operands, overflow_mode, status.clone(), deps, env)?;
+        }
+    };
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::StatsResult {
+            stats,
+            status,
+            error_code,
+        })?),
+    })
+}
+
+/// The most operations a batch may hold, to keep the gas usage bounded
+pub const MAX_BATCH_SIZE: usize = 32;
+
+fn batch<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    ops: Vec<Operation>,
+    mode: BatchMode,
+) -> StdResult<HandleResponse> {
+    if ops.len() > MAX_BATCH_SIZE {
+        return Err(StdError::generic_err(format!(
+            "Invalid input: A batch can hold at most {} operations",
+            MAX_BATCH_SIZE
+        )));
+    }
+
+    // References to the previous result point to the result of the previous successful operation
+    // of the batch, or to the sender's history for the operations preceding it
+    let sender = deps.api.canonical_address(&env.message.sender)?;
+    let mut previous = None;
+    let mut outcomes = Vec::with_capacity(ops.len());
+    for operation in ops {
+        let outcome = run_operation(operation, |operand| {
+            resolve_operand(&deps.storage, &sender, operand, previous)
+        })?;
+        if let (_, _, _, Ok(res)) = outcome {
+            previous = Some(res);
+        }
+        outcomes.push(outcome);
+    }
+    let failures = outcomes
+        .iter()
+        .filter(|(_, _, _, result)| result.is_err())
+        .count();
+
+    let mut results = Vec::with_capacity(outcomes.len());
+    let status = if mode == BatchMode::AllOrNothing && failures > 0 {
+        for outcome in outcomes {
+            results.push(match outcome {
+                (_, _, _, Ok(res)) => OperationResult {
+                    n: Some(res),
+                    status: String::from("Calculation not recorded"),
+                    error_code: None,
+                },
+                (_, _, _, Err(err)) => OperationResult {
+                    n: None,
+                    status: err.to_string(),
+                    error_code: Some(err.error_code()),
+                },
+            });
+        }
+        format!(
+            "{} of the calculations failed, none of them was recorded",
+            failures
+        )
+    } else {
+        for outcome in outcomes {
+            let mut status = String::new();
+            let mut error_code = None;
+            let n = match outcome {
+                (op, operands, overflow_mode, Ok(res)) => {
+                    insert_result(
+                        op,
+                        operands,
+                        overflow_mode,
+                        Some(res.into()),
+                        deps,
+                        env.clone(),
+                        &mut status,
+                    )?;
+                    Some(res)
+                }
+                (op, operands, overflow_mode, Err(err)) => {
+                    status = err.to_string();
+                    error_code = Some(err.error_code());
+                    insert_failure(
+                        op,
+                        operands,
+                        overflow_mode,
+                        status.clone(),
+                        deps,
+                        env.clone(),
+                    )?;
+                    None
+                }
+            };
+            results.push(OperationResult {
+                n,
+                status,
+                error_code,
+            });
+        }
+        format!(
+            "{} of the calculations succeeded and were recorded",
+            results.len() - failures
+        )
+    };
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::BatchResult { results, status })?),
+    })
+}
+
+fn evaluate<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    expression: String,
+    overflow_mode: Option<OverflowMode>,
+) -> StdResult<HandleResponse> {
+    let mut result: Option<Uint128> = None;
+    let mut status = String::new();
+    let mut error_code = None;
+
+    let outcome = evaluate_expression(&expression, overflow_mode.unwrap_or_default());
+    let op = CalculationOp::Evaluate { expression };
+
+    match outcome {
+        Ok(res) => {
+            result = Some(res);
+            insert_result(
+                op,
+                vec![],
+                overflow_mode,
+                Some(res.into()),
+                deps,
+                env,
+                &mut status,
+            )?;
+        }
+        Err(err) => {
+            status = err.to_string();
+            error_code = Some(err.error_code());
+            insert_failure(op, vec![], overflow_mode, status.clone(), deps, env)?;
+        }
+    };
+
+    calculation_response(result, status, error_code)
+}
+
+pub fn query<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, msg: QueryMsg) -> QueryResult {
+    match msg {
+        QueryMsg::GetHistory { .. }
+        | QueryMsg::GetCalculationRecords { .. }
+        | QueryMsg::GetHistoryCounts { .. }
+        | QueryMsg::GetRegisters { .. } => viewing_keys_queries(deps, msg),
+        QueryMsg::WithPermit { permit, query } => permit_queries(deps, permit, query),
+        QueryMsg::Compute { op } => {
+            let config: State = load(&deps.storage, CONFIG_KEY)?;
+            if config.contract_status != ContractStatusLevel::Normal {
+                return Err(StdError::generic_err(
+                    "The contract is stopped, and this query is not allowed",
+                ));
+            }
+            to_binary(&compute(op)?)
+        }
+        QueryMsg::ContractStatus {} => {
+            let config: State = load(&deps.storage, CONFIG_KEY)?;
+            to_binary(&QueryAnswer::ContractStatus(ContractStatus {
+                status: config.contract_status,
+            }))
+        }
+    }
+}
+
+pub fn viewing_keys_queries<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    msg: QueryMsg,
+) -> QueryResult {
+    msg.authenticate(deps)?;
+
+    match msg {
+        QueryMsg::GetHistory {
+            address,
+            page,
+            page_size,
+            include_failures,
+            cursor,
+            ..
+        } => to_binary(&may_get_history(
+            deps,
+            &address,
+            page.unwrap_or(0),
+            page_size,
+            include_failures.unwrap_or(true),
+            cursor,
+        )?),
+        QueryMsg::GetCalculationRecords {
+            address,
+            page,
+            page_size,
+            include_failures,
+            cursor,
+            ..
+        } => to_binary(&may_get_calculation_records(
+            deps,
+            &address,
+            page.unwrap_or(0),
+            page_size,
+            include_failures.unwrap_or(true),
+            cursor,
+        )?),
+        QueryMsg::GetHistoryCounts { address, .. } => {
+            to_binary(&may_get_history_counts(deps, &address)?)
+        }
+        QueryMsg::GetRegisters { address, .. } => to_binary(&may_get_registers(deps, &address)?),
+        QueryMsg::WithPermit { .. } | QueryMsg::Compute { .. } | QueryMsg::ContractStatus {} => {
+            Err(StdError::generic_err(
+                "This query isn't authenticated with a viewing key",
+            ))
+        }
+    }
+}
+
+pub fn permit_queries<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    permit: Permit,
+    query: QueryWithPermit,
+) -> QueryResult {
+    let config: State = load(&deps.storage, CONFIG_KEY)?;
+    let account = validate(
+        deps,
+        PREFIX_REVOKED_PERMITS,
+        &permit,
+        config.contract_address,
+    )?;
+
+    let permission = query.permission();
+    if !permit.check_permission(&Permission::Owner) && !permit.check_permission(&permission) {
+        return Err(StdError::generic_err(format!(
+            "No permission to query, got permissions {:?}, expected {:?}",
+            permit.params.permissions, permission
+        )));
+    }
+
+    match query {
+        QueryWithPermit::GetHistory {
+            page,
+            page_size,
+            include_failures,
+            cursor,
+        } => to_binary(&may_get_history(
+            deps,
+            &account,
+            page.unwrap_or(0),
+            page_size,
+            include_failures.unwrap_or(true),
+            cursor,
+        )?),
+        QueryWithPermit::GetCalculationRecords {
+            page,
+            page_size,
+            include_failures,
+            cursor,
+        } => to_binary(&may_get_calculation_records(
+            deps,
+            &account,
+            page.unwrap_or(0),
+            page_size,
+            include_failures.unwrap_or(true),
+            cursor,
+        )?),
+        QueryWithPermit::GetHistoryCounts {} => to_binary(&may_get_history_counts(deps, &account)?),
+        QueryWithPermit::GetRegisters {} => to_binary(&may_get_registers(deps, &account)?),
+    }
+}
+
+pub fn may_get_history<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    account: &HumanAddr,
+    page: u32,
+    page_size: u32,
+    include_failures: bool,
+    cursor: Option<HistoryCursor>,
+) -> StdResult<GetHistory> {
+    let address = deps.api.canonical_address(account)?;
+    let page = get_transfers(
+        &deps.storage,
+        &address,
+        page,
+        page_size,
+        include_failures,
+        cursor,
+    )?;
+    let history = page
+        .records
+        .iter()
+        .map(CalculationRecord::to_display_string)
+        .collect();
+
+    let result = GetHistory {
+        status: page.status,
+        history,
+        total: page.total,
+        next: page.next,
+    };
+    Ok(result)
+}
+
+pub fn may_get_calculation_records<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    account: &HumanAddr,
+    page: u32,
+    page_size: u32,
+    include_failures: bool,
+    cursor: Option<HistoryCursor>,
+) -> StdResult<GetCalculationRecords> {
+    let address = deps.api.canonical_address(account)?;
+    let mut page = get_transfers(
+        &deps.storage,
+        &address,
+        page,
+        page_size,
+        include_failures,
+        cursor,
+    )?;
+    for record in page.records.iter_mut() {
+        record.display = Some(record.to_display_string());
+    }
+
+    let result = GetCalculationRecords {
+        status: page.status,
+        records: page.records,
+        total: page.total,
+        next: page.next,
+    };
+    Ok(result)
+}
+
+pub fn may_get_history_counts<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    account: &HumanAddr,
+) -> StdResult<QueryAnswer> {
+    let address = deps.api.canonical_address(account)?;
+    let counts = get_history_counts(&deps.storage, &address)?;
+    Ok(QueryAnswer::GetHistoryCounts(counts))
+}
+
+pub fn may_get_registers<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    account: &HumanAddr,
+) -> StdResult<QueryAnswer> {
+    let address = deps.api.canonical_address(account)?;
+    let registers = load_registers(&deps.storage, address.as_slice())?;
+    Ok(QueryAnswer::GetRegisters(GetRegisters { registers }))
+}
+
+/// Runs an operation on numbers without recording it
+pub fn compute(operation: Operation) -> StdResult<QueryAnswer> {
+    let (_, _, _, outcome) = run_operation(operation, |operand| match operand {
+        Operand::Value(n) => Ok(Ok(n)),
+        _ => Ok(InvalidInput {
+            message: "Computations can only be performed on numbers",
+        }
+        .fail()),
+    })?;
+
+    let (n, status, error_code) = match outcome {
+        Ok(res) => (Some(res), String::from("Calculation performed"), None),
+        Err(err) => (None, err.to_string(), Some(err.error_code())),
+    };
+    Ok(QueryAnswer::Compute(Compute {
+        n,
+        status,
+        error_code,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decimal::BigDecimal;
+    use crate::msg::Direction;
+    use crate::state::OperationCount;
+    use crate::uint512::MAX_UINT512_DIGITS;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, MockApi, MockQuerier, MockStorage};
+    use cosmwasm_std::{coins, from_binary, from_slice, Binary, CosmosMsg, WasmMsg};
+    use secret_toolkit::permit::{pubkey_to_account, PermitParams, PermitSignature, PubKey};
+    use std::str::FromStr;
+
+    fn init_helper() -> (
+        StdResult<InitResponse>,
+        Extern<MockStorage, MockApi, MockQuerier>,
+    ) {
+        let mut deps = mock_dependencies(20, &[]);
+        let env = mock_env("instantiator", &coins(1000, "token"));
+
+        let init_msg = InitMsg {
+            prng_seed: String::from("waehfjklasd"),
+            decimal_places: None,
+            admin: None,
+        };
+
+        (init(&mut deps, env, init_msg), deps)
+    }
+
+    fn create_viewing_key(
+        deps: &mut Extern<cosmwasm_std::MemoryStorage, MockApi, MockQuerier>,
+    ) -> ViewingKey {
+        let msg = HandleMsg::CreateViewingKey {
+            entropy: String::from("wefhjyr"),
+            label: None,
+            expires_at_height: None,
+            expires_at_time: None,
+            scopes: None,
+            padding: None,
+        };
+        let handle_result = handle(deps, mock_env("bob", &[]), msg);
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        // Get the viewing key of the reply to HandleMsg::CreateViewingKey
+        let answer: HandleAnswer = from_binary(&handle_result.unwrap().data.unwrap()).unwrap();
+        match answer {
+            HandleAnswer::CreateViewingKey { key } => key,
+            _ => panic!("NOPE"),
+        }
+    }
+
+    fn query_history_wrong_vk(deps: Extern<cosmwasm_std::MemoryStorage, MockApi, MockQuerier>) {
         let wrong_vk_query_response = query(
             &deps,
             QueryMsg::GetHistory {
                 address: HumanAddr("bob".to_string()),
-                key: "wrong_vk".to_string(),
+                key: "wrong_vk".to_string(),
+                page: None,
+                page_size: 1,
+                include_failures: None,
+                cursor: None,
+            },
+        );
+        let error = match wrong_vk_query_response {
+            Ok(_response) => "This line should not be reached!".to_string(),
+            Err(_err) => "Wrong viewing key for this address or viewing key not set".to_string(),
+        };
+        assert_eq!(
+            error,
+            "Wrong viewing key for this address or viewing key not set".to_string()
+        );
+    }
+
+    fn query_transactions_history(
+        deps: &mut Extern<cosmwasm_std::MemoryStorage, MockApi, MockQuerier>,
+    ) -> StdResult<Vec<String>> {
+        let vk = create_viewing_key(deps);
+        let query_response = query(
+            &*deps,
+            QueryMsg::GetHistory {
+                address: HumanAddr("bob".to_string()),
+                key: vk.0,
+                page: None,
+                page_size: 1,
+                include_failures: None,
+                cursor: None,
+            },
+        )
+        .unwrap();
+        let hui: GetHistory = from_binary(&query_response)?;
+        Ok(hui.history)
+    }
+
+    #[test]
+    fn test_init_sanity() {
+        let (init_result, _deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_create_viewing_key() {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // Compute the viewing key
+        let key = create_viewing_key(&mut deps);
+
+        // Get the viewing key from the storage
+        let bob_canonical = deps
+            .api
+            .canonical_address(&HumanAddr("bob".to_string()))
+            .unwrap();
+        let saved_vk = ViewingKey::read_viewing_key(&deps.storage, &bob_canonical).unwrap();
+
+        // Verify that the key in the storage is the same as the key from HandleAnswer::CreateViewingKey
+        assert!(key.check_viewing_key(saved_vk.as_slice()));
+    }
+
+    #[test]
+    fn test_add() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // Perform an Add operation
+        let env = mock_env("bob", &coins(2, "token"));
+        let n1: u128 = 3;
+        let n2: u128 = 5;
+        let msg = HandleMsg::Add {
+            operands: (Uint128::from(n1).into(), Uint128::from(n2).into()),
+            overflow_mode: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Query the user's transactions history using their viewing key
+        let history = query_transactions_history(&mut deps)?;
+
+        // Verify the transactions history
+        for i in history {
+            assert_eq!("3 + 5 = 8".to_string(), i);
+        }
+
+        // Now try to hack into bob's account using the wrong key - and fail
+        query_history_wrong_vk(deps);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sub() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // Perform an Add operation
+        let env = mock_env("bob", &coins(2, "token"));
+        let n1: u128 = 20;
+        let n2: u128 = 5;
+        let msg = HandleMsg::Sub {
+            operands: (Uint128::from(n1).into(), Uint128::from(n2).into()),
+            overflow_mode: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Query the user's transactions history using their viewing key
+        let history = query_transactions_history(&mut deps)?;
+
+        // Verify the transactions history
+        for i in history {
+            assert_eq!("20 - 5 = 15".to_string(), i);
+        }
+
+        // Now try to hack into bob's account using the wrong key - and fail
+        query_history_wrong_vk(deps);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mul() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // Perform an Add operation
+        let env = mock_env("bob", &coins(2, "token"));
+        let n1: u128 = 20;
+        let n2: u128 = 5;
+        let msg = HandleMsg::Mul {
+            operands: (Uint128::from(n1).into(), Uint128::from(n2).into()),
+            overflow_mode: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Query the user's transactions history using their viewing key
+        let history = query_transactions_history(&mut deps)?;
+
+        // Verify the transactions history
+        for i in history {
+            assert_eq!("20 * 5 = 100".to_string(), i);
+        }
+
+        // Now try to hack into bob's account using the wrong key - and fail
+        query_history_wrong_vk(deps);
+        Ok(())
+    }
+
+    #[test]
+    fn test_div() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // Perform an Add operation
+        let env = mock_env("bob", &coins(2, "token"));
+        let n1: u128 = 20;
+        let n2: u128 = 5;
+        let msg = HandleMsg::Div((Uint128::from(n1).into(), Uint128::from(n2).into()));
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Query the user's transactions history using their viewing key
+        let history = query_transactions_history(&mut deps)?;
+
+        // Verify the transactions history
+        for i in history {
+            assert_eq!("20 / 5 = 4".to_string(), i);
+        }
+
+        // Now try to hack into bob's account using the wrong key - and fail
+        query_history_wrong_vk(deps);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sqrt() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // Perform an Add operation
+        let env = mock_env("bob", &coins(2, "token"));
+        let n: u128 = 121;
+        let msg = HandleMsg::Sqrt(Uint128::from(n).into());
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Query the user's transactions history using their viewing key
+        let history = query_transactions_history(&mut deps)?;
+
+        // Verify the transactions history
+        for i in history {
+            assert_eq!("√121 = 11".to_string(), i);
+        }
+
+        // Now try to hack into bob's account using the wrong key - and fail
+        query_history_wrong_vk(deps);
+        Ok(())
+    }
+
+    #[test]
+    fn test_signed_sub() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // Perform a signed Sub operation with a negative result
+        let env = mock_env("bob", &coins(2, "token"));
+        let msg = HandleMsg::SignedSub {
+            operands: (Int128::from(5i128), Int128::from(20i128)),
+            overflow_mode: None,
+        };
+        let res = handle(&mut deps, env, msg).unwrap();
+        let answer: HandleAnswer = from_binary(&res.data.unwrap())?;
+        match answer {
+            HandleAnswer::SignedCalculationResult { n, .. } => {
+                assert_eq!(n, Some(Int128::from(-15i128)))
+            }
+            _ => panic!("Unexpected answer"),
+        }
+
+        // Query the user's transactions history using their viewing key
+        let history = query_transactions_history(&mut deps)?;
+
+        // Verify the transactions history
+        assert_eq!(history, vec!["5 - 20 = -15".to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decimal_div() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // Perform a decimal Div operation, rounded to 2 decimal places
+        let env = mock_env("bob", &coins(2, "token"));
+        let msg = HandleMsg::DecimalDiv {
+            operands: (Decimal::from_str("100")?, Decimal::from_str("3")?),
+            decimal_places: Some(2),
+            rounding: Some(RoundingMode::HalfEven),
+            overflow_mode: None,
+        };
+        let res = handle(&mut deps, env, msg).unwrap();
+        let answer: HandleAnswer = from_binary(&res.data.unwrap())?;
+        match answer {
+            HandleAnswer::DecimalCalculationResult { n, .. } => {
+                assert_eq!(n, Some(Decimal::from_str("33.33")?))
+            }
+            _ => panic!("Unexpected answer"),
+        }
+
+        // Perform a decimal Div operation with the contract's default number of decimal places
+        let env = mock_env("bob", &coins(2, "token"));
+        let msg = HandleMsg::DecimalDiv {
+            operands: (Decimal::from_str("100")?, Decimal::from_str("200")?),
+            decimal_places: None,
+            rounding: None,
+            overflow_mode: None,
+        };
+        handle(&mut deps, env, msg).unwrap();
+
+        // Query the user's transactions history using their viewing key
+        let history = query_transactions_history(&mut deps)?;
+
+        // Verify the transactions history
+        assert_eq!(history, vec!["100 / 200 = 0.5".to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_calculation_records() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // Perform a couple of calculations
+        let env = mock_env("bob", &coins(2, "token"));
+        let msg = HandleMsg::Add {
+            operands: (Uint128(3).into(), Uint128(5).into()),
+            overflow_mode: None,
+        };
+        handle(&mut deps, env, msg).unwrap();
+        let env = mock_env("bob", &coins(2, "token"));
+        let msg = HandleMsg::Sqrt(Uint128(121).into());
+        handle(&mut deps, env.clone(), msg).unwrap();
+
+        // Query the structured records, latest first
+        let vk = create_viewing_key(&mut deps);
+        let query_response = query(
+            &deps,
+            QueryMsg::GetCalculationRecords {
+                address: HumanAddr("bob".to_string()),
+                key: vk.0,
+                page: None,
+                page_size: 10,
+                include_failures: None,
+                cursor: None,
+            },
+        )?;
+        let records: GetCalculationRecords = from_binary(&query_response)?;
+        assert_eq!(
+            records.records,
+            vec![
+                CalculationRecord {
+                    op: CalculationOp::Sqrt,
+                    operands: vec![Number::Unsigned(Uint128(121))],
+                    result: Some(Number::Unsigned(Uint128(11))),
+                    block_height: env.block.height,
+                    block_time: env.block.time,
+                    status: Success,
+                    error: None,
+                    overflow_mode: None,
+                    display: Some("√121 = 11".to_string()),
+                },
+                CalculationRecord {
+                    op: CalculationOp::Add,
+                    operands: vec![Number::Unsigned(Uint128(3)), Number::Unsigned(Uint128(5))],
+                    result: Some(Number::Unsigned(Uint128(8))),
+                    block_height: env.block.height,
+                    block_time: env.block.time,
+                    status: Success,
+                    error: None,
+                    overflow_mode: None,
+                    display: Some("3 + 5 = 8".to_string()),
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_failures() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+        let vk = create_viewing_key(&mut deps);
+        let get_history = |deps: &Extern<MockStorage, MockApi, MockQuerier>,
+                           include_failures: Option<bool>|
+         -> StdResult<Vec<String>> {
+            let query_response = query(
+                deps,
+                QueryMsg::GetHistory {
+                    address: HumanAddr("bob".to_string()),
+                    key: vk.0.clone(),
+                    page: None,
+                    page_size: 10,
+                    include_failures,
+                    cursor: None,
+                },
+            )?;
+            let history: GetHistory = from_binary(&query_response)?;
+            Ok(history.history)
+        };
+
+        // Failures aren't recorded by default
+        let msg = HandleMsg::Div((Uint128(1).into(), Uint128(0).into()));
+        handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        assert!(get_history(&deps, None)?.is_empty());
+
+        // Opt in to recording failures
+        let msg = HandleMsg::SetRecordFailures { enabled: true };
+        handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+
+        let msg = HandleMsg::Div((Uint128(1).into(), Uint128(0).into()));
+        handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        let msg = HandleMsg::Div((Uint128(4).into(), Uint128(2).into()));
+        handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+
+        assert_eq!(
+            get_history(&deps, None)?,
+            vec![
+                "4 / 2 = 2".to_string(),
+                "1 / 0 = ? (Invalid input: Cannot divide by zero!)".to_string()
+            ]
+        );
+        assert_eq!(
+            get_history(&deps, Some(false))?,
+            vec!["4 / 2 = 2".to_string()]
+        );
+
+        // and so are calculations whose operands can't be resolved
+        let msg = HandleMsg::Add {
+            operands: (Operand::HistoryIndex(100), Uint128(5).into()),
+            overflow_mode: None,
+        };
+        handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        let msg = HandleMsg::IsPrime(Operand::Register("empty".to_string()));
+        handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        let msg = HandleMsg::Batch {
+            ops: vec![Operation::Sqrt(Operand::Register("empty".to_string()))],
+            mode: Some(BatchMode::BestEffort),
+        };
+        handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        assert_eq!(
+            get_history(&deps, None)?[..3],
+            vec![
+                "√? = ? (Invalid input: Register 'empty' is empty)".to_string(),
+                "isprime(?) = ? (Invalid input: Register 'empty' is empty)".to_string(),
+                "? + ? = ? (Invalid input: History entry 100 has no result)".to_string(),
+            ]
+        );
+        assert_eq!(
+            get_history(&deps, Some(false))?,
+            vec!["4 / 2 = 2".to_string()]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+        let ops = vec![
+            Operation::Add {
+                operands: (Uint128(3).into(), Uint128(5).into()),
+                overflow_mode: None,
+            },
+            Operation::Div((Uint128(1).into(), Uint128(0).into())),
+            Operation::Sqrt(Uint128(121).into()),
+        ];
+
+        // By default, a failing calculation aborts the whole batch
+        let msg = HandleMsg::Batch {
+            ops: ops.clone(),
+            mode: None,
+        };
+        let res = handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        let answer: HandleAnswer = from_binary(&res.data.unwrap())?;
+        match answer {
+            HandleAnswer::BatchResult { results, .. } => {
+                let results: Vec<_> = results.into_iter().map(|res| res.n).collect();
+                assert_eq!(results, vec![Some(Uint128(8)), None, Some(Uint128(11))]);
+            }
+            _ => panic!("Unexpected answer"),
+        }
+        let vk = create_viewing_key(&mut deps);
+        let get_history = |deps: &Extern<MockStorage, MockApi, MockQuerier>| {
+            let query_response = query(
+                deps,
+                QueryMsg::GetHistory {
+                    address: HumanAddr("bob".to_string()),
+                    key: vk.0.clone(),
+                    page: None,
+                    page_size: 10,
+                    include_failures: None,
+                    cursor: None,
+                },
+            )
+            .unwrap();
+            from_binary::<GetHistory>(&query_response).unwrap().history
+        };
+        assert!(get_history(&deps).is_empty());
+
+        // In best effort mode, the successful calculations are recorded
+        let msg = HandleMsg::Batch {
+            ops,
+            mode: Some(BatchMode::BestEffort),
+        };
+        handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        assert_eq!(
+            get_history(&deps),
+            vec!["√121 = 11".to_string(), "3 + 5 = 8".to_string()]
+        );
+
+        // Batches are limited in size
+        let op = Operation::Sqrt(Uint128(4).into());
+        let msg = HandleMsg::Batch {
+            ops: vec![op; MAX_BATCH_SIZE + 1],
+            mode: None,
+        };
+        assert_eq!(
+            handle(&mut deps, mock_env("bob", &[]), msg).unwrap_err(),
+            StdError::generic_err("Invalid input: A batch can hold at most 32 operations")
+        );
+        assert_eq!(get_history(&deps).len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluate() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // Evaluate an expression
+        let env = mock_env("bob", &coins(2, "token"));
+        let msg = HandleMsg::Evaluate {
+            expression: "(3 + 5) * sqrt(121) / 2".to_string(),
+            overflow_mode: None,
+        };
+        let res = handle(&mut deps, env, msg).unwrap();
+        let answer: HandleAnswer = from_binary(&res.data.unwrap())?;
+        match answer {
+            HandleAnswer::CalculationResult { n, .. } => assert_eq!(n, Some(Uint128(44))),
+            _ => panic!("Unexpected answer"),
+        }
+
+        // Query the user's transactions history using their viewing key
+        let history = query_transactions_history(&mut deps)?;
+
+        // Verify the transactions history
+        assert_eq!(history, vec!["(3 + 5) * sqrt(121) / 2 = 44".to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_chained_operands() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+        let result = |res: HandleResponse| match from_binary(&res.data.unwrap()).unwrap() {
+            HandleAnswer::CalculationResult { n, .. } => n,
+            _ => panic!("Unexpected answer"),
+        };
+
+        // There's nothing to refer to yet
+        let msg = HandleMsg::Add {
+            operands: (Operand::Previous, Uint128(5).into()),
+            overflow_mode: None,
+        };
+        let res = handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        assert_eq!(result(res), None);
+
+        let msg = HandleMsg::Add {
+            operands: (Uint128(3).into(), Uint128(5).into()),
+            overflow_mode: None,
+        };
+        handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        let msg = HandleMsg::Mul {
+            operands: (Operand::Previous, Uint128(2).into()),
+            overflow_mode: None,
+        };
+        let res = handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        assert_eq!(result(res), Some(Uint128(16)));
+        let msg = HandleMsg::Sub {
+            operands: (Operand::Previous, Operand::HistoryIndex(0)),
+            overflow_mode: None,
+        };
+        let res = handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        assert_eq!(result(res), Some(Uint128(8)));
+        let msg = HandleMsg::Sqrt(Operand::HistoryIndex(3));
+        let res = handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        assert_eq!(result(res), None);
+
+        // Within a batch, the previous result is the one of the previous calculation
+        let msg = HandleMsg::Batch {
+            ops: vec![
+                Operation::Add {
+                    operands: (Operand::Previous, Uint128(1).into()),
+                    overflow_mode: None,
+                },
+                Operation::Mul {
+                    operands: (Operand::Previous, Operand::Previous),
+                    overflow_mode: None,
+                },
+            ],
+            mode: None,
+        };
+        handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+
+        // The history records the resolved operands
+        let vk = create_viewing_key(&mut deps);
+        let query_response = query(
+            &deps,
+            QueryMsg::GetHistory {
+                address: HumanAddr("bob".to_string()),
+                key: vk.0,
+                page: None,
+                page_size: 10,
+                include_failures: None,
+                cursor: None,
+            },
+        )?;
+        let history: GetHistory = from_binary(&query_response)?;
+        assert_eq!(
+            history.history,
+            vec![
+                "9 * 9 = 81".to_string(),
+                "8 + 1 = 9".to_string(),
+                "16 - 8 = 8".to_string(),
+                "8 * 2 = 16".to_string(),
+                "3 + 5 = 8".to_string(),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_registers() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+        let memory_result = |res: HandleResponse| match from_binary(&res.data.unwrap()).unwrap() {
+            HandleAnswer::MemoryResult { n, .. } => n,
+            _ => panic!("Unexpected answer"),
+        };
+        let register = || "m".to_string();
+
+        // Store a result, then add to and subtract from it
+        let msg = HandleMsg::Add {
+            operands: (Uint128(3).into(), Uint128(5).into()),
+            overflow_mode: None,
+        };
+        handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        let msg = HandleMsg::MemoryStore {
+            register: register(),
+            value: Operand::Previous,
+        };
+        let res = handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        assert_eq!(memory_result(res), Some(Uint128(8)));
+        let msg = HandleMsg::MemoryAdd {
+            register: register(),
+            value: Uint128(4).into(),
+        };
+        let res = handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        assert_eq!(memory_result(res), Some(Uint128(12)));
+        let msg = HandleMsg::MemorySub {
+            register: register(),
+            value: Uint128(13).into(),
+        };
+        let res = handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        assert_eq!(memory_result(res), None);
+
+        // Recall the register as an operand
+        let msg = HandleMsg::Mul {
+            operands: (Operand::Register(register()), Uint128(2).into()),
+            overflow_mode: None,
+        };
+        let res = handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        match from_binary(&res.data.unwrap())? {
+            HandleAnswer::CalculationResult { n, .. } => assert_eq!(n, Some(Uint128(24))),
+            _ => panic!("Unexpected answer"),
+        }
+
+        // Registers are private
+        let msg = HandleMsg::Add {
+            operands: (Operand::Register(register()), Uint128(1).into()),
+            overflow_mode: None,
+        };
+        let res = handle(&mut deps, mock_env("alice", &[]), msg).unwrap();
+        match from_binary(&res.data.unwrap())? {
+            HandleAnswer::CalculationResult { n, .. } => assert_eq!(n, None),
+            _ => panic!("Unexpected answer"),
+        }
+        let query_msg = QueryMsg::GetRegisters {
+            address: HumanAddr("bob".to_string()),
+            key: "wrong_vk".to_string(),
+        };
+        assert!(query(&deps, query_msg).is_err());
+
+        let vk = create_viewing_key(&mut deps);
+        let get_registers = |deps: &Extern<MockStorage, MockApi, MockQuerier>| {
+            let query_msg = QueryMsg::GetRegisters {
+                address: HumanAddr("bob".to_string()),
+                key: vk.0.clone(),
+            };
+            match from_binary(&query(deps, query_msg).unwrap()).unwrap() {
+                QueryAnswer::GetRegisters(GetRegisters { registers }) => registers,
+                _ => panic!("Unexpected answer"),
+            }
+        };
+        assert_eq!(
+            get_registers(&deps),
+            vec![Register {
+                name: register(),
+                value: Uint128(12),
+            }]
+        );
+
+        // Clear the register
+        let msg = HandleMsg::MemoryClear {
+            register: register(),
+        };
+        handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        assert!(get_registers(&deps).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_permits() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let msg = HandleMsg::RevokePermit {
+            permit_name: "wallet".to_string(),
+            padding: None,
+        };
+        let res = handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        match from_binary(&res.data.unwrap())? {
+            HandleAnswer::RevokePermit { status } => assert_eq!(status, Success),
+            _ => panic!("Unexpected answer"),
+        }
+
+        // Permits are only accepted by the contracts they were issued for
+        let permit = Permit {
+            params: PermitParams {
+                allowed_tokens: vec![HumanAddr("another_contract".to_string())],
+                permit_name: "wallet".to_string(),
+                chain_id: "secret-4".to_string(),
+                permissions: vec![Permission::History],
+            },
+            signature: PermitSignature {
+                pub_key: PubKey {
+                    r#type: "tendermint/PubKeySecp256k1".to_string(),
+                    value: Binary(vec![2; 33]),
+                },
+                signature: Binary(vec![0; 64]),
+            },
+        };
+        let query_msg = QueryMsg::WithPermit {
+            permit,
+            query: QueryWithPermit::GetHistory {
+                page: None,
+                page_size: 10,
+                include_failures: None,
+                cursor: None,
+            },
+        };
+        let err = query(&deps, query_msg).unwrap_err();
+        assert!(err.to_string().contains("Permit doesn't apply to token"));
+
+        // Permits signed for this contract by a test key
+        let pub_key = Binary::from_base64("AsQ9IU97j/P1OyYFb6NfperPlizQW95rH09E/EoHfekU")?;
+        let signer = deps.api.human_address(&pubkey_to_account(&pub_key))?;
+        let permit = |name: &str, permission: Permission, signature: &str| {
+            Ok::<_, StdError>(Permit {
+                params: PermitParams {
+                    allowed_tokens: vec![HumanAddr("cosmos2contract".to_string())],
+                    permit_name: name.to_string(),
+                    chain_id: "secret-4".to_string(),
+                    permissions: vec![permission],
+                },
+                signature: PermitSignature {
+                    pub_key: PubKey {
+                        r#type: "tendermint/PubKeySecp256k1".to_string(),
+                        value: pub_key.clone(),
+                    },
+                    signature: Binary::from_base64(signature)?,
+                },
+            })
+        };
+        let history_permit = permit(
+            "history",
+            Permission::History,
+            "AwMjYX3Pc3LiM8gVBrOSPmoJ3t1EzoSvr5Ix+7YwMpUQ13Hq3+SmyY5SPuGIzWT52o24LAfSqY+oi7OUgfo4Pw==",
+        )?;
+        let balance_permit = permit(
+            "balance",
+            Permission::Balance,
+            "hu+YNUmVNuWPzZj7Qh49yuYUeL0S3Dv6Y/CqPh0shNQumJsnOluqE2OodJBABWBdmeI11jp7LOiuJ8rE0eripg==",
+        )?;
+        let owner_permit = permit(
+            "owner",
+            Permission::Owner,
+            "D9ET56uqqLbx1aIcbep3m5jeNxEWIlLcWw8A0SG6I5gBiX6Yfz+Toxe/1sBvIzKIE+Ls17dkfWqomXim8SQ01Q==",
+        )?;
+        let get_history = QueryWithPermit::GetHistory {
+            page: None,
+            page_size: 10,
+            include_failures: None,
+            cursor: None,
+        };
+        let with_permit = |deps: &Extern<MockStorage, MockApi, MockQuerier>,
+                           permit: &Permit,
+                           query_msg: &QueryWithPermit| {
+            query(
+                deps,
+                QueryMsg::WithPermit {
+                    permit: permit.clone(),
+                    query: query_msg.clone(),
+                },
+            )
+        };
+
+        let msg = HandleMsg::Add {
+            operands: (Uint128(3).into(), Uint128(5).into()),
+            overflow_mode: None,
+        };
+        handle(&mut deps, mock_env(signer.clone(), &[]), msg)?;
+        let msg = HandleMsg::MemoryStore {
+            register: "tax".to_string(),
+            value: Uint128(7).into(),
+        };
+        handle(&mut deps, mock_env(signer.clone(), &[]), msg)?;
+
+        // A permit grants the queries of its permissions over the history of its signer
+        let history: GetHistory = from_binary(&with_permit(&deps, &history_permit, &get_history)?)?;
+        assert_eq!(history.history, vec!["3 + 5 = 8".to_string()]);
+        let err = with_permit(&deps, &balance_permit, &get_history).unwrap_err();
+        assert!(err.to_string().contains("No permission to query"));
+        let err =
+            with_permit(&deps, &history_permit, &QueryWithPermit::GetRegisters {}).unwrap_err();
+        assert!(err.to_string().contains("No permission to query"));
+        let query_response = with_permit(&deps, &owner_permit, &QueryWithPermit::GetRegisters {})?;
+        match from_binary(&query_response)? {
+            QueryAnswer::GetRegisters(GetRegisters { registers }) => assert_eq!(
+                registers,
+                vec![Register {
+                    name: "tax".to_string(),
+                    value: Uint128(7),
+                }]
+            ),
+            answer => panic!("Unexpected answer: {:?}", answer),
+        }
+        assert!(with_permit(&deps, &owner_permit, &get_history).is_ok());
+
+        // A tampered permit doesn't match its signature
+        let mut tampered = history_permit.clone();
+        tampered.params.permissions = vec![Permission::Owner];
+        let err = with_permit(&deps, &tampered, &QueryWithPermit::GetRegisters {}).unwrap_err();
+        assert!(err.to_string().contains("Failed to verify signatures"));
+
+        // Revoked permits are refused, but the signer's other permits still work
+        let msg = HandleMsg::RevokePermit {
+            permit_name: "history".to_string(),
+            padding: None,
+        };
+        handle(&mut deps, mock_env(signer, &[]), msg)?;
+        let err = with_permit(&deps, &history_permit, &get_history).unwrap_err();
+        assert!(err.to_string().contains("was revoked"));
+        assert!(with_permit(&deps, &owner_permit, &get_history).is_ok());
+
+        // Queries without viewing keys can't be authenticated with one
+        assert!(viewing_keys_queries(&deps, QueryMsg::ContractStatus {}).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_cursor() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+        for n in 1..=5u128 {
+            let msg = HandleMsg::Add {
+                operands: (Uint128(n).into(), Uint128(n).into()),
+                overflow_mode: None,
+            };
+            handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        }
+        let vk = create_viewing_key(&mut deps);
+        let get_history = |deps: &Extern<MockStorage, MockApi, MockQuerier>,
+                           page: Option<u32>,
+                           cursor: Option<HistoryCursor>| {
+            let query_response = query(
+                deps,
+                QueryMsg::GetHistory {
+                    address: HumanAddr("bob".to_string()),
+                    key: vk.0.clone(),
+                    page,
+                    page_size: 2,
+                    include_failures: None,
+                    cursor,
+                },
+            )
+            .unwrap();
+            from_binary::<GetHistory>(&query_response).unwrap()
+        };
+
+        // Pages report the size of the whole history
+        let history = get_history(&deps, Some(2), None);
+        assert_eq!(history.history, vec!["1 + 1 = 2".to_string()]);
+        assert_eq!(history.total, 5);
+        assert_eq!(history.next, None);
+
+        // Read forward from an index
+        let cursor = HistoryCursor {
+            start: 1,
+            direction: Direction::Forward,
+        };
+        let history = get_history(&deps, None, Some(cursor));
+        assert_eq!(
+            history.history,
+            vec!["2 + 2 = 4".to_string(), "3 + 3 = 6".to_string()]
+        );
+        assert_eq!(history.next, Some(3));
+
+        // Read backward from the latest entry
+        let cursor = HistoryCursor {
+            start: u32::MAX,
+            direction: Direction::Backward,
+        };
+        let history = get_history(&deps, None, Some(cursor));
+        assert_eq!(
+            history.history,
+            vec!["5 + 5 = 10".to_string(), "4 + 4 = 8".to_string()]
+        );
+        assert_eq!(history.next, Some(2));
+
+        let cursor = HistoryCursor {
+            start: 1,
+            direction: Direction::Backward,
+        };
+        let history = get_history(&deps, None, Some(cursor));
+        assert_eq!(
+            history.history,
+            vec!["2 + 2 = 4".to_string(), "1 + 1 = 2".to_string()]
+        );
+        assert_eq!(history.next, None);
+        assert_eq!(history.total, 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pow_mod_log() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let msgs = vec![
+            HandleMsg::Pow {
+                operands: (Uint128(2).into(), Uint128(10).into()),
+                overflow_mode: None,
+            },
+            HandleMsg::Pow {
+                operands: (Uint128(1).into(), Uint128(1 << 33).into()),
+                overflow_mode: None,
+            },
+            HandleMsg::Pow {
+                operands: (Uint128(0).into(), Uint128(u128::MAX).into()),
+                overflow_mode: None,
+            },
+            HandleMsg::Mod((Uint128(17).into(), Uint128(5).into())),
+            HandleMsg::Log2(Uint128(1024).into()),
+            HandleMsg::Log10(Uint128(1000).into()),
+            HandleMsg::Log {
+                value: Uint128(125).into(),
+                base: Uint128(5),
+            },
+        ];
+        for msg in msgs {
+            handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        }
+
+        let vk = create_viewing_key(&mut deps);
+        let query_response = query(
+            &deps,
+            QueryMsg::GetHistory {
+                address: HumanAddr("bob".to_string()),
+                key: vk.0,
                 page: None,
-                page_size: 1,
+                page_size: 10,
+                include_failures: None,
+                cursor: None,
             },
+        )?;
+        let history: GetHistory = from_binary(&query_response)?;
+        assert_eq!(
+            history.history,
+            vec![
+                "log5(125) = 3".to_string(),
+                "log10(1000) = 3".to_string(),
+                "log2(1024) = 10".to_string(),
+                "17 mod 5 = 2".to_string(),
+                format!("0 ^ {} = 0", u128::MAX),
+                "1 ^ 8589934592 = 1".to_string(),
+                "2 ^ 10 = 1024".to_string(),
+            ]
         );
-        let error = match wrong_vk_query_response {
-            Ok(_response) => "This line should not be reached!".to_string(),
-            Err(_err) => "Wrong viewing key for this address or viewing key not set".to_string(),
-        };
+        Ok(())
+    }
+
+    #[test]
+    fn test_nth_root() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+        let nth_root =
+            |deps: &mut Extern<MockStorage, MockApi, MockQuerier>, value: u128, n: u32| {
+                let msg = HandleMsg::NthRoot {
+                    value: Uint128(value).into(),
+                    n,
+                };
+                let res = handle(deps, mock_env("bob", &[]), msg).unwrap();
+                match from_binary(&res.data.unwrap()).unwrap() {
+                    HandleAnswer::NthRootResult {
+                        n,
+                        exact,
+                        remainder,
+                        ..
+                    } => (n, exact, remainder),
+                    _ => panic!("Unexpected answer"),
+                }
+            };
+
         assert_eq!(
-            error,
-            "Wrong viewing key for this address or viewing key not set".to_string()
+            nth_root(&mut deps, 27, 3),
+            (Some(Uint128(3)), Some(true), Some(Uint128(0)))
+        );
+        assert_eq!(
+            nth_root(&mut deps, 90, 4),
+            (Some(Uint128(3)), Some(false), Some(Uint128(9)))
+        );
+        assert_eq!(nth_root(&mut deps, 8, 0), (None, None, None));
+
+        let history = query_transactions_history(&mut deps)?;
+        assert_eq!(history, vec!["root4(90) = 3".to_string()]);
+
+        // The bound of the root of a huge degree is computed without overflowing
+        assert_eq!(
+            nth_root(&mut deps, u128::MAX, u32::MAX),
+            (Some(Uint128(1)), Some(false), Some(Uint128(u128::MAX - 1)))
+        );
+
+        let history = query_transactions_history(&mut deps)?;
+        assert_eq!(
+            history,
+            vec![format!("root{}({}) = 1", u32::MAX, u128::MAX)]
         );
+
+        let op = Operation::NthRoot {
+            value: Uint128(u128::MAX).into(),
+            n: u32::MAX,
+        };
+        match from_binary(&query(&deps, QueryMsg::Compute { op })?)? {
+            QueryAnswer::Compute(compute) => assert_eq!(compute.n, Some(Uint128(1))),
+            answer => panic!("Unexpected answer: {:?}", answer),
+        }
+        Ok(())
     }
 
-    fn query_transactions_history(
-        deps: &mut Extern<cosmwasm_std::MemoryStorage, MockApi, MockQuerier>,
-    ) -> StdResult<Vec<String>> {
-        let vk = create_viewing_key(deps);
+    #[test]
+    fn test_number_theory() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let msg = HandleMsg::Gcd((Uint128(84).into(), Uint128(36).into()));
+        handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        let msg = HandleMsg::Lcm {
+            operands: (Uint128(4).into(), Uint128(6).into()),
+            overflow_mode: None,
+        };
+        handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+
+        let msg = HandleMsg::IsPrime(Uint128(97).into());
+        let res = handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        match from_binary(&res.data.unwrap())? {
+            HandleAnswer::IsPrimeResult { is_prime, .. } => assert_eq!(is_prime, Some(true)),
+            _ => panic!("Unexpected answer"),
+        }
+
+        let msg = HandleMsg::Factorize(Uint128(360).into());
+        let res = handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        match from_binary(&res.data.unwrap())? {
+            HandleAnswer::FactorizeResult { factors, .. } => assert_eq!(
+                factors,
+                Some(vec![
+                    Factor {
+                        prime: Uint128(2),
+                        exponent: 3
+                    },
+                    Factor {
+                        prime: Uint128(3),
+                        exponent: 2
+                    },
+                    Factor {
+                        prime: Uint128(5),
+                        exponent: 1
+                    },
+                ])
+            ),
+            _ => panic!("Unexpected answer"),
+        }
+
+        let vk = create_viewing_key(&mut deps);
         let query_response = query(
-            &*deps,
+            &deps,
             QueryMsg::GetHistory {
                 address: HumanAddr("bob".to_string()),
                 key: vk.0,
                 page: None,
-                page_size: 1,
+                page_size: 10,
+                include_failures: None,
+                cursor: None,
             },
-        )
-        .unwrap();
-        let hui: GetHistory = from_binary(&query_response)?;
-        Ok(hui.history)
+        )?;
+        let history: GetHistory = from_binary(&query_response)?;
+        assert_eq!(
+            history.history,
+            vec![
+                "factorize(360) = 2^3 * 3^2 * 5".to_string(),
+                "isprime(97) = true".to_string(),
+                "lcm(4, 6) = 12".to_string(),
+                "gcd(84, 36) = 12".to_string(),
+            ]
+        );
+        Ok(())
     }
 
     #[test]
-    fn test_init_sanity() {
-        let (init_result, _deps) = init_helper();
+    fn test_stats() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
         assert!(
             init_result.is_ok(),
             "Init failed: {}",
             init_result.err().unwrap()
         );
+
+        let msg = HandleMsg::Stats {
+            values: vec![Uint128(1), Uint128(2), Uint128(4)],
+            decimal_places: Some(2),
+            rounding: Some(RoundingMode::HalfEven),
+            overflow_mode: None,
+        };
+        let res = handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        match from_binary(&res.data.unwrap())? {
+            HandleAnswer::StatsResult { stats, .. } => {
+                let stats = stats.unwrap();
+                assert_eq!(stats.sum, Uint128(7));
+                assert_eq!(stats.mean, BigDecimal::from_str("2.33")?);
+            }
+            _ => panic!("Unexpected answer"),
+        }
+
+        let history = query_transactions_history(&mut deps)?;
+        assert_eq!(
+            history,
+            vec!["stats(1, 2, 4) = sum 7, min 1, max 4, mean 2.33, median 2, mode 1, variance 1.56, std dev 1.25".to_string()]
+        );
+        Ok(())
     }
 
     #[test]
-    fn test_create_viewing_key() {
+    fn test_big_mul() -> StdResult<()> {
         // Initialize the contract
         let (init_result, mut deps) = init_helper();
         assert!(
@@ -323,22 +3375,354 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        // Compute the viewing key
-        let key = create_viewing_key(&mut deps);
+        // Multiply two amounts of a token with 18 decimals
+        let amount = Uint512::from_str("5000000000000000000000000")?;
+        let msg = HandleMsg::BigMul {
+            operands: (amount, amount),
+            overflow_mode: None,
+        };
+        let res = handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        let expected = Uint512::from_str("25000000000000000000000000000000000000000000000000")?;
+        match from_binary(&res.data.unwrap())? {
+            HandleAnswer::BigCalculationResult { n, .. } => assert_eq!(n, Some(expected)),
+            _ => panic!("Unexpected answer"),
+        }
 
-        // Get the viewing key from the storage
-        let bob_canonical = deps
-            .api
-            .canonical_address(&HumanAddr("bob".to_string()))
-            .unwrap();
-        let saved_vk = ViewingKey::read_viewing_key(&deps.storage, &bob_canonical).unwrap();
+        let history = query_transactions_history(&mut deps)?;
+        assert_eq!(
+            history,
+            vec![format!("{} * {} = {}", amount, amount, expected)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_big_operations() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let amount = Uint512::from_str("5000000000000000000000000")?;
+        let square = Uint512::from_str("25000000000000000000000000000000000000000000000000")?;
+        let cases = [
+            (
+                HandleMsg::BigMulDiv {
+                    a: amount,
+                    b: amount,
+                    denom: Uint512::from_str("1000000000000000000")?,
+                    rounding: None,
+                    overflow_mode: None,
+                },
+                "25000000000000000000000000000000",
+                format!("{} * {} / 1000000000000000000", amount, amount),
+            ),
+            (
+                HandleMsg::BigNthRoot {
+                    value: square,
+                    n: 2,
+                },
+                "5000000000000000000000000",
+                format!("root2({})", square),
+            ),
+            (
+                HandleMsg::BigLog10(square),
+                "49",
+                format!("log10({})", square),
+            ),
+            (
+                HandleMsg::BigLog {
+                    value: square,
+                    base: amount,
+                },
+                "2",
+                format!("log{}({})", amount, square),
+            ),
+            (
+                HandleMsg::BigGcd((square, Uint512::from(Uint128(48)))),
+                "16",
+                format!("gcd({}, 48)", square),
+            ),
+            (
+                HandleMsg::BigLcm {
+                    operands: (amount, Uint512::from(Uint128(3))),
+                    overflow_mode: None,
+                },
+                "15000000000000000000000000",
+                format!("lcm({}, 3)", amount),
+            ),
+        ];
+        for (msg, expected, calculation) in cases {
+            let res = handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+            match from_binary(&res.data.unwrap())? {
+                HandleAnswer::BigCalculationResult { n, .. } => {
+                    assert_eq!(n, Some(Uint512::from_str(expected)?))
+                }
+                _ => panic!("Unexpected answer"),
+            }
+            let history = query_transactions_history(&mut deps)?;
+            assert_eq!(history, vec![format!("{} = {}", calculation, expected)]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_percent_fee_mul_div() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let msgs = vec![
+            HandleMsg::Percent {
+                value: Uint128(200).into(),
+                bps: 2500,
+                rounding: None,
+                overflow_mode: None,
+            },
+            HandleMsg::ApplyFee {
+                amount: Uint128(999).into(),
+                fee_bps: 30,
+                rounding: Some(RoundingMode::Ceil),
+            },
+            HandleMsg::MulDiv {
+                a: Uint128(u128::MAX).into(),
+                b: Uint128(3).into(),
+                denom: Uint128(6).into(),
+                rounding: None,
+                overflow_mode: None,
+            },
+        ];
+        for msg in msgs {
+            handle(&mut deps, mock_env("bob", &[]), msg).unwrap();
+        }
+
+        let vk = create_viewing_key(&mut deps);
+        let query_response = query(
+            &deps,
+            QueryMsg::GetHistory {
+                address: HumanAddr("bob".to_string()),
+                key: vk.0,
+                page: None,
+                page_size: 10,
+                include_failures: None,
+                cursor: None,
+            },
+        )?;
+        let history: GetHistory = from_binary(&query_response)?;
+        assert_eq!(
+            history.history,
+            vec![
+                format!("{} * 3 / 6 = {}", u128::MAX, u128::MAX / 2),
+                "999 - 30 bps fee = 996".to_string(),
+                "2500 bps of 200 = 50".to_string(),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_overflow_modes() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, mut deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let max = Uint128(u128::MAX);
+        let msgs = vec![
+            HandleMsg::Add {
+                operands: (max.into(), Uint128(1).into()),
+                overflow_mode: None,
+            },
+            HandleMsg::Add {
+                operands: (max.into(), Uint128(1).into()),
+                overflow_mode: Some(OverflowMode::Saturating),
+            },
+            HandleMsg::Mul {
+                operands: (max.into(), Uint128(2).into()),
+                overflow_mode: Some(OverflowMode::Wrapping),
+            },
+            HandleMsg::Sub {
+                operands: (Uint128(3).into(), Uint128(5).into()),
+                overflow_mode: Some(OverflowMode::Saturating),
+            },
+            HandleMsg::SignedAdd {
+                operands: (Int128(i128::MAX), Int128(1)),
+                overflow_mode: Some(OverflowMode::Wrapping),
+            },
+            HandleMsg::Add {
+                operands: (Uint128(1).into(), Uint128(2).into()),
+                overflow_mode: Some(OverflowMode::Checked),
+            },
+            HandleMsg::DecimalMul {
+                operands: (
+                    Decimal::from_str(&max.to_string())?,
+                    Decimal::from_str("1.5")?,
+                ),
+                decimal_places: Some(0),
+                rounding: None,
+                overflow_mode: Some(OverflowMode::Saturating),
+            },
+            HandleMsg::BigAdd {
+                operands: (Uint512::max(), Uint512::from(Uint128(1))),
+                overflow_mode: Some(OverflowMode::Wrapping),
+            },
+            HandleMsg::Evaluate {
+                expression: "2 * (3 - 5)".to_string(),
+                overflow_mode: Some(OverflowMode::Wrapping),
+            },
+            HandleMsg::Stats {
+                values: vec![max, Uint128(1)],
+                decimal_places: Some(0),
+                rounding: None,
+                overflow_mode: Some(OverflowMode::Saturating),
+            },
+        ];
+        let mut results = vec![];
+        for msg in msgs {
+            let handle_response = handle(&mut deps, mock_env("bob", &[]), msg)?;
+            results.push(from_binary(&handle_response.data.unwrap())?);
+        }
+        assert!(matches!(
+            results[0],
+            HandleAnswer::CalculationResult { n: None, .. }
+        ));
+        assert!(matches!(
+            results[1],
+            HandleAnswer::CalculationResult { n: Some(n), .. } if n == max
+        ));
+        // Only the sum of the statistics saturates, the mean is that of the exact sum
+        match &results[9] {
+            HandleAnswer::StatsResult {
+                stats: Some(stats), ..
+            } => {
+                assert_eq!(stats.sum, max);
+                assert_eq!(stats.mean.to_string(), (1u128 << 127).to_string());
+            }
+            _ => panic!("Unexpected answer"),
+        }
+
+        let vk = create_viewing_key(&mut deps);
+        let query_response = query(
+            &deps,
+            QueryMsg::GetHistory {
+                address: HumanAddr("bob".to_string()),
+                key: vk.0,
+                page: None,
+                page_size: 10,
+                include_failures: None,
+                cursor: None,
+            },
+        )?;
+        let history: GetHistory = from_binary(&query_response)?;
+        assert_eq!(
+            history.history[1..],
+            vec![
+                format!("2 * (3 - 5) = {} (wrapping)", u128::MAX - 3),
+                format!("{} + 1 = 0 (wrapping)", "9".repeat(MAX_UINT512_DIGITS)),
+                format!("{} * 1.5 = {} (saturating)", u128::MAX, u128::MAX),
+                "1 + 2 = 3".to_string(),
+                format!("{} + 1 = {} (wrapping)", i128::MAX, i128::MIN),
+                "3 - 5 = 0 (saturating)".to_string(),
+                format!("{} * 2 = {} (wrapping)", u128::MAX, u128::MAX - 1),
+                format!("{} + 1 = {} (saturating)", u128::MAX, u128::MAX),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_overflow_mode_json() -> StdResult<()> {
+        let msg: HandleMsg =
+            from_slice(br#"{"add":{"operands":["5","previous"],"overflow_mode":"wrapping"}}"#)?;
+        assert_eq!(
+            msg,
+            HandleMsg::Add {
+                operands: (Uint128(5).into(), Operand::Previous),
+                overflow_mode: Some(OverflowMode::Wrapping),
+            }
+        );
+        let msg: HandleMsg = from_slice(br#"{"sub":{"operands":["5","3"]}}"#)?;
+        assert_eq!(
+            msg,
+            HandleMsg::Sub {
+                operands: (Uint128(5).into(), Uint128(3).into()),
+                overflow_mode: None,
+            }
+        );
+        // Operations that can't overflow take their operands as a pair
+        let msg: HandleMsg = from_slice(br#"{"div":["6","3"]}"#)?;
+        assert_eq!(msg, HandleMsg::Div((Uint128(6).into(), Uint128(3).into())));
+
+        assert!(from_slice::<HandleMsg>(br#"{"add":["5","3","wrapping"]}"#).is_err());
+        assert!(from_slice::<HandleMsg>(br#"{"div":{"operands":["6","3"]}}"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_compute() -> StdResult<()> {
+        // Initialize the contract
+        let (init_result, deps) = init_helper();
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let compute = |op: Operation| -> StdResult<Compute> {
+            match from_binary(&query(&deps, QueryMsg::Compute { op })?)? {
+                QueryAnswer::Compute(compute) => Ok(compute),
+                _ => panic!("Unexpected query answer"),
+            }
+        };
+
+        let result = compute(Operation::Add {
+            operands: (Uint128(3).into(), Uint128(5).into()),
+            overflow_mode: None,
+        })?;
+        assert_eq!(result.n, Some(Uint128(8)));
+        assert_eq!(result.status, "Calculation performed");
+
+        let result = compute(Operation::MulDiv {
+            a: Uint128(10).into(),
+            b: Uint128(2).into(),
+            denom: Uint128(3).into(),
+            rounding: Some(RoundingMode::Ceil),
+            overflow_mode: None,
+        })?;
+        assert_eq!(result.n, Some(Uint128(7)));
+
+        let result = compute(Operation::Div((Uint128(1).into(), Uint128(0).into())))?;
+        assert_eq!(result.n, None);
+        assert!(result.status.contains("by zero"));
+
+        let result = compute(Operation::Sqrt(Operand::Previous))?;
+        assert_eq!(result.n, None);
+        assert_eq!(
+            result.status,
+            "Invalid input: Computations can only be performed on numbers"
+        );
 
-        // Verify that the key in the storage is the same as the key from HandleAnswer::CreateViewingKey
-        assert!(key.check_viewing_key(saved_vk.as_slice()));
+        // Nothing was recorded
+        assert!(get_last_result(
+            &deps.storage,
+            &deps.api.canonical_address(&HumanAddr("bob".to_string()))?
+        )?
+        .is_none());
+        Ok(())
     }
 
     #[test]
-    fn test_add() -> StdResult<()> {
+    fn test_calculate_with_callback() -> StdResult<()> {
         // Initialize the contract
         let (init_result, mut deps) = init_helper();
         assert!(
@@ -347,28 +3731,75 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        // Perform an Add operation
-        let env = mock_env("bob", &coins(2, "token"));
-        let n1: u128 = 3;
-        let n2: u128 = 5;
-        let msg = HandleMsg::Add((Uint128::from(n1), Uint128::from(n2)));
-        let _res = handle(&mut deps, env, msg).unwrap();
-
-        // Query the user's transactions history using their viewing key
-        let history = query_transactions_history(&mut deps)?;
+        let callback = Callback {
+            contract_addr: HumanAddr("vault".to_string()),
+            code_hash: "code_hash".to_string(),
+            msg: Some(Binary::from(b"reward")),
+        };
+        let msg = HandleMsg::Calculate {
+            op: Operation::Mul {
+                operands: (Uint128(6).into(), Uint128(7).into()),
+                overflow_mode: None,
+            },
+            callback: Some(callback.clone()),
+        };
+        let handle_response = handle(&mut deps, mock_env("bob", &[]), msg)?;
+        let answer: HandleAnswer = from_binary(&handle_response.data.unwrap())?;
+        assert!(matches!(
+            answer,
+            HandleAnswer::CalculationResult {
+                n: Some(Uint128(42)),
+                ..
+            }
+        ));
+        assert_eq!(
+            handle_response.messages,
+            vec![ReceiverHandleMsg::ReceiveCalculation {
+                sender: HumanAddr("bob".to_string()),
+                n: Some(Uint128(42)),
+                status: "Calculation performed and recorded!".to_string(),
+                error_code: None,
+                msg: Some(Binary::from(b"reward")),
+            }
+            .to_cosmos_msg(
+                callback.code_hash.clone(),
+                callback.contract_addr.clone(),
+                None
+            )?]
+        );
+        assert_eq!(
+            query_transactions_history(&mut deps)?,
+            vec!["6 * 7 = 42".to_string()]
+        );
 
-        // Verify the transactions history
-        for i in history {
-            assert_eq!("3 + 5 = 8".to_string(), i);
+        // Failures are sent too
+        let msg = HandleMsg::Calculate {
+            op: Operation::Div((Uint128(6).into(), Uint128(0).into())),
+            callback: Some(callback),
+        };
+        let handle_response = handle(&mut deps, mock_env("bob", &[]), msg)?;
+        match &handle_response.messages[..] {
+            [CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr, msg, ..
+            })] => {
+                assert_eq!(contract_addr, &HumanAddr("vault".to_string()));
+                let msg = String::from_utf8(msg.0.clone()).unwrap();
+                assert!(msg.starts_with(r#"{"receive_calculation":{"sender":"bob","n":null"#));
+            }
+            messages => panic!("Unexpected messages: {:?}", messages),
         }
 
-        // Now try to hack into bob's account using the wrong key - and fail
-        query_history_wrong_vk(deps);
+        let msg = HandleMsg::Calculate {
+            op: Operation::Sqrt(Uint128(16).into()),
+            callback: None,
+        };
+        let handle_response = handle(&mut deps, mock_env("bob", &[]), msg)?;
+        assert!(handle_response.messages.is_empty());
         Ok(())
     }
 
     #[test]
-    fn test_sub() -> StdResult<()> {
+    fn test_admin_and_contract_status() -> StdResult<()> {
         // Initialize the contract
         let (init_result, mut deps) = init_helper();
         assert!(
@@ -377,112 +3808,819 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        // Perform an Add operation
-        let env = mock_env("bob", &coins(2, "token"));
-        let n1: u128 = 20;
-        let n2: u128 = 5;
-        let msg = HandleMsg::Sub((Uint128::from(n1), Uint128::from(n2)));
-        let _res = handle(&mut deps, env, msg).unwrap();
+        let contract_status = |deps: &Extern<MockStorage, MockApi, MockQuerier>| match from_binary(
+            &query(deps, QueryMsg::ContractStatus {}).unwrap(),
+        )
+        .unwrap()
+        {
+            QueryAnswer::ContractStatus(ContractStatus { status }) => status,
+            _ => panic!("Unexpected query answer"),
+        };
+        assert_eq!(contract_status(&deps), ContractStatusLevel::Normal);
 
-        // Query the user's transactions history using their viewing key
-        let history = query_transactions_history(&mut deps)?;
+        let stop_calculations = HandleMsg::SetContractStatus {
+            level: ContractStatusLevel::StopCalculations,
+            padding: None,
+        };
+        let handle_result = handle(&mut deps, mock_env("bob", &[]), stop_calculations.clone());
+        assert!(handle_result.is_err());
+        handle(&mut deps, mock_env("instantiator", &[]), stop_calculations)?;
+        assert_eq!(
+            contract_status(&deps),
+            ContractStatusLevel::StopCalculations
+        );
 
-        // Verify the transactions history
-        for i in history {
-            assert_eq!("20 - 5 = 15".to_string(), i);
-        }
+        let add = HandleMsg::Add {
+            operands: (Uint128(1).into(), Uint128(2).into()),
+            overflow_mode: None,
+        };
+        let handle_result = handle(&mut deps, mock_env("bob", &[]), add.clone());
+        assert_eq!(
+            handle_result.unwrap_err(),
+            StdError::generic_err("The contract is stopped, and this action is not allowed")
+        );
+        let query_result = query(
+            &deps,
+            QueryMsg::Compute {
+                op: Operation::Sqrt(Uint128(4).into()),
+            },
+        );
+        assert!(query_result.is_err());
+        create_viewing_key(&mut deps);
 
-        // Now try to hack into bob's account using the wrong key - and fail
-        query_history_wrong_vk(deps);
+        // The admin can hand over the contract, even when all of it is stopped
+        let stop_all = HandleMsg::SetContractStatus {
+            level: ContractStatusLevel::StopAll,
+            padding: None,
+        };
+        handle(&mut deps, mock_env("instantiator", &[]), stop_all)?;
+        let msg = HandleMsg::CreateViewingKey {
+            entropy: "entropy".to_string(),
+            label: None,
+            expires_at_height: None,
+            expires_at_time: None,
+            scopes: None,
+            padding: None,
+        };
+        assert!(handle(&mut deps, mock_env("bob", &[]), msg).is_err());
+        let msg = HandleMsg::ChangeAdmin {
+            address: HumanAddr("a".to_string()),
+            padding: None,
+        };
+        assert!(handle(&mut deps, mock_env("instantiator", &[]), msg).is_err());
+        let msg = HandleMsg::ChangeAdmin {
+            address: HumanAddr("alice".to_string()),
+            padding: None,
+        };
+        handle(&mut deps, mock_env("instantiator", &[]), msg)?;
+
+        let resume = HandleMsg::SetContractStatus {
+            level: ContractStatusLevel::Normal,
+            padding: None,
+        };
+        let handle_result = handle(&mut deps, mock_env("instantiator", &[]), resume.clone());
+        assert!(handle_result.is_err());
+        handle(&mut deps, mock_env("alice", &[]), resume)?;
+        handle(&mut deps, mock_env("bob", &[]), add)?;
+        assert_eq!(contract_status(&deps), ContractStatusLevel::Normal);
+
+        // The contract can't be initialized with an invalid admin either
+        let mut deps = mock_dependencies(20, &[]);
+        let init_msg = InitMsg {
+            prng_seed: String::from("waehfjklasd"),
+            decimal_places: None,
+            admin: Some(HumanAddr(
+                "an address much too long to be valid".to_string(),
+            )),
+        };
+        assert!(init(&mut deps, mock_env("instantiator", &[]), init_msg).is_err());
         Ok(())
     }
 
     #[test]
-    fn test_mul() -> StdResult<()> {
-        // Initialize the contract
+    fn test_error_codes() -> StdResult<()> {
         let (init_result, mut deps) = init_helper();
-        assert!(
-            init_result.is_ok(),
-            "Init failed: {}",
-            init_result.err().unwrap()
-        );
+        assert!(init_result.is_ok());
 
-        // Perform an Add operation
-        let env = mock_env("bob", &coins(2, "token"));
-        let n1: u128 = 20;
-        let n2: u128 = 5;
-        let msg = HandleMsg::Mul((Uint128::from(n1), Uint128::from(n2)));
-        let _res = handle(&mut deps, env, msg).unwrap();
+        // Operands that can't be resolved fail like any other invalid input
+        let msg = HandleMsg::Add {
+            operands: (Operand::Previous, Uint128(5).into()),
+            overflow_mode: None,
+        };
+        let handle_response = handle(&mut deps, mock_env("bob", &[]), msg)?;
+        match from_binary(&handle_response.data.unwrap())? {
+            HandleAnswer::CalculationResult {
+                status, error_code, ..
+            } => {
+                assert_eq!(status, "Invalid input: There is no previous result");
+                assert_eq!(error_code, Some(ErrorCode::InvalidInput));
+            }
+            answer => panic!("Unexpected answer: {:?}", answer),
+        }
 
-        // Query the user's transactions history using their viewing key
-        let history = query_transactions_history(&mut deps)?;
+        let mut error_code = |msg: HandleMsg| -> StdResult<Option<ErrorCode>> {
+            let handle_response = handle(&mut deps, mock_env("bob", &[]), msg)?;
+            Ok(match from_binary(&handle_response.data.unwrap())? {
+                HandleAnswer::CalculationResult { error_code, .. }
+                | HandleAnswer::SignedCalculationResult { error_code, .. }
+                | HandleAnswer::DecimalCalculationResult { error_code, .. }
+                | HandleAnswer::BigCalculationResult { error_code, .. }
+                | HandleAnswer::NthRootResult { error_code, .. }
+                | HandleAnswer::IsPrimeResult { error_code, .. }
+                | HandleAnswer::FactorizeResult { error_code, .. }
+                | HandleAnswer::StatsResult { error_code, .. }
+                | HandleAnswer::MemoryResult { error_code, .. } => error_code,
+                answer => panic!("Unexpected answer: {:?}", answer),
+            })
+        };
 
-        // Verify the transactions history
-        for i in history {
-            assert_eq!("20 * 5 = 100".to_string(), i);
+        let msg = HandleMsg::Add {
+            operands: (Uint128(1).into(), Uint128(2).into()),
+            overflow_mode: None,
+        };
+        assert_eq!(error_code(msg)?, None);
+        let msg = HandleMsg::Sub {
+            operands: (Uint128(1).into(), Uint128(2).into()),
+            overflow_mode: None,
+        };
+        assert_eq!(error_code(msg)?, Some(ErrorCode::Underflow));
+        let msg = HandleMsg::Mul {
+            operands: (Uint128(u128::MAX).into(), Uint128(2).into()),
+            overflow_mode: None,
+        };
+        assert_eq!(error_code(msg)?, Some(ErrorCode::Overflow));
+        let msg = HandleMsg::Div((Uint128(1).into(), Uint128(0).into()));
+        assert_eq!(error_code(msg)?, Some(ErrorCode::DivideByZero));
+        let msg = HandleMsg::Log2(Uint128(0).into());
+        assert_eq!(error_code(msg)?, Some(ErrorCode::InvalidInput));
+        let msg = HandleMsg::Sqrt(Operand::Register("empty".to_string()));
+        assert_eq!(error_code(msg)?, Some(ErrorCode::InvalidInput));
+        let msg = HandleMsg::SignedSub {
+            operands: (Int128(i128::MIN), Int128(1)),
+            overflow_mode: None,
+        };
+        assert_eq!(error_code(msg)?, Some(ErrorCode::Underflow));
+        let msg = HandleMsg::BigMod((Uint512::from(Uint128(1)), Uint512::zero()));
+        assert_eq!(error_code(msg)?, Some(ErrorCode::DivideByZero));
+        let msg = HandleMsg::Evaluate {
+            expression: "2 +".to_string(),
+            overflow_mode: None,
+        };
+        assert_eq!(error_code(msg)?, Some(ErrorCode::InvalidExpression));
+
+        let msg = HandleMsg::NthRoot {
+            value: Uint128(8).into(),
+            n: 0,
+        };
+        assert_eq!(error_code(msg)?, Some(ErrorCode::InvalidInput));
+        let msg = HandleMsg::NthRoot {
+            value: Uint128(8).into(),
+            n: 3,
+        };
+        assert_eq!(error_code(msg)?, None);
+        let msg = HandleMsg::IsPrime(Operand::HistoryIndex(1000));
+        assert_eq!(error_code(msg)?, Some(ErrorCode::InvalidInput));
+        let msg = HandleMsg::Factorize(Operand::Register("empty".to_string()));
+        assert_eq!(error_code(msg)?, Some(ErrorCode::InvalidInput));
+        let msg = HandleMsg::Stats {
+            values: vec![],
+            decimal_places: None,
+            rounding: None,
+            overflow_mode: None,
+        };
+        assert_eq!(error_code(msg)?, Some(ErrorCode::InvalidInput));
+        let msg = HandleMsg::Stats {
+            values: vec![Uint128(u128::MAX), Uint128(1)],
+            decimal_places: None,
+            rounding: None,
+            overflow_mode: None,
+        };
+        assert_eq!(error_code(msg)?, Some(ErrorCode::Overflow));
+        let msg = HandleMsg::MemoryStore {
+            register: String::new(),
+            value: Uint128(1).into(),
+        };
+        assert_eq!(error_code(msg)?, Some(ErrorCode::InvalidInput));
+        let msg = HandleMsg::MemoryStore {
+            register: "x".to_string(),
+            value: Uint128(u128::MAX).into(),
+        };
+        assert_eq!(error_code(msg)?, None);
+        let msg = HandleMsg::MemoryAdd {
+            register: "x".to_string(),
+            value: Uint128(1).into(),
+        };
+        assert_eq!(error_code(msg)?, Some(ErrorCode::Overflow));
+
+        let query_result = query(
+            &deps,
+            QueryMsg::Compute {
+                op: Operation::Div((Uint128(1).into(), Uint128(0).into())),
+            },
+        )?;
+        match from_binary(&query_result)? {
+            QueryAnswer::Compute(Compute { error_code, .. }) => {
+                assert_eq!(error_code, Some(ErrorCode::DivideByZero))
+            }
+            answer => panic!("Unexpected answer: {:?}", answer),
         }
+        Ok(())
+    }
 
-        // Now try to hack into bob's account using the wrong key - and fail
-        query_history_wrong_vk(deps);
+    #[test]
+    fn test_migrate() -> StdResult<()> {
+        use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
+        use secret_toolkit::storage::{AppendStore, AppendStoreMut};
+
+        // The storage of version 1 of the contract
+        let mut deps = mock_dependencies(20, &[]);
+        let legacy_state = LegacyState {
+            prng_seed: vec![1, 2, 3],
+        };
+        save(&mut deps.storage, CONFIG_KEY, &legacy_state)?;
+        let bob = deps.api.canonical_address(&HumanAddr("bob".to_string()))?;
+        {
+            let mut storage =
+                PrefixedStorage::multilevel(&[b"calculations", bob.as_slice()], &mut deps.storage);
+            let mut store = AppendStoreMut::attach_or_create(&mut storage)?;
+            store.push(&"3 + 5 = 8".to_string())?;
+            store.push(&"√16 = 4".to_string())?;
+        }
+
+        let migrate_msg = MigrateMsg {
+            decimal_places: None,
+            admin: None,
+        };
+        migrate(
+            &mut deps,
+            mock_env("instantiator", &[]),
+            migrate_msg.clone(),
+        )?;
+        let config: State = load(&deps.storage, CONFIG_KEY)?;
+        assert_eq!(config.version, CONTRACT_VERSION);
+        assert_eq!(config.prng_seed, vec![1, 2, 3]);
+        assert_eq!(config.admin, HumanAddr("instantiator".to_string()));
+
+        // The history of version 1 is readable before it is migrated
+        let vk = create_viewing_key(&mut deps);
+        let get_history = |deps: &Extern<MockStorage, MockApi, MockQuerier>| {
+            let query_response = query(
+                deps,
+                QueryMsg::GetHistory {
+                    address: HumanAddr("bob".to_string()),
+                    key: vk.0.clone(),
+                    page: None,
+                    page_size: 10,
+                    include_failures: None,
+                    cursor: None,
+                },
+            )?;
+            let history: GetHistory = from_binary(&query_response)?;
+            Ok::<_, StdError>(history.history)
+        };
+        assert_eq!(get_history(&deps)?, vec!["√16 = 4", "3 + 5 = 8"]);
+
+        // and it is migrated, in order, before the next calculation is recorded
+        let msg = HandleMsg::Add {
+            operands: (Operand::Previous, Uint128(1).into()),
+            overflow_mode: None,
+        };
+        let handle_response = handle(&mut deps, mock_env("bob", &[]), msg)?;
+        let answer: HandleAnswer = from_binary(&handle_response.data.unwrap())?;
+        assert!(matches!(
+            answer,
+            HandleAnswer::CalculationResult {
+                n: Some(Uint128(5)),
+                ..
+            }
+        ));
+        assert_eq!(
+            get_history(&deps)?,
+            vec!["4 + 1 = 5", "√16 = 4", "3 + 5 = 8"]
+        );
+        let storage =
+            ReadonlyPrefixedStorage::multilevel(&[b"calculations", bob.as_slice()], &deps.storage);
+        assert!(AppendStore::<String, _>::attach(&storage).is_none());
+
+        // Migrating again is harmless, but a newer version can't be migrated to this one
+        migrate(
+            &mut deps,
+            mock_env("instantiator", &[]),
+            migrate_msg.clone(),
+        )?;
+        let config = State {
+            version: CONTRACT_VERSION + 1,
+            ..load(&deps.storage, CONFIG_KEY)?
+        };
+        save(&mut deps.storage, CONFIG_KEY, &config)?;
+        assert!(migrate(&mut deps, mock_env("instantiator", &[]), migrate_msg).is_err());
+
+        let record = CalculationRecord::from_legacy("12 / 4 = 3");
+        assert_eq!(record.op, CalculationOp::Div);
+        assert_eq!(record.to_display_string(), "12 / 4 = 3");
         Ok(())
     }
 
     #[test]
-    fn test_div() -> StdResult<()> {
-        // Initialize the contract
+    fn test_migrate_long_history() -> StdResult<()> {
+        use crate::state::LEGACY_MIGRATION_CHUNK_SIZE;
+        use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
+        use secret_toolkit::storage::AppendStoreMut;
+
         let (init_result, mut deps) = init_helper();
-        assert!(
-            init_result.is_ok(),
-            "Init failed: {}",
-            init_result.err().unwrap()
+        assert!(init_result.is_ok());
+        let vk = create_viewing_key(&mut deps);
+        let bob = deps.api.canonical_address(&HumanAddr("bob".to_string()))?;
+        let legacy_len = 2 * LEGACY_MIGRATION_CHUNK_SIZE + 5;
+        {
+            let mut storage =
+                PrefixedStorage::multilevel(&[b"calculations", bob.as_slice()], &mut deps.storage);
+            let mut store = AppendStoreMut::attach_or_create(&mut storage)?;
+            for n in 0..legacy_len {
+                store.push(&format!("{} + 1 = {}", n, n + 1))?;
+            }
+        }
+        let get_history = |deps: &Extern<MockStorage, MockApi, MockQuerier>| {
+            let query_response = query(
+                deps,
+                QueryMsg::GetHistory {
+                    address: HumanAddr("bob".to_string()),
+                    key: vk.0.clone(),
+                    page: None,
+                    page_size: 100,
+                    include_failures: None,
+                    cursor: None,
+                },
+            )?;
+            let history: GetHistory = from_binary(&query_response)?;
+            Ok::<_, StdError>(history.history)
+        };
+        let legacy_entries = |deps: &Extern<MockStorage, MockApi, MockQuerier>| {
+            let storage = ReadonlyPrefixedStorage::multilevel(
+                &[b"calculations", bob.as_slice()],
+                &deps.storage,
+            );
+            (0..legacy_len)
+                .filter(|position: &u32| storage.get(&position.to_be_bytes()).is_some())
+                .count() as u32
+        };
+
+        // Each calculation migrates one chunk of the legacy history, and the history reads the
+        // same, in order, while it is partially migrated
+        let mut expected: Vec<String> = (0..legacy_len)
+            .rev()
+            .map(|n| format!("{} + 1 = {}", n, n + 1))
+            .collect();
+        for step in 1..=3 {
+            let msg = HandleMsg::Mul {
+                operands: (Uint128(step).into(), Uint128(2).into()),
+                overflow_mode: None,
+            };
+            handle(&mut deps, mock_env("bob", &[]), msg)?;
+            expected.insert(0, format!("{} * 2 = {}", step, step * 2));
+            assert_eq!(get_history(&deps)?, expected);
+            let migrated = (step as u32 * LEGACY_MIGRATION_CHUNK_SIZE).min(legacy_len);
+            assert_eq!(legacy_entries(&deps), legacy_len - migrated);
+        }
+        let storage =
+            ReadonlyPrefixedStorage::multilevel(&[b"calculations", bob.as_slice()], &deps.storage);
+        assert!(storage.get(b"len").is_none());
+
+        // Deleting entries while the migration is in progress moves the entries after them, in
+        // whichever store they are
+        let long_len = 4 * LEGACY_MIGRATION_CHUNK_SIZE + 5;
+        let (init_result, mut deps) = init_helper();
+        assert!(init_result.is_ok());
+        let vk = create_viewing_key(&mut deps);
+        {
+            let mut storage =
+                PrefixedStorage::multilevel(&[b"calculations", bob.as_slice()], &mut deps.storage);
+            let mut store = AppendStoreMut::attach_or_create(&mut storage)?;
+            for n in 0..long_len {
+                store.push(&format!("{} + 1 = {}", n, n + 1))?;
+            }
+        }
+        let get_history = |deps: &Extern<MockStorage, MockApi, MockQuerier>| {
+            let query_response = query(
+                deps,
+                QueryMsg::GetHistory {
+                    address: HumanAddr("bob".to_string()),
+                    key: vk.0.clone(),
+                    page: None,
+                    page_size: 100,
+                    include_failures: None,
+                    cursor: None,
+                },
+            )?;
+            let history: GetHistory = from_binary(&query_response)?;
+            Ok::<_, StdError>(history.history)
+        };
+        let mut expected: Vec<String> = (0..long_len)
+            .rev()
+            .map(|n| format!("{} + 1 = {}", n, n + 1))
+            .collect();
+        let msg = HandleMsg::Mul {
+            operands: (Uint128(1).into(), Uint128(2).into()),
+            overflow_mode: None,
+        };
+        handle(&mut deps, mock_env("bob", &[]), msg)?;
+        expected.insert(0, "1 * 2 = 2".to_string());
+
+        // A migrated entry, and then one that isn't migrated yet. Each deletion migrates a chunk
+        for index in [1, long_len - 3] {
+            let msg = HandleMsg::DeleteHistoryEntry { index };
+            handle(&mut deps, mock_env("bob", &[]), msg)?;
+            expected.remove(expected.len() - 1 - index as usize);
+            assert_eq!(get_history(&deps)?, expected);
+        }
+        let storage =
+            ReadonlyPrefixedStorage::multilevel(&[b"calculations", bob.as_slice()], &deps.storage);
+        assert_eq!(
+            storage.get(b"len"),
+            Some((long_len - 2).to_be_bytes().to_vec())
         );
 
-        // Perform an Add operation
-        let env = mock_env("bob", &coins(2, "token"));
-        let n1: u128 = 20;
-        let n2: u128 = 5;
-        let msg = HandleMsg::Div((Uint128::from(n1), Uint128::from(n2)));
-        let _res = handle(&mut deps, env, msg).unwrap();
+        // The migration then carries on where it was
+        for step in 2..=3 {
+            let msg = HandleMsg::Mul {
+                operands: (Uint128(step).into(), Uint128(2).into()),
+                overflow_mode: None,
+            };
+            handle(&mut deps, mock_env("bob", &[]), msg)?;
+            expected.insert(0, format!("{} * 2 = {}", step, step * 2));
+            assert_eq!(get_history(&deps)?, expected);
+        }
+        let storage =
+            ReadonlyPrefixedStorage::multilevel(&[b"calculations", bob.as_slice()], &deps.storage);
+        assert!(storage.get(b"len").is_none());
+        Ok(())
+    }
 
-        // Query the user's transactions history using their viewing key
-        let history = query_transactions_history(&mut deps)?;
+    #[test]
+    fn test_history_retention() -> StdResult<()> {
+        use cosmwasm_storage::ReadonlyPrefixedStorage;
 
-        // Verify the transactions history
-        for i in history {
-            assert_eq!("20 / 5 = 4".to_string(), i);
+        let (init_result, mut deps) = init_helper();
+        assert!(init_result.is_ok());
+        let vk = create_viewing_key(&mut deps);
+        let get_history = |deps: &Extern<MockStorage, MockApi, MockQuerier>| {
+            let query_response = query(
+                deps,
+                QueryMsg::GetHistory {
+                    address: HumanAddr("bob".to_string()),
+                    key: vk.0.clone(),
+                    page: None,
+                    page_size: 10,
+                    include_failures: None,
+                    cursor: None,
+                },
+            )?;
+            let history: GetHistory = from_binary(&query_response)?;
+            Ok::<_, StdError>(history.history)
+        };
+        let add = |n1: Operand, n2: u128| HandleMsg::Add {
+            operands: (n1, Uint128(n2).into()),
+            overflow_mode: None,
+        };
+
+        for n in 1..=4 {
+            handle(&mut deps, mock_env("bob", &[]), add(Uint128(1).into(), n))?;
         }
+        let msg = HandleMsg::DeleteHistoryEntry { index: 1 };
+        handle(&mut deps, mock_env("bob", &[]), msg)?;
+        assert_eq!(
+            get_history(&deps)?,
+            vec!["1 + 4 = 5", "1 + 3 = 4", "1 + 1 = 2"]
+        );
+        let msg = HandleMsg::DeleteHistoryEntry { index: 3 };
+        assert!(handle(&mut deps, mock_env("bob", &[]), msg).is_err());
 
-        // Now try to hack into bob's account using the wrong key - and fail
-        query_history_wrong_vk(deps);
+        // Limiting the history drops its oldest entries, and then the oldest entry on each append
+        let msg = HandleMsg::SetRetention {
+            max_entries: Some(0),
+        };
+        assert!(handle(&mut deps, mock_env("bob", &[]), msg).is_err());
+        let msg = HandleMsg::SetRetention {
+            max_entries: Some(2),
+        };
+        handle(&mut deps, mock_env("bob", &[]), msg)?;
+        assert_eq!(get_history(&deps)?, vec!["1 + 4 = 5", "1 + 3 = 4"]);
+        handle(&mut deps, mock_env("bob", &[]), add(Uint128(1).into(), 5))?;
+        handle(&mut deps, mock_env("bob", &[]), add(Operand::Previous, 10))?;
+        handle(
+            &mut deps,
+            mock_env("bob", &[]),
+            add(Operand::HistoryIndex(0), 1),
+        )?;
+        assert_eq!(get_history(&deps)?, vec!["6 + 1 = 7", "6 + 10 = 16"]);
+
+        let bob = deps.api.canonical_address(&HumanAddr("bob".to_string()))?;
+        let records = ReadonlyPrefixedStorage::multilevel(
+            &[b"calculation_records", bob.as_slice()],
+            &deps.storage,
+        );
+        // Dropped entries are only cut off by the length of the store
+        assert_eq!(records.get(b"len"), Some(2u32.to_be_bytes().to_vec()));
+
+        // Removing the limit lets the history grow again, in order
+        let msg = HandleMsg::SetRetention { max_entries: None };
+        handle(&mut deps, mock_env("bob", &[]), msg)?;
+        handle(&mut deps, mock_env("bob", &[]), add(Uint128(1).into(), 7))?;
+        assert_eq!(
+            get_history(&deps)?,
+            vec!["1 + 7 = 8", "6 + 1 = 7", "6 + 10 = 16"]
+        );
+
+        handle(&mut deps, mock_env("bob", &[]), HandleMsg::ClearHistory {})?;
+        assert!(get_history(&deps)?.is_empty());
+        let records = ReadonlyPrefixedStorage::multilevel(
+            &[b"calculation_records", bob.as_slice()],
+            &deps.storage,
+        );
+        assert_eq!(records.get(b"len"), Some(0u32.to_be_bytes().to_vec()));
+        let handle_response = handle(&mut deps, mock_env("bob", &[]), add(Operand::Previous, 1))?;
+        let answer: HandleAnswer = from_binary(&handle_response.data.unwrap())?;
+        assert!(matches!(
+            answer,
+            HandleAnswer::CalculationResult { n: None, .. }
+        ));
         Ok(())
     }
 
     #[test]
-    fn test_sqrt() -> StdResult<()> {
-        // Initialize the contract
+    fn test_viewing_key_expiry() -> StdResult<()> {
         let (init_result, mut deps) = init_helper();
-        assert!(
-            init_result.is_ok(),
-            "Init failed: {}",
-            init_result.err().unwrap()
+        assert!(init_result.is_ok());
+        let env_at = |height: u64| {
+            let mut env = mock_env("bob", &[]);
+            env.block.height = height;
+            env
+        };
+        let create_key = |label: Option<&str>, expires_at_height| HandleMsg::CreateViewingKey {
+            entropy: "entropy".to_string(),
+            label: label.map(String::from),
+            expires_at_height,
+            expires_at_time: None,
+            scopes: None,
+            padding: None,
+        };
+        let query_registers = |deps: &Extern<MockStorage, MockApi, MockQuerier>, key: &str| {
+            query(
+                deps,
+                QueryMsg::GetRegisters {
+                    address: HumanAddr("bob".to_string()),
+                    key: key.to_string(),
+                },
+            )
+        };
+
+        let handle_result = handle(&mut deps, env_at(100), create_key(None, Some(100)));
+        assert!(handle_result.is_err());
+        let handle_response = handle(
+            &mut deps,
+            env_at(100),
+            create_key(Some("auditor"), Some(110)),
+        )?;
+        let key = match from_binary(&handle_response.data.unwrap())? {
+            HandleAnswer::CreateViewingKey { key } => key,
+            answer => panic!("Unexpected answer: {:?}", answer),
+        };
+        let bob = deps.api.canonical_address(&HumanAddr("bob".to_string()))?;
+        let info = ViewingKey::read_viewing_key_info(&deps.storage, &bob)?.unwrap();
+        assert_eq!(info.label, Some("auditor".to_string()));
+        assert_eq!(info.created_at_height, 100);
+        assert!(query_registers(&deps, &key.0).is_ok());
+
+        // The key expires once the contract handles a message other than a calculation at its
+        // expiry height
+        let msg = HandleMsg::SetRecordFailures { enabled: true };
+        handle(&mut deps, env_at(109), msg.clone())?;
+        assert!(query_registers(&deps, &key.0).is_ok());
+        let calculation = HandleMsg::Sqrt(Uint128(4).into());
+        handle(&mut deps, env_at(115), calculation)?;
+        assert!(query_registers(&deps, &key.0).is_ok());
+        handle(&mut deps, env_at(115), msg)?;
+        assert_eq!(
+            query_registers(&deps, &key.0).unwrap_err(),
+            StdError::generic_err("The viewing key has expired")
         );
 
-        // Perform an Add operation
-        let env = mock_env("bob", &coins(2, "token"));
-        let n: u128 = 121;
-        let msg = HandleMsg::Sqrt(Uint128::from(n));
-        let _res = handle(&mut deps, env, msg).unwrap();
+        // A new key replaces it, until it is revoked
+        let msg = HandleMsg::SetViewingKey {
+            key: "new key".to_string(),
+            label: None,
+            expires_at_height: None,
+            expires_at_time: None,
+            scopes: None,
+            padding: None,
+        };
+        handle(&mut deps, env_at(111), msg)?;
+        assert!(query_registers(&deps, "new key").is_ok());
+        let msg = HandleMsg::RevokeViewingKey {
+            label: None,
+            padding: None,
+        };
+        handle(&mut deps, env_at(112), msg)?;
+        assert!(query_registers(&deps, "new key").is_err());
+        assert_eq!(
+            ViewingKey::read_viewing_key_info(&deps.storage, &bob)?,
+            None
+        );
+        Ok(())
+    }
 
-        // Query the user's transactions history using their viewing key
-        let history = query_transactions_history(&mut deps)?;
+    #[test]
+    fn test_scoped_viewing_keys() -> StdResult<()> {
+        let (init_result, mut deps) = init_helper();
+        assert!(init_result.is_ok());
+        let vk = create_viewing_key(&mut deps);
+        let set_key = |key: &str, label: Option<&str>, scopes| HandleMsg::SetViewingKey {
+            key: key.to_string(),
+            label: label.map(String::from),
+            expires_at_height: None,
+            expires_at_time: None,
+            scopes,
+            padding: None,
+        };
+        let bob = || HumanAddr("bob".to_string());
+        let get_counts = |deps: &Extern<MockStorage, MockApi, MockQuerier>, key: &str| {
+            let query_response = query(
+                deps,
+                QueryMsg::GetHistoryCounts {
+                    address: bob(),
+                    key: key.to_string(),
+                },
+            )?;
+            match from_binary(&query_response)? {
+                QueryAnswer::GetHistoryCounts(counts) => Ok::<_, StdError>(counts),
+                answer => panic!("Unexpected answer: {:?}", answer),
+            }
+        };
+        let get_history = |deps: &Extern<MockStorage, MockApi, MockQuerier>, key: &str| {
+            query(
+                deps,
+                QueryMsg::GetHistory {
+                    address: bob(),
+                    key: key.to_string(),
+                    page: None,
+                    page_size: 10,
+                    include_failures: None,
+                    cursor: None,
+                },
+            )
+        };
 
-        // Verify the transactions history
-        for i in history {
-            assert_eq!("√121 = 11".to_string(), i);
+        let msg = HandleMsg::SetRecordFailures { enabled: true };
+        handle(&mut deps, mock_env("bob", &[]), msg)?;
+        for msg in [
+            HandleMsg::Add {
+                operands: (Uint128(1).into(), Uint128(2).into()),
+                overflow_mode: None,
+            },
+            HandleMsg::Add {
+                operands: (Uint128(3).into(), Uint128(4).into()),
+                overflow_mode: None,
+            },
+            HandleMsg::Sub {
+                operands: (Uint128(1).into(), Uint128(2).into()),
+                overflow_mode: None,
+            },
+        ] {
+            handle(&mut deps, mock_env("bob", &[]), msg)?;
         }
 
-        // Now try to hack into bob's account using the wrong key - and fail
-        query_history_wrong_vk(deps);
+        // Scoped keys need a label and at least one scope
+        let msg = set_key("accountant", None, Some(vec![ViewingKeyScope::Counts]));
+        assert!(handle(&mut deps, mock_env("bob", &[]), msg).is_err());
+        let msg = set_key("accountant", Some("accountant"), Some(vec![]));
+        assert!(handle(&mut deps, mock_env("bob", &[]), msg).is_err());
+
+        let msg = set_key(
+            "accountant",
+            Some("accountant"),
+            Some(vec![ViewingKeyScope::Counts]),
+        );
+        handle(&mut deps, mock_env("bob", &[]), msg)?;
+        let msg = set_key(
+            "auditor",
+            Some("auditor"),
+            Some(vec![ViewingKeyScope::History]),
+        );
+        handle(&mut deps, mock_env("bob", &[]), msg)?;
+
+        // The main key is still valid for every query, and scoped keys only for their scopes
+        let counts = get_counts(&deps, &vk.0)?;
+        assert_eq!(counts.total, 3);
+        assert_eq!(counts.failures, 1);
+        assert_eq!(
+            counts.operations,
+            vec![
+                OperationCount {
+                    op: "add".to_string(),
+                    count: 2
+                },
+                OperationCount {
+                    op: "sub".to_string(),
+                    count: 1
+                },
+            ]
+        );
+        assert_eq!(get_counts(&deps, "accountant")?, counts);
+        assert_eq!(
+            get_history(&deps, "accountant").unwrap_err(),
+            StdError::generic_err("This viewing key can't be used for this query")
+        );
+        assert!(get_history(&deps, "auditor").is_ok());
+        assert_eq!(get_counts(&deps, "auditor")?, counts);
+        let query_response = query(
+            &deps,
+            QueryMsg::GetRegisters {
+                address: bob(),
+                key: "auditor".to_string(),
+            },
+        );
+        assert!(query_response.is_err());
+
+        // Setting a scoped key with the same label replaces it, and revoking it leaves the others
+        let msg = set_key(
+            "new accountant",
+            Some("accountant"),
+            Some(vec![ViewingKeyScope::Counts]),
+        );
+        handle(&mut deps, mock_env("bob", &[]), msg)?;
+        assert!(get_counts(&deps, "accountant").is_err());
+        assert!(get_counts(&deps, "new accountant").is_ok());
+        let msg = HandleMsg::RevokeViewingKey {
+            label: Some("accountant".to_string()),
+            padding: None,
+        };
+        handle(&mut deps, mock_env("bob", &[]), msg.clone())?;
+        assert!(handle(&mut deps, mock_env("bob", &[]), msg).is_err());
+        assert!(get_counts(&deps, "new accountant").is_err());
+        assert!(get_counts(&deps, "auditor").is_ok());
+        assert!(get_counts(&deps, &vk.0).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_legacy_history_reads() -> StdResult<()> {
+        use cosmwasm_storage::PrefixedStorage;
+        use secret_toolkit::storage::AppendStoreMut;
+
+        let (init_result, mut deps) = init_helper();
+        assert!(init_result.is_ok());
+        let vk = create_viewing_key(&mut deps);
+        let bob = deps.api.canonical_address(&HumanAddr("bob".to_string()))?;
+        {
+            let mut storage =
+                PrefixedStorage::multilevel(&[b"calculations", bob.as_slice()], &mut deps.storage);
+            let mut store = AppendStoreMut::attach_or_create(&mut storage)?;
+            store.push(&"3 + 5 = 8".to_string())?;
+            store.push(&"10 - 4 = 6".to_string())?;
+        }
+
+        // Every query reads the history of version 1 of the contract, as it is
+        let query_response = query(
+            &deps,
+            QueryMsg::GetCalculationRecords {
+                address: HumanAddr("bob".to_string()),
+                key: vk.0.clone(),
+                page: None,
+                page_size: 10,
+                include_failures: None,
+                cursor: None,
+            },
+        )?;
+        let records: GetCalculationRecords = from_binary(&query_response)?;
+        assert_eq!(records.total, 2);
+        assert_eq!(records.records[0].op, CalculationOp::Sub);
+        assert_eq!(records.records[0].display, Some("10 - 4 = 6".to_string()));
+        let query_response = query(
+            &deps,
+            QueryMsg::GetHistoryCounts {
+                address: HumanAddr("bob".to_string()),
+                key: vk.0,
+            },
+        )?;
+        match from_binary(&query_response)? {
+            QueryAnswer::GetHistoryCounts(counts) => assert_eq!(counts.total, 2),
+            answer => panic!("Unexpected answer: {:?}", answer),
+        }
+
+        // and so do operands referring to it
+        let msg = HandleMsg::Add {
+            operands: (Operand::HistoryIndex(0), Operand::Previous),
+            overflow_mode: None,
+        };
+        let handle_response = handle(&mut deps, mock_env("bob", &[]), msg)?;
+        let answer: HandleAnswer = from_binary(&handle_response.data.unwrap())?;
+        assert!(matches!(
+            answer,
+            HandleAnswer::CalculationResult {
+                n: Some(Uint128(14)),
+                ..
+            }
+        ));
         Ok(())
     }
 }
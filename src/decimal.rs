@@ -0,0 +1,497 @@
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::{de, ser, Deserialize, Deserializer, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Sub;
+use std::str::FromStr;
+
+use cosmwasm_std::StdError;
+
+use crate::big_uint::{U256, U512};
+use crate::calculations_utils::RESULT_TOO_LARGE;
+use crate::error::{CalculatorResult, InvalidInput, Overflow};
+use crate::overflow::OverflowMode;
+use snafu::{ensure, OptionExt};
+
+/// The largest number of fractional digits a Decimal can hold
+pub const MAX_DECIMAL_PLACES: u32 = 18;
+
+/// An unsigned fixed-point decimal number, serialized as a decimal string (e.g. "33.33").
+///
+/// The value is `atomics / 10^decimal_places`. Trailing fractional zeros are always stripped, so
+/// two Decimals are equal exactly when the numbers they represent are.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct Decimal {
+    atomics: u128,
+    decimal_places: u32,
+}
+
+impl Decimal {
+    pub fn new(atomics: u128, decimal_places: u32) -> Self {
+        let mut decimal = Decimal {
+            atomics,
+            decimal_places,
+        };
+        while decimal.decimal_places > 0 && decimal.atomics % 10 == 0 {
+            decimal.atomics /= 10;
+            decimal.decimal_places -= 1;
+        }
+        decimal
+    }
+
+    pub const fn zero() -> Self {
+        Decimal {
+            atomics: 0,
+            decimal_places: 0,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.atomics == 0
+    }
+
+    pub fn atomics(&self) -> u128 {
+        self.atomics
+    }
+
+    pub fn decimal_places(&self) -> u32 {
+        self.decimal_places
+    }
+
+    /// Returns the value multiplied by `10^decimal_places`, which must not be smaller than the
+    /// Decimal's own number of decimal places.
+    pub(crate) fn scaled_to(&self, decimal_places: u32) -> U256 {
+        U256::from(self.atomics) * U256::exp10((decimal_places - self.decimal_places) as usize)
+    }
+
+    /// Builds a Decimal with `decimal_places` fractional digits from `value / 10^value_places`,
+    /// rounding off the digits that don't fit.
+    pub(crate) fn from_scaled(
+        value: U256,
+        value_places: u32,
+        decimal_places: u32,
+        rounding: RoundingMode,
+    ) -> CalculatorResult<Self> {
+        Decimal::from_scaled_overflowing(
+            value,
+            value_places,
+            decimal_places,
+            rounding,
+            OverflowMode::Checked,
+        )
+    }
+
+    /// Like `from_scaled`, but atomics that don't fit in 128 bits saturate to u128::MAX or wrap
+    /// around modulo 2^128 in the other overflow modes
+    pub(crate) fn from_scaled_overflowing(
+        value: U256,
+        value_places: u32,
+        decimal_places: u32,
+        rounding: RoundingMode,
+        overflow_mode: OverflowMode,
+    ) -> CalculatorResult<Self> {
+        validate_decimal_places(decimal_places)?;
+
+        let (atomics, overflowed) = scale(value, value_places, decimal_places, rounding);
+        let checked = if overflowed {
+            None
+        } else {
+            u128::try_from(atomics).ok()
+        };
+        overflow_mode.resolve(
+            checked
+                .map(|atomics| Decimal::new(atomics, decimal_places))
+                .context(Overflow {
+                    message: RESULT_TOO_LARGE,
+                }),
+            || Decimal::new(u128::MAX, decimal_places),
+            || Decimal::new(atomics.low_u128(), decimal_places),
+        )
+    }
+
+    /// Builds the Decimal `-value / 10^value_places` would wrap around to modulo 2^128 atomics,
+    /// rounding the negative number like `from_scaled` rounds positive ones
+    pub(crate) fn from_negative_scaled_wrapping(
+        value: U256,
+        value_places: u32,
+        decimal_places: u32,
+        rounding: RoundingMode,
+    ) -> Self {
+        // Rounding the negative number down rounds its magnitude up, and the other way around
+        let rounding = match rounding {
+            RoundingMode::Floor => RoundingMode::Ceil,
+            RoundingMode::Ceil => RoundingMode::Floor,
+            RoundingMode::HalfEven => RoundingMode::HalfEven,
+        };
+        let (magnitude, _) = scale(value, value_places, decimal_places, rounding);
+        Decimal::new(0u128.wrapping_sub(magnitude.low_u128()), decimal_places)
+    }
+}
+
+/// Brings `value / 10^value_places` to `decimal_places` fractional digits. Returns the atomics
+/// modulo 2^256, whose lowest 128 bits are always right, and whether they overflowed 256 bits
+fn scale(
+    value: U256,
+    value_places: u32,
+    decimal_places: u32,
+    rounding: RoundingMode,
+) -> (U256, bool) {
+    if decimal_places >= value_places {
+        value.overflowing_mul(U256::exp10((decimal_places - value_places) as usize))
+    } else {
+        let factor = U256::exp10((value_places - decimal_places) as usize);
+        (rounding.divide(value, factor), false)
+    }
+}
+
+pub fn validate_decimal_places(decimal_places: u32) -> CalculatorResult<()> {
+    ensure!(
+        decimal_places <= MAX_DECIMAL_PLACES,
+        InvalidInput {
+            message: format!(
+                "Decimals are limited to {} decimal places",
+                MAX_DECIMAL_PLACES
+            ),
+        }
+    );
+    Ok(())
+}
+
+impl FromStr for Decimal {
+    type Err = StdError;
+
+    /// Converts a decimal string such as "1", "1.5" or "0.001" to a Decimal.
+    /// Signs, exponents and more than MAX_DECIMAL_PLACES fractional digits are not accepted.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (whole, fractional) = match input.find('.') {
+            Some(dot) => (&input[..dot], &input[dot + 1..]),
+            None => (input, ""),
+        };
+
+        let is_number = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if whole.is_empty() || input.ends_with('.') || !is_number(whole) || !is_number(fractional) {
+            return Err(StdError::generic_err(format!(
+                "Invalid decimal number '{}'",
+                input
+            )));
+        }
+
+        let decimal_places = fractional.len() as u32;
+        validate_decimal_places(decimal_places)?;
+
+        (whole.to_string() + fractional)
+            .parse::<u128>()
+            .map(|atomics| Decimal::new(atomics, decimal_places))
+            .map_err(|_| StdError::generic_err(format!("Decimal number '{}' is too large", input)))
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let divisor = 10u128.pow(self.decimal_places);
+        let whole = self.atomics / divisor;
+        let fractional = self.atomics % divisor;
+
+        if self.decimal_places == 0 {
+            write!(f, "{}", whole)
+        } else {
+            write!(
+                f,
+                "{}.{:0width$}",
+                whole,
+                fractional,
+                width = self.decimal_places as usize
+            )
+        }
+    }
+}
+
+impl JsonSchema for Decimal {
+    fn schema_name() -> String {
+        "Decimal".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        String::json_schema(gen)
+    }
+}
+
+/// Serializes as a decimal string
+impl Serialize for Decimal {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// Deserializes from a decimal string
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D>(deserializer: D) -> Result<Decimal, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(DecimalVisitor)
+    }
+}
+
+struct DecimalVisitor;
+
+impl<'de> de::Visitor<'de> for DecimalVisitor {
+    type Value = Decimal;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("string-encoded decimal")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Decimal::from_str(v).map_err(|e| E::custom(format!("Error parsing '{}': {}", v, e)))
+    }
+}
+
+/// A fixed-point decimal number like Decimal, with 512 bits of atomics for results that may not
+/// fit in 128 bits even without fractional digits, such as the variance of large numbers
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct BigDecimal {
+    atomics: U512,
+    decimal_places: u32,
+}
+
+impl BigDecimal {
+    pub(crate) fn new(atomics: U512, decimal_places: u32) -> Self {
+        let mut decimal = BigDecimal {
+            atomics,
+            decimal_places,
+        };
+        let ten = U512::from(10);
+        while decimal.decimal_places > 0 && (decimal.atomics % ten).is_zero() {
+            decimal.atomics /= ten;
+            decimal.decimal_places -= 1;
+        }
+        decimal
+    }
+}
+
+impl FromStr for BigDecimal {
+    type Err = StdError;
+
+    /// Converts a decimal string such as "1" or "1.5" to a BigDecimal, as Decimal does
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (whole, fractional) = match input.find('.') {
+            Some(dot) => (&input[..dot], &input[dot + 1..]),
+            None => (input, ""),
+        };
+
+        let is_number = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if whole.is_empty() || input.ends_with('.') || !is_number(whole) || !is_number(fractional) {
+            return Err(StdError::generic_err(format!(
+                "Invalid decimal number '{}'",
+                input
+            )));
+        }
+
+        let decimal_places = fractional.len() as u32;
+        validate_decimal_places(decimal_places)?;
+
+        U512::from_dec_str(&(whole.to_string() + fractional))
+            .map(|atomics| BigDecimal::new(atomics, decimal_places))
+            .map_err(|_| StdError::generic_err(format!("Decimal number '{}' is too large", input)))
+    }
+}
+
+impl fmt::Display for BigDecimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (whole, fractional) = self
+            .atomics
+            .div_mod(U512::exp10(self.decimal_places as usize));
+
+        if self.decimal_places == 0 {
+            write!(f, "{}", whole)
+        } else {
+            write!(
+                f,
+                "{}.{:0>width$}",
+                whole,
+                fractional.to_string(),
+                width = self.decimal_places as usize
+            )
+        }
+    }
+}
+
+impl JsonSchema for BigDecimal {
+    fn schema_name() -> String {
+        "BigDecimal".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        String::json_schema(gen)
+    }
+}
+
+/// Serializes as a decimal string, like Decimal
+impl Serialize for BigDecimal {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// Deserializes from a decimal string, which includes serialized Decimals
+impl<'de> Deserialize<'de> for BigDecimal {
+    fn deserialize<D>(deserializer: D) -> Result<BigDecimal, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(BigDecimalVisitor)
+    }
+}
+
+struct BigDecimalVisitor;
+
+impl<'de> de::Visitor<'de> for BigDecimalVisitor {
+    type Value = BigDecimal;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("string-encoded decimal")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        BigDecimal::from_str(v).map_err(|e| E::custom(format!("Error parsing '{}': {}", v, e)))
+    }
+}
+
+/// How to round results that have more fractional digits than requested
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundingMode {
+    /// Round down
+    Floor,
+    /// Round up
+    Ceil,
+    /// Round to the nearest value, and ties to the even one
+    HalfEven,
+}
+
+impl RoundingMode {
+    /// Divides `numerator` by the non-zero `denominator`, rounding the quotient
+    pub(crate) fn divide(&self, numerator: U256, denominator: U256) -> U256 {
+        let (quotient, remainder) = numerator.div_mod(denominator);
+        if self.rounds_up(quotient.bit(0), remainder, denominator) {
+            quotient + 1
+        } else {
+            quotient
+        }
+    }
+
+    /// Divides `numerator` by the non-zero `denominator` in 512 bits, rounding the quotient
+    pub(crate) fn divide_wide(&self, numerator: U512, denominator: U512) -> U512 {
+        let (quotient, remainder) = numerator.div_mod(denominator);
+        if self.rounds_up(quotient.bit(0), remainder, denominator) {
+            quotient + 1
+        } else {
+            quotient
+        }
+    }
+
+    /// Returns whether a quotient whose division left `remainder` out of `denominator` should be
+    /// rounded up
+    pub(crate) fn rounds_up<T>(&self, quotient_is_odd: bool, remainder: T, denominator: T) -> bool
+    where
+        T: Copy + Default + Ord + Sub<Output = T>,
+    {
+        match self {
+            RoundingMode::Floor => false,
+            RoundingMode::Ceil => remainder != T::default(),
+            RoundingMode::HalfEven => {
+                let other_half = denominator - remainder;
+                remainder > other_half || (remainder == other_half && quotient_is_odd)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::{from_slice, to_vec};
+
+    #[test]
+    fn test_decimal_parse_and_display() {
+        let decimal = Decimal::from_str("33.3300").unwrap();
+        assert_eq!(decimal, Decimal::new(3333, 2));
+        assert_eq!(decimal.to_string(), "33.33");
+        assert_eq!(Decimal::from_str("0.05").unwrap().to_string(), "0.05");
+        assert_eq!(Decimal::from_str("12").unwrap().to_string(), "12");
+
+        for invalid in &[
+            "",
+            ".5",
+            "5.",
+            "-1",
+            "+1",
+            "1.2.3",
+            "1e5",
+            "0.0000000000000000001",
+        ] {
+            assert!(
+                Decimal::from_str(invalid).is_err(),
+                "{} was parsed",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_decimal_json() {
+        let decimal = Decimal::new(15, 1);
+        assert_eq!(to_vec(&decimal).unwrap(), b"\"1.5\"");
+        assert_eq!(from_slice::<Decimal>(b"\"1.50\"").unwrap(), decimal);
+    }
+
+    #[test]
+    fn test_big_decimal_json() {
+        let big = BigDecimal::new(U512::from(u128::MAX) * U512::from(1000), 3);
+        assert_eq!(
+            to_vec(&big).unwrap(),
+            b"\"340282366920938463463374607431768211455\""
+        );
+        assert_eq!(
+            from_slice::<BigDecimal>(b"\"0.50\"").unwrap(),
+            BigDecimal::new(U512::from(5), 1)
+        );
+        // Decimals stored before are read back as the same number
+        let decimal = to_vec(&Decimal::new(15, 1)).unwrap();
+        assert_eq!(
+            from_slice::<BigDecimal>(&decimal).unwrap().to_string(),
+            "1.5"
+        );
+        assert!(BigDecimal::from_str("1.").is_err());
+    }
+
+    #[test]
+    fn test_rounding_modes() {
+        let divide = |rounding: RoundingMode, n: u128, d: u128| {
+            rounding.divide(U256::from(n), U256::from(d)).as_u128()
+        };
+
+        assert_eq!(divide(RoundingMode::Floor, 7, 2), 3);
+        assert_eq!(divide(RoundingMode::Ceil, 7, 2), 4);
+        assert_eq!(divide(RoundingMode::Ceil, 6, 2), 3);
+        assert_eq!(divide(RoundingMode::HalfEven, 5, 2), 2);
+        assert_eq!(divide(RoundingMode::HalfEven, 7, 2), 4);
+        assert_eq!(divide(RoundingMode::HalfEven, 8, 3), 3);
+        assert_eq!(divide(RoundingMode::HalfEven, 7, 3), 2);
+    }
+}
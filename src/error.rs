@@ -0,0 +1,55 @@
+use cosmwasm_std::StdError;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+
+/// Why a calculation failed
+#[derive(Snafu, Clone, Debug, PartialEq)]
+#[snafu(visibility(pub(crate)))]
+pub enum CalculatorError {
+    /// The result is too large for its number type
+    #[snafu(display("Invalid input: {}", message))]
+    Overflow { message: String },
+    /// The result is negative, or too small for its number type
+    #[snafu(display("Invalid input: {}", message))]
+    Underflow { message: String },
+    #[snafu(display("Invalid input: {}", message))]
+    DivideByZero { message: String },
+    /// Any other invalid operand
+    #[snafu(display("Invalid input: {}", message))]
+    InvalidInput { message: String },
+    /// An expression that can't be parsed
+    #[snafu(display("Invalid expression: {}", message))]
+    InvalidExpression { message: String },
+}
+
+pub type CalculatorResult<T> = Result<T, CalculatorError>;
+
+/// A stable, machine-readable code of a CalculatorError
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    Overflow,
+    Underflow,
+    DivideByZero,
+    InvalidInput,
+    InvalidExpression,
+}
+
+impl CalculatorError {
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            CalculatorError::Overflow { .. } => ErrorCode::Overflow,
+            CalculatorError::Underflow { .. } => ErrorCode::Underflow,
+            CalculatorError::DivideByZero { .. } => ErrorCode::DivideByZero,
+            CalculatorError::InvalidInput { .. } => ErrorCode::InvalidInput,
+            CalculatorError::InvalidExpression { .. } => ErrorCode::InvalidExpression,
+        }
+    }
+}
+
+impl From<CalculatorError> for StdError {
+    fn from(err: CalculatorError) -> Self {
+        StdError::generic_err(err.to_string())
+    }
+}
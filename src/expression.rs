@@ -0,0 +1,302 @@
+use crate::calculations_utils::{
+    calculate_div, calculate_overflowing_add, calculate_overflowing_mul, calculate_overflowing_sub,
+    calculate_sqrt, ArithmeticCalculation, OverflowingCalculation,
+};
+use crate::error::{CalculatorError, CalculatorResult, InvalidExpression};
+use crate::overflow::OverflowMode;
+use cosmwasm_std::Uint128;
+use snafu::OptionExt;
+
+/// Expressions longer than this are rejected before parsing, to keep the gas usage bounded.
+pub const MAX_EXPRESSION_LENGTH: usize = 256;
+/// The deepest parentheses/function nesting accepted by the parser.
+pub const MAX_EXPRESSION_DEPTH: usize = 32;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(Uint128),
+    Identifier(String),
+    Operator(char),
+    LeftParen,
+    RightParen,
+}
+
+enum Expr {
+    Number(Uint128),
+    Binary {
+        operation: OverflowingCalculation,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    Function {
+        function: ArithmeticCalculation,
+        argument: Box<Expr>,
+    },
+}
+
+impl Expr {
+    fn evaluate(&self, overflow_mode: OverflowMode) -> CalculatorResult<Uint128> {
+        match self {
+            Expr::Number(n) => Ok(*n),
+            Expr::Binary {
+                operation,
+                lhs,
+                rhs,
+            } => operation(
+                lhs.evaluate(overflow_mode)?,
+                rhs.evaluate(overflow_mode)?,
+                overflow_mode,
+            ),
+            Expr::Function { function, argument } => {
+                function(argument.evaluate(overflow_mode)?, Uint128::zero())
+            }
+        }
+    }
+}
+
+/// Parses an infix expression such as `(3 + 5) * sqrt(121) / 2` and evaluates it using the
+/// checked arithmetic of `calculations_utils`.
+/// `*` and `/` bind tighter than `+` and `-`, operators of the same precedence are evaluated
+/// left to right, and parentheses may be used to override that order. Every operation of the
+/// expression handles overflows using `overflow_mode`.
+pub fn evaluate_expression(
+    expression: &str,
+    overflow_mode: OverflowMode,
+) -> CalculatorResult<Uint128> {
+    if expression.len() > MAX_EXPRESSION_LENGTH {
+        return InvalidExpression {
+            message: format!(
+                "expressions are limited to {} characters",
+                MAX_EXPRESSION_LENGTH
+            ),
+        }
+        .fail();
+    }
+
+    let tokens = tokenize(expression)?;
+    let mut parser = Parser {
+        tokens: &tokens,
+        position: 0,
+        depth: 0,
+    };
+    let expr = parser.parse_expression()?;
+    if let Some(token) = parser.peek() {
+        return Err(unexpected_token(token));
+    }
+
+    expr.evaluate(overflow_mode)
+}
+
+fn tokenize(expression: &str) -> CalculatorResult<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = expression.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() {
+            let mut digits = String::new();
+            while let Some(&d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                digits.push(d);
+                chars.next();
+            }
+            let n = digits.parse::<u128>().ok().context(InvalidExpression {
+                message: format!("{} is too large", digits),
+            })?;
+            tokens.push(Token::Number(Uint128(n)));
+        } else if c.is_ascii_alphabetic() {
+            let mut name = String::new();
+            while let Some(&l) = chars.peek().filter(|l| l.is_ascii_alphanumeric()) {
+                name.push(l);
+                chars.next();
+            }
+            tokens.push(Token::Identifier(name));
+        } else {
+            tokens.push(match c {
+                '+' | '-' | '*' | '/' => Token::Operator(c),
+                '(' => Token::LeftParen,
+                ')' => Token::RightParen,
+                _ => {
+                    return InvalidExpression {
+                        message: format!("unexpected character '{}'", c),
+                    }
+                    .fail()
+                }
+            });
+            chars.next();
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn unexpected_token(token: &Token) -> CalculatorError {
+    let description = match token {
+        Token::Number(n) => n.to_string(),
+        Token::Identifier(name) => name.clone(),
+        Token::Operator(c) => c.to_string(),
+        Token::LeftParen => "(".to_string(),
+        Token::RightParen => ")".to_string(),
+    };
+    CalculatorError::InvalidExpression {
+        message: format!("unexpected '{}'", description),
+    }
+}
+
+fn binary_operation(operator: char) -> OverflowingCalculation {
+    match operator {
+        '+' => calculate_overflowing_add,
+        '-' => calculate_overflowing_sub,
+        '*' => calculate_overflowing_mul,
+        // Divisions can't overflow
+        _ => |n1, n2, _| calculate_div(n1, n2),
+    }
+}
+
+fn unary_function(name: &str) -> CalculatorResult<ArithmeticCalculation> {
+    match name {
+        "sqrt" => Ok(calculate_sqrt),
+        _ => InvalidExpression {
+            message: format!("unknown function '{}'", name),
+        }
+        .fail(),
+    }
+}
+
+/// A recursive descent parser over the following grammar:
+///
+/// expression := term (('+' | '-') term)*
+/// term       := factor (('*' | '/') factor)*
+/// factor     := number | function '(' expression ')' | '(' expression ')'
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> CalculatorResult<&'a Token> {
+        let token = self.peek().context(InvalidExpression {
+            message: "unexpected end of input",
+        })?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect_right_paren(&mut self) -> CalculatorResult<()> {
+        match self.next()? {
+            Token::RightParen => Ok(()),
+            token => Err(unexpected_token(token)),
+        }
+    }
+
+    fn parse_expression(&mut self) -> CalculatorResult<Expr> {
+        self.parse_binary(&['+', '-'], Self::parse_term)
+    }
+
+    fn parse_term(&mut self) -> CalculatorResult<Expr> {
+        self.parse_binary(&['*', '/'], Self::parse_factor)
+    }
+
+    fn parse_binary(
+        &mut self,
+        operators: &[char],
+        parse_operand: fn(&mut Self) -> CalculatorResult<Expr>,
+    ) -> CalculatorResult<Expr> {
+        let mut lhs = parse_operand(self)?;
+
+        while let Some(Token::Operator(operator)) = self.peek() {
+            if !operators.contains(operator) {
+                break;
+            }
+            self.position += 1;
+            let rhs = parse_operand(self)?;
+            lhs = Expr::Binary {
+                operation: binary_operation(*operator),
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            };
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_factor(&mut self) -> CalculatorResult<Expr> {
+        match self.next()? {
+            Token::Number(n) => Ok(Expr::Number(*n)),
+            Token::LeftParen => self.parse_nested(),
+            Token::Identifier(name) => {
+                let function = unary_function(name)?;
+                match self.next()? {
+                    Token::LeftParen => Ok(Expr::Function {
+                        function,
+                        argument: Box::new(self.parse_nested()?),
+                    }),
+                    token => Err(unexpected_token(token)),
+                }
+            }
+            token => Err(unexpected_token(token)),
+        }
+    }
+
+    /// Parses the expression following an opening parenthesis, up to and including its matching
+    /// closing parenthesis.
+    fn parse_nested(&mut self) -> CalculatorResult<Expr> {
+        self.depth += 1;
+        if self.depth > MAX_EXPRESSION_DEPTH {
+            return InvalidExpression {
+                message: format!("nesting is limited to {} levels", MAX_EXPRESSION_DEPTH),
+            }
+            .fail();
+        }
+
+        let expr = self.parse_expression()?;
+        self.expect_right_paren()?;
+        self.depth -= 1;
+        Ok(expr)
+    }
+}
+
+#[test]
+fn test_evaluate_expression() {
+    let actual = evaluate_expression("(3 + 5) * sqrt(121) / 2", OverflowMode::Checked);
+    assert_eq!(Uint128::from(44u128), actual.unwrap());
+}
+
+#[test]
+fn test_evaluate_expression_precedence() {
+    let actual = evaluate_expression("2 + 3 * 4 - 10 / 5", OverflowMode::Checked);
+    assert_eq!(Uint128::from(12u128), actual.unwrap());
+
+    let actual = evaluate_expression("20 - 5 - 3", OverflowMode::Checked);
+    assert_eq!(Uint128::from(12u128), actual.unwrap());
+}
+
+#[test]
+fn test_evaluate_expression_errors() {
+    assert!(evaluate_expression("1 / (2 - 2)", OverflowMode::Checked).is_err());
+    assert!(evaluate_expression("3 - 5", OverflowMode::Checked).is_err());
+    assert!(evaluate_expression("(1 + 2", OverflowMode::Checked).is_err());
+    assert!(evaluate_expression("1 + 2)", OverflowMode::Checked).is_err());
+    assert!(evaluate_expression("1 +", OverflowMode::Checked).is_err());
+    assert!(evaluate_expression("cbrt(8)", OverflowMode::Checked).is_err());
+    assert!(evaluate_expression("2 ^ 3", OverflowMode::Checked).is_err());
+    assert!(
+        evaluate_expression(&"(".repeat(MAX_EXPRESSION_DEPTH + 1), OverflowMode::Checked).is_err()
+    );
+}
+
+#[test]
+fn test_evaluate_expression_overflow_modes() {
+    let expression = "340282366920938463463374607431768211455 + 2 - 3";
+    assert!(evaluate_expression(expression, OverflowMode::Checked).is_err());
+    let actual = evaluate_expression(expression, OverflowMode::Saturating);
+    assert_eq!(Uint128(u128::MAX - 3), actual.unwrap());
+    let actual = evaluate_expression(expression, OverflowMode::Wrapping);
+    assert_eq!(Uint128(u128::MAX - 1), actual.unwrap());
+    assert!(evaluate_expression("1 / 0", OverflowMode::Wrapping).is_err());
+}
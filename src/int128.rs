@@ -0,0 +1,108 @@
+use schemars::JsonSchema;
+use serde::{de, ser, Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use cosmwasm_std::StdError;
+
+/// A signed 128-bit integer, serialized as a decimal string (e.g. "-42") the same way `Uint128` is.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord, JsonSchema)]
+pub struct Int128(#[schemars(with = "String")] pub i128);
+
+impl Int128 {
+    /// Creates an Int128(0)
+    pub const fn zero() -> Self {
+        Int128(0)
+    }
+
+    /// Returns a copy of the internal data
+    pub fn i128(&self) -> i128 {
+        self.0
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl From<i128> for Int128 {
+    fn from(val: i128) -> Self {
+        Int128(val)
+    }
+}
+
+impl From<i64> for Int128 {
+    fn from(val: i64) -> Self {
+        Int128(val.into())
+    }
+}
+
+impl FromStr for Int128 {
+    type Err = StdError;
+
+    fn from_str(val: &str) -> Result<Self, Self::Err> {
+        val.parse::<i128>()
+            .map(Int128)
+            .map_err(|e| StdError::generic_err(format!("Parsing signed integer: {}", e)))
+    }
+}
+
+impl fmt::Display for Int128 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Serializes as a decimal string
+impl Serialize for Int128 {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// Deserializes from a decimal string
+impl<'de> Deserialize<'de> for Int128 {
+    fn deserialize<D>(deserializer: D) -> Result<Int128, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(Int128Visitor)
+    }
+}
+
+struct Int128Visitor;
+
+impl<'de> de::Visitor<'de> for Int128Visitor {
+    type Value = Int128;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("string-encoded signed integer")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Int128::from_str(v).map_err(|e| E::custom(format!("Error parsing '{}': {}", v, e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::{from_slice, to_vec};
+
+    #[test]
+    fn test_int128_json() {
+        let n = Int128(-42);
+        let serialized = to_vec(&n).unwrap();
+        assert_eq!(serialized, b"\"-42\"");
+
+        let deserialized: Int128 = from_slice(b"\"-42\"").unwrap();
+        assert_eq!(deserialized, n);
+        assert!(from_slice::<Int128>(b"\"4x2\"").is_err());
+    }
+}
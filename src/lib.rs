@@ -1,7 +1,15 @@
+mod big_uint;
 mod calculations_utils;
 pub mod contract;
+pub mod decimal;
+pub mod error;
+mod expression;
+pub mod int128;
 pub mod msg;
+pub mod operand;
+pub mod overflow;
 pub mod state;
+pub mod uint512;
 mod utils;
 mod viewing_key;
 
@@ -9,7 +17,7 @@ mod viewing_key;
 mod wasm {
     use super::contract;
     use cosmwasm_std::{
-        do_handle, do_init, do_query, ExternalApi, ExternalQuerier, ExternalStorage,
+        do_handle, do_init, do_migrate, do_query, ExternalApi, ExternalQuerier, ExternalStorage,
     };
 
     #[no_mangle]
@@ -30,6 +38,15 @@ mod wasm {
         )
     }
 
+    #[no_mangle]
+    extern "C" fn migrate(env_ptr: u32, msg_ptr: u32) -> u32 {
+        do_migrate(
+            &contract::migrate::<ExternalStorage, ExternalApi, ExternalQuerier>,
+            env_ptr,
+            msg_ptr,
+        )
+    }
+
     #[no_mangle]
     extern "C" fn query(msg_ptr: u32) -> u32 {
         do_query(
@@ -1,5 +1,16 @@
-use crate::viewing_key::{ViewingKey, VIEWING_KEY_SIZE};
+use crate::decimal::{Decimal, RoundingMode};
+use crate::error::ErrorCode;
+use crate::int128::Int128;
+use crate::operand::Operand;
+use crate::overflow::OverflowMode;
+use crate::state::{
+    may_load, CalculationRecord, Factor, HistoryCounts, LatestBlock, Register, Statistics,
+    LATEST_BLOCK_KEY,
+};
+use crate::uint512::Uint512;
+use crate::viewing_key::{ViewingKey, ViewingKeyScope, VIEWING_KEY_SIZE};
 use cosmwasm_std::Api;
+use cosmwasm_std::Binary;
 use cosmwasm_std::Extern;
 use cosmwasm_std::HumanAddr;
 use cosmwasm_std::Querier;
@@ -8,11 +19,29 @@ use cosmwasm_std::StdResult;
 use cosmwasm_std::Storage;
 use cosmwasm_std::Uint128;
 use schemars::JsonSchema;
+use secret_toolkit::permit::{Permission, Permit};
+use secret_toolkit::utils::HandleCallback;
 use serde::{Deserialize, Serialize};
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InitMsg {
     /// User supplied entropy string for pseudorandom number generator seed
     pub prng_seed: String,
+    /// The number of decimal places decimal calculations are rounded to, unless a message asks
+    /// for a different number. Defaults to 18
+    pub decimal_places: Option<u32>,
+    /// The address allowed to change the admin and the status of the contract. Defaults to the
+    /// instantiator
+    pub admin: Option<HumanAddr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {
+    /// The number of decimal places decimal calculations are rounded to, when migrating from
+    /// version 1 of the contract. Defaults to 18
+    pub decimal_places: Option<u32>,
+    /// The admin of the contract, when migrating from version 1 of the contract. Defaults to the
+    /// address that migrates it
+    pub admin: Option<HumanAddr>,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
@@ -22,26 +51,426 @@ pub enum ResponseStatus {
     Failure,
 }
 
+/// Which messages the contract accepts
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractStatusLevel {
+    Normal,
+    /// Only messages that don't calculate anything are accepted, and computations are refused
+    StopCalculations,
+    /// Only the admin messages are accepted
+    StopAll,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum HandleMsg {
-    Add((Uint128, Uint128)),
-    Sub((Uint128, Uint128)),
-    Mul((Uint128, Uint128)),
-    Div((Uint128, Uint128)),
-    Sqrt(Uint128),
+    Add {
+        operands: (Operand, Operand),
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    Sub {
+        operands: (Operand, Operand),
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    Mul {
+        operands: (Operand, Operand),
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    Div((Operand, Operand)),
+    Sqrt(Operand),
+    /// The nth root of `value`, rounded down
+    NthRoot {
+        value: Operand,
+        n: u32,
+    },
+    Pow {
+        operands: (Operand, Operand),
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    Mod((Operand, Operand)),
+    Log2(Operand),
+    Log10(Operand),
+    /// The integer logarithm of `value` in `base`, rounded down
+    Log {
+        value: Operand,
+        base: Uint128,
+    },
+    Gcd((Operand, Operand)),
+    Lcm {
+        operands: (Operand, Operand),
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    /// `value * bps / 10000`, i.e. the given number of basis points of the value
+    Percent {
+        value: Operand,
+        bps: u32,
+        /// Defaults to floor
+        rounding: Option<RoundingMode>,
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    /// The amount left after deducting a fee of `fee_bps` basis points of it. The fee is at most
+    /// 100%, so this can't overflow and has no overflow mode
+    ApplyFee {
+        amount: Operand,
+        fee_bps: u32,
+        /// How the fee is rounded. Defaults to floor
+        rounding: Option<RoundingMode>,
+    },
+    /// `a * b / denom`, computed without overflowing when only the product is too large
+    MulDiv {
+        a: Operand,
+        b: Operand,
+        denom: Operand,
+        /// Defaults to floor
+        rounding: Option<RoundingMode>,
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    IsPrime(Operand),
+    /// Factorizes a number into its prime factors. Fails if that takes too many steps
+    Factorize(Operand),
+    /// Summary statistics of a list of numbers
+    Stats {
+        values: Vec<Uint128>,
+        /// The number of decimal places of the fractional statistics. Defaults to the one the
+        /// contract was initialized with
+        decimal_places: Option<u32>,
+        rounding: Option<RoundingMode>,
+        /// How the sum overflows. Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    SignedAdd {
+        operands: (Int128, Int128),
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    SignedSub {
+        operands: (Int128, Int128),
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    SignedMul {
+        operands: (Int128, Int128),
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    SignedDiv {
+        operands: (Int128, Int128),
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    /// Calculations on unsigned integers of up to 150 digits, for results that don't fit in a
+    /// Uint128. Results of more than 150 digits saturate to the largest 150-digit number, or wrap
+    /// around modulo 10^150, in the other overflow modes
+    BigAdd {
+        operands: (Uint512, Uint512),
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    BigSub {
+        operands: (Uint512, Uint512),
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    BigMul {
+        operands: (Uint512, Uint512),
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    BigDiv((Uint512, Uint512)),
+    BigSqrt(Uint512),
+    BigPow {
+        operands: (Uint512, Uint512),
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    BigMod((Uint512, Uint512)),
+    /// The nth root of `value`, rounded down
+    BigNthRoot {
+        value: Uint512,
+        n: u32,
+    },
+    BigLog2(Uint512),
+    BigLog10(Uint512),
+    /// The integer logarithm of `value` in `base`, rounded down
+    BigLog {
+        value: Uint512,
+        base: Uint512,
+    },
+    BigGcd((Uint512, Uint512)),
+    BigLcm {
+        operands: (Uint512, Uint512),
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    /// `a * b / denom`, computed without overflowing when only the product is too large
+    BigMulDiv {
+        a: Uint512,
+        b: Uint512,
+        denom: Uint512,
+        /// Defaults to floor
+        rounding: Option<RoundingMode>,
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    DecimalAdd {
+        operands: (Decimal, Decimal),
+        decimal_places: Option<u32>,
+        rounding: Option<RoundingMode>,
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    DecimalSub {
+        operands: (Decimal, Decimal),
+        decimal_places: Option<u32>,
+        rounding: Option<RoundingMode>,
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    DecimalMul {
+        operands: (Decimal, Decimal),
+        decimal_places: Option<u32>,
+        rounding: Option<RoundingMode>,
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    DecimalDiv {
+        operands: (Decimal, Decimal),
+        decimal_places: Option<u32>,
+        rounding: Option<RoundingMode>,
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    DecimalSqrt {
+        value: Decimal,
+        decimal_places: Option<u32>,
+        rounding: Option<RoundingMode>,
+    },
+    /// Performs up to 32 calculations in a single transaction. An operand referring to the
+    /// previous result refers to the result of the previous successful calculation of the batch
+    Batch {
+        ops: Vec<Operation>,
+        /// Defaults to all_or_nothing
+        mode: Option<BatchMode>,
+    },
+    /// Evaluates an infix expression such as `(3 + 5) * sqrt(121) / 2`
+    Evaluate {
+        expression: String,
+        /// How each operation of the expression overflows. Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    /// Performs a calculation like the message of the same operation, and then sends its outcome
+    /// to a contract in a `receive_calculation` message, if a callback is given
+    Calculate {
+        op: Operation,
+        callback: Option<Callback>,
+    },
 
-    /// Creates a new viewing key with user supplied entropy
+    /// Creates a new viewing key with user supplied entropy, replacing the sender's key. Keys can
+    /// be labeled, and can expire at a block height or time. Queries don't know the current
+    /// block, so keys expire once the contract handles a message other than a calculation in a
+    /// block past their expiry.
+    /// A key given `scopes` is added beside the sender's key instead, replacing the scoped key
+    /// with the same label, and can only be used for the queries of its scopes
     CreateViewingKey {
         entropy: String,
+        label: Option<String>,
+        expires_at_height: Option<u64>,
+        /// In seconds since the epoch
+        expires_at_time: Option<u64>,
+        scopes: Option<Vec<ViewingKeyScope>>,
         padding: Option<String>,
     },
     SetViewingKey {
         key: String,
+        label: Option<String>,
+        expires_at_height: Option<u64>,
+        /// In seconds since the epoch
+        expires_at_time: Option<u64>,
+        scopes: Option<Vec<ViewingKeyScope>>,
+        padding: Option<String>,
+    },
+    /// Revokes the sender's viewing key, or their scoped key with the given label, which can't be
+    /// used in queries anymore
+    RevokeViewingKey {
+        label: Option<String>,
+        padding: Option<String>,
+    },
+
+    /// Sets whether failed calculations are recorded in the sender's history, along with the
+    /// reason they failed. Failures aren't recorded by default
+    SetRecordFailures {
+        enabled: bool,
+    },
+    /// Deletes all the entries of the sender's history
+    ClearHistory {},
+    /// Deletes the entry at `index` of the sender's history, counting from the oldest one. The
+    /// entries after it move down by one
+    DeleteHistoryEntry {
+        index: u32,
+    },
+    /// Limits the sender's history to its `max_entries` latest entries, dropping the oldest one
+    /// whenever a calculation is recorded in a full history. The history is unbounded if no limit
+    /// is given, which is the default
+    SetRetention {
+        max_entries: Option<u32>,
+    },
+
+    /// Stores a value in one of the sender's memory registers, replacing its content
+    MemoryStore {
+        register: String,
+        value: Operand,
+    },
+    /// Adds a value to one of the sender's memory registers (M+). Empty registers hold 0
+    MemoryAdd {
+        register: String,
+        value: Operand,
+    },
+    /// Subtracts a value from one of the sender's memory registers (M-)
+    MemorySub {
+        register: String,
+        value: Operand,
+    },
+    /// Empties one of the sender's memory registers (MC)
+    MemoryClear {
+        register: String,
+    },
+
+    /// Revokes the sender's query permits with the given name
+    RevokePermit {
+        permit_name: String,
+        padding: Option<String>,
+    },
+
+    /// Admin only
+    ChangeAdmin {
+        address: HumanAddr,
+        padding: Option<String>,
+    },
+    /// Admin only. Stops (or resumes) calculations or all activity, e.g. during an incident
+    SetContractStatus {
+        level: ContractStatusLevel,
         padding: Option<String>,
     },
 }
 
+/// A single calculation of a batch
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Add {
+        operands: (Operand, Operand),
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    Sub {
+        operands: (Operand, Operand),
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    Mul {
+        operands: (Operand, Operand),
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    Div((Operand, Operand)),
+    Sqrt(Operand),
+    /// The nth root of `value`, rounded down
+    NthRoot {
+        value: Operand,
+        n: u32,
+    },
+    Pow {
+        operands: (Operand, Operand),
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    Mod((Operand, Operand)),
+    Log2(Operand),
+    Log10(Operand),
+    /// The integer logarithm of `value` in `base`, rounded down
+    Log {
+        value: Operand,
+        base: Uint128,
+    },
+    Gcd((Operand, Operand)),
+    Lcm {
+        operands: (Operand, Operand),
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    /// `value * bps / 10000`, i.e. the given number of basis points of the value
+    Percent {
+        value: Operand,
+        bps: u32,
+        /// Defaults to floor
+        rounding: Option<RoundingMode>,
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+    /// The amount left after deducting a fee of `fee_bps` basis points of it. The fee is at most
+    /// 100%, so this can't overflow and has no overflow mode
+    ApplyFee {
+        amount: Operand,
+        fee_bps: u32,
+        /// How the fee is rounded. Defaults to floor
+        rounding: Option<RoundingMode>,
+    },
+    /// `a * b / denom`, computed without overflowing when only the product is too large
+    MulDiv {
+        a: Operand,
+        b: Operand,
+        denom: Operand,
+        /// Defaults to floor
+        rounding: Option<RoundingMode>,
+        /// Defaults to checked
+        overflow_mode: Option<OverflowMode>,
+    },
+}
+
+/// A contract to send the outcome of a calculation to
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Callback {
+    pub contract_addr: HumanAddr,
+    pub code_hash: String,
+    /// Passed on to the contract as is
+    pub msg: Option<Binary>,
+}
+
+/// The message sent to a callback contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiverHandleMsg {
+    ReceiveCalculation {
+        /// The address that requested the calculation
+        sender: HumanAddr,
+        n: Option<Uint128>,
+        status: String,
+        error_code: Option<ErrorCode>,
+        msg: Option<Binary>,
+    },
+}
+
+impl HandleCallback for ReceiverHandleMsg {
+    const BLOCK_SIZE: usize = 256;
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchMode {
+    /// If any of the calculations fails, none of them is recorded
+    AllOrNothing,
+    /// The successful calculations are recorded even if others fail
+    BestEffort,
+}
+
 /// Responses from handle functions
 #[derive(Serialize, Deserialize, Debug, JsonSchema)]
 #[serde(rename_all = "snake_case")]
@@ -49,6 +478,58 @@ pub enum HandleAnswer {
     CalculationResult {
         n: Option<Uint128>,
         status: String,
+        /// The code of the error the calculation failed with, if any
+        error_code: Option<ErrorCode>,
+    },
+    SignedCalculationResult {
+        n: Option<Int128>,
+        status: String,
+        /// The code of the error the calculation failed with, if any
+        error_code: Option<ErrorCode>,
+    },
+    DecimalCalculationResult {
+        n: Option<Decimal>,
+        status: String,
+        /// The code of the error the calculation failed with, if any
+        error_code: Option<ErrorCode>,
+    },
+    BigCalculationResult {
+        n: Option<Uint512>,
+        status: String,
+        /// The code of the error the calculation failed with, if any
+        error_code: Option<ErrorCode>,
+    },
+    /// Return the root, and whether it is exact or was rounded down, leaving a remainder
+    NthRootResult {
+        n: Option<Uint128>,
+        exact: Option<bool>,
+        remainder: Option<Uint128>,
+        status: String,
+        /// The code of the error the calculation failed with, if any
+        error_code: Option<ErrorCode>,
+    },
+    IsPrimeResult {
+        is_prime: Option<bool>,
+        status: String,
+        /// The code of the error the calculation failed with, if any
+        error_code: Option<ErrorCode>,
+    },
+    /// Return the prime factors, in increasing order
+    FactorizeResult {
+        factors: Option<Vec<Factor>>,
+        status: String,
+        /// The code of the error the calculation failed with, if any
+        error_code: Option<ErrorCode>,
+    },
+    StatsResult {
+        stats: Option<Box<Statistics>>,
+        status: String,
+        /// The code of the error the calculation failed with, if any
+        error_code: Option<ErrorCode>,
+    },
+    BatchResult {
+        results: Vec<OperationResult>,
+        status: String,
     },
 
     /// Return the created key
@@ -58,8 +539,53 @@ pub enum HandleAnswer {
     SetViewingKey {
         status: ResponseStatus,
     },
+    RevokeViewingKey {
+        status: ResponseStatus,
+    },
+    SetRecordFailures {
+        status: ResponseStatus,
+    },
+    ClearHistory {
+        status: ResponseStatus,
+    },
+    DeleteHistoryEntry {
+        status: ResponseStatus,
+    },
+    SetRetention {
+        status: ResponseStatus,
+    },
+    /// Return the new content of the register
+    MemoryResult {
+        n: Option<Uint128>,
+        status: String,
+        /// The code of the error the register couldn't be updated with, if any
+        error_code: Option<ErrorCode>,
+    },
+    MemoryClear {
+        status: ResponseStatus,
+    },
+    RevokePermit {
+        status: ResponseStatus,
+    },
+    ChangeAdmin {
+        status: ResponseStatus,
+    },
+    SetContractStatus {
+        status: ResponseStatus,
+    },
 }
 
+/// The result of a single calculation of a batch
+#[derive(Serialize, Deserialize, Debug, PartialEq, JsonSchema)]
+pub struct OperationResult {
+    pub n: Option<Uint128>,
+    pub status: String,
+    pub error_code: Option<ErrorCode>,
+}
+
+/// Queries with a viewing key check its expiry against the latest block the contract handled a
+/// message other than a calculation in, which can be well behind the current block. An expired key
+/// keeps working until the contract handles such a message
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
@@ -73,7 +599,46 @@ pub enum QueryMsg {
 
         /// The number of items to take once the wanted page is reached
         page_size: u32,
+
+        /// Whether to include failed calculations, if any were recorded. Defaults to true
+        include_failures: Option<bool>,
+
+        /// Reads the page from a position in the history instead of skipping 'page' pages
+        cursor: Option<HistoryCursor>,
     },
+    /// GetCalculationRecords returns the user's calculations history as structured records.
+    GetCalculationRecords {
+        address: HumanAddr,
+        key: String,
+
+        /// How many pages with 'page_size' items to skip
+        page: Option<u32>,
+
+        /// The number of items to take once the wanted page is reached
+        page_size: u32,
+
+        /// Whether to include failed calculations, if any were recorded. Defaults to true
+        include_failures: Option<bool>,
+
+        /// Reads the page from a position in the history instead of skipping 'page' pages
+        cursor: Option<HistoryCursor>,
+    },
+    /// GetHistoryCounts returns how many calculations of each operation the user's history has,
+    /// without their operands and results.
+    GetHistoryCounts { address: HumanAddr, key: String },
+    /// GetRegisters returns the content of the user's memory registers.
+    GetRegisters { address: HumanAddr, key: String },
+    /// Runs a query on behalf of the signer of a SNIP-24 permit, instead of authenticating with
+    /// a viewing key
+    WithPermit {
+        permit: Permit,
+        query: QueryWithPermit,
+    },
+    /// Compute runs a calculation without recording it, so it needs no authentication. Its
+    /// operands must be numbers, since there is no history or memory to refer to
+    Compute { op: Operation },
+    /// ContractStatus returns which messages the contract currently accepts.
+    ContractStatus {},
 }
 
 impl QueryMsg {
@@ -81,23 +646,118 @@ impl QueryMsg {
         &self,
         deps: &Extern<S, A, Q>,
     ) -> StdResult<HumanAddr> {
-        let (address, key) = match self {
-            QueryMsg::GetHistory { address, key, .. } => (address.clone(), ViewingKey(key.clone())),
+        let (address, key, scope) = match self {
+            QueryMsg::GetHistory { address, key, .. }
+            | QueryMsg::GetCalculationRecords { address, key, .. } => (
+                address.clone(),
+                ViewingKey(key.clone()),
+                ViewingKeyScope::History,
+            ),
+            QueryMsg::GetHistoryCounts { address, key } => (
+                address.clone(),
+                ViewingKey(key.clone()),
+                ViewingKeyScope::Counts,
+            ),
+            QueryMsg::GetRegisters { address, key } => (
+                address.clone(),
+                ViewingKey(key.clone()),
+                ViewingKeyScope::Registers,
+            ),
+            QueryMsg::WithPermit { .. } => {
+                return Err(StdError::generic_err(
+                    "Queries with permits are authenticated by their signature",
+                ))
+            }
+            QueryMsg::Compute { .. } | QueryMsg::ContractStatus {} => {
+                return Err(StdError::generic_err(
+                    "This query doesn't need authentication",
+                ))
+            }
         };
 
         let canonical_addr = deps.api.canonical_address(&address)?;
 
         let expected_key = ViewingKey::read_viewing_key(&deps.storage, &canonical_addr);
 
-        if expected_key.is_none() {
-            // Checking the key will take significant time. We don't want to exit immediately if it isn't set
-            // in a way which will allow to time the command and determine if a viewing key doesn't exist
-            key.check_viewing_key(&[0u8; VIEWING_KEY_SIZE]);
-            Err(StdError::generic_err("Wrong viewing key"))
-        } else if key.check_viewing_key(expected_key.unwrap().as_slice()) {
-            Ok(address)
+        // Checking the key will take significant time. We don't want to skip it if no key is set
+        // in a way which will allow to time the command and determine if a viewing key doesn't exist
+        let is_main_key = key
+            .check_viewing_key(expected_key.as_deref().unwrap_or(&[0u8; VIEWING_KEY_SIZE]))
+            && expected_key.is_some();
+
+        // The main key can be used for every query, and scoped keys for the queries of their scopes
+        let (info, scopes) = if is_main_key {
+            let info = ViewingKey::read_viewing_key_info(&deps.storage, &canonical_addr)?;
+            (info, None)
         } else {
-            Err(StdError::generic_err("Wrong viewing key"))
+            match key.find_scoped_viewing_key(&deps.storage, &canonical_addr)? {
+                Some(scoped_key) => (Some(scoped_key.info), Some(scoped_key.scopes)),
+                None => return Err(StdError::generic_err("Wrong viewing key")),
+            }
+        };
+
+        let latest_block: Option<LatestBlock> = may_load(&deps.storage, LATEST_BLOCK_KEY)?;
+        if let (Some(info), Some(block)) = (info, latest_block) {
+            if info.is_expired(block.height, block.time) {
+                return Err(StdError::generic_err("The viewing key has expired"));
+            }
+        }
+        match scopes {
+            Some(scopes) if !scopes.iter().any(|granted| granted.grants(scope)) => Err(
+                StdError::generic_err("This viewing key can't be used for this query"),
+            ),
+            _ => Ok(address),
+        }
+    }
+}
+
+/// A position in a user's history to read a page from, as an alternative to page numbers
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+pub struct HistoryCursor {
+    /// The index of the first entry to read, counting from the oldest one. Reading backward from
+    /// an index past the end of the history starts from the latest entry
+    pub start: u32,
+    pub direction: Direction,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// Towards newer entries
+    Forward,
+    /// Towards older entries
+    Backward,
+}
+
+/// The queries that can be authenticated with a permit, on behalf of its signer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryWithPermit {
+    GetHistory {
+        page: Option<u32>,
+        page_size: u32,
+        include_failures: Option<bool>,
+        cursor: Option<HistoryCursor>,
+    },
+    GetCalculationRecords {
+        page: Option<u32>,
+        page_size: u32,
+        include_failures: Option<bool>,
+        cursor: Option<HistoryCursor>,
+    },
+    GetHistoryCounts {},
+    GetRegisters {},
+}
+
+impl QueryWithPermit {
+    /// The permission a permit needs to grant for the query, besides the owner permission that
+    /// grants every query
+    pub fn permission(&self) -> Permission {
+        match self {
+            QueryWithPermit::GetHistory { .. }
+            | QueryWithPermit::GetCalculationRecords { .. }
+            | QueryWithPermit::GetHistoryCounts {} => Permission::History,
+            QueryWithPermit::GetRegisters {} => Permission::Owner,
         }
     }
 }
@@ -107,10 +767,46 @@ impl QueryMsg {
 #[serde(rename_all = "snake_case")]
 pub enum QueryAnswer {
     GetHistory(GetHistory),
+    GetCalculationRecords(GetCalculationRecords),
+    GetHistoryCounts(HistoryCounts),
+    GetRegisters(GetRegisters),
+    Compute(Compute),
+    ContractStatus(ContractStatus),
 }
 
 #[derive(Serialize, Deserialize, Debug, JsonSchema)]
 pub struct GetHistory {
     pub status: String,
     pub history: Vec<String>,
+    /// The number of entries in the history, including failures
+    pub total: u32,
+    /// The start of the following page when reading with a cursor, unless the end was reached
+    pub next: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+pub struct GetCalculationRecords {
+    pub status: String,
+    pub records: Vec<CalculationRecord>,
+    /// The number of entries in the history, including failures
+    pub total: u32,
+    /// The start of the following page when reading with a cursor, unless the end was reached
+    pub next: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+pub struct GetRegisters {
+    pub registers: Vec<Register>,
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+pub struct ContractStatus {
+    pub status: ContractStatusLevel,
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+pub struct Compute {
+    pub n: Option<Uint128>,
+    pub status: String,
+    pub error_code: Option<ErrorCode>,
 }
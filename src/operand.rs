@@ -0,0 +1,153 @@
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::de::{EnumAccess, VariantAccess};
+use serde::{de, ser, Deserialize, Deserializer, Serialize};
+use std::fmt;
+
+use cosmwasm_std::Uint128;
+
+/// An operand of an unsigned calculation: either a number, a reference to a previous result in
+/// the sender's history, or the content of one of the sender's memory registers.
+///
+/// In JSON a number is written as a string (e.g. "5", like any Uint128), the result of the
+/// sender's last calculation as "previous", the result of the Nth entry of the sender's
+/// history (counting from 0, oldest first) as {"history_index": N}, and the content of a
+/// memory register as {"register": "name"}.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operand {
+    Value(Uint128),
+    Previous,
+    HistoryIndex(u64),
+    Register(String),
+}
+
+const PREVIOUS: &str = "previous";
+const HISTORY_INDEX: &str = "history_index";
+const REGISTER: &str = "register";
+
+impl From<Uint128> for Operand {
+    fn from(n: Uint128) -> Self {
+        Operand::Value(n)
+    }
+}
+
+impl Serialize for Operand {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        match self {
+            Operand::Value(n) => n.serialize(serializer),
+            Operand::Previous => serializer.serialize_unit_variant("Operand", 1, PREVIOUS),
+            Operand::HistoryIndex(index) => {
+                serializer.serialize_newtype_variant("Operand", 2, HISTORY_INDEX, index)
+            }
+            Operand::Register(name) => {
+                serializer.serialize_newtype_variant("Operand", 3, REGISTER, name)
+            }
+        }
+    }
+}
+
+/// Deserialized as an enum, because the JSON deserializer used by contracts doesn't support
+/// untagged enums. Numbers are read as the names of unit variants
+impl<'de> Deserialize<'de> for Operand {
+    fn deserialize<D>(deserializer: D) -> Result<Operand, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_enum(
+            "Operand",
+            &[PREVIOUS, HISTORY_INDEX, REGISTER],
+            OperandVisitor,
+        )
+    }
+}
+
+struct OperandVisitor;
+
+impl<'de> de::Visitor<'de> for OperandVisitor {
+    type Value = Operand;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(
+            "a string-encoded number, \"previous\", {\"history_index\": N} or {\"register\": name}",
+        )
+    }
+
+    fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
+    where
+        A: EnumAccess<'de>,
+    {
+        let (variant, access): (String, _) = data.variant()?;
+        match variant.as_str() {
+            PREVIOUS => access.unit_variant().map(|_| Operand::Previous),
+            HISTORY_INDEX => access.newtype_variant().map(Operand::HistoryIndex),
+            REGISTER => access.newtype_variant().map(Operand::Register),
+            number => {
+                access.unit_variant()?;
+                number.parse::<u128>().map(|n| Operand::Value(Uint128(n))).map_err(|_| {
+                    de::Error::custom(format!(
+                        "Error parsing '{}': expected a number or a reference to a previous result or register",
+                        number
+                    ))
+                })
+            }
+        }
+    }
+}
+
+/// Only used to generate the JSON schema of Operand
+#[derive(JsonSchema)]
+#[serde(untagged)]
+#[allow(dead_code)]
+enum OperandSchema {
+    Value(Uint128),
+    Reference(OperandReference),
+}
+
+#[derive(JsonSchema)]
+#[serde(rename_all = "snake_case")]
+#[allow(dead_code)]
+enum OperandReference {
+    Previous,
+    HistoryIndex(u64),
+    Register(String),
+}
+
+impl JsonSchema for Operand {
+    fn schema_name() -> String {
+        "Operand".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        OperandSchema::json_schema(gen)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::{from_slice, to_vec};
+
+    #[test]
+    fn test_operand_json() {
+        let operands = vec![
+            Operand::Value(Uint128(5)),
+            Operand::Previous,
+            Operand::HistoryIndex(3),
+            Operand::Register("tax".to_string()),
+        ];
+        let serialized = to_vec(&operands).unwrap();
+        assert_eq!(
+            String::from_utf8(serialized.clone()).unwrap(),
+            r#"["5","previous",{"history_index":3},{"register":"tax"}]"#
+        );
+        assert_eq!(from_slice::<Vec<Operand>>(&serialized).unwrap(), operands);
+
+        assert!(from_slice::<Operand>(br#""-5""#).is_err());
+        assert!(from_slice::<Operand>(br#""last""#).is_err());
+        assert!(from_slice::<Operand>(br#"{"previous":3}"#).is_err());
+    }
+}
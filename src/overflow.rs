@@ -0,0 +1,50 @@
+use crate::error::CalculatorResult;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// How a calculation handles a result that doesn't fit in its number type
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowMode {
+    /// The calculation fails
+    Checked,
+    /// The result is clamped to the largest (or smallest) number of the type
+    Saturating,
+    /// The result wraps around the bounds of the type
+    Wrapping,
+}
+
+impl Default for OverflowMode {
+    fn default() -> Self {
+        OverflowMode::Checked
+    }
+}
+
+impl OverflowMode {
+    /// Resolves the outcome of a checked calculation that can only fail by overflowing: the error
+    /// is kept in checked mode, and replaced by the saturated or wrapped result in the other modes
+    pub(crate) fn resolve<T>(
+        &self,
+        checked: CalculatorResult<T>,
+        saturated: impl FnOnce() -> T,
+        wrapped: impl FnOnce() -> T,
+    ) -> CalculatorResult<T> {
+        match (self, checked) {
+            (_, Ok(n)) => Ok(n),
+            (OverflowMode::Checked, Err(err)) => Err(err),
+            (OverflowMode::Saturating, Err(_)) => Ok(saturated()),
+            (OverflowMode::Wrapping, Err(_)) => Ok(wrapped()),
+        }
+    }
+}
+
+impl fmt::Display for OverflowMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OverflowMode::Checked => write!(f, "checked"),
+            OverflowMode::Saturating => write!(f, "saturating"),
+            OverflowMode::Wrapping => write!(f, "wrapping"),
+        }
+    }
+}
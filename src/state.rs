@@ -1,29 +1,464 @@
-use cosmwasm_std::{CanonicalAddr, ReadonlyStorage, StdError, StdResult, Storage};
+use crate::calculations_utils::{get_calculation_string, get_expression_string};
+use crate::decimal::{BigDecimal, Decimal};
+use crate::error::{CalculatorResult, InvalidInput};
+use crate::int128::Int128;
+use crate::msg::{ContractStatusLevel, Direction, HistoryCursor, ResponseStatus};
+use crate::overflow::OverflowMode;
+use crate::uint512::Uint512;
+use cosmwasm_std::{
+    CanonicalAddr, HumanAddr, ReadonlyStorage, StdError, StdResult, Storage, Uint128,
+};
 use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
 use schemars::JsonSchema;
 use secret_toolkit::serialization::{Bincode2, Serde};
 use secret_toolkit::storage::{AppendStore, AppendStoreMut};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use snafu::OptionExt;
 use std::any::type_name;
+use std::fmt;
 
 pub static CONFIG_KEY: &[u8] = b"config";
-const PREFIX_CALCULATIONS: &[u8] = b"calculations";
+pub static LATEST_BLOCK_KEY: &[u8] = b"latest_block";
+/// The calculations history as structured records. Histories of version 1 of the contract stay
+/// under their own prefix, and every read of a history falls back to them
+const PREFIX_CALCULATION_RECORDS: &[u8] = b"calculation_records";
+/// The calculations history of version 1 of the contract, which is stored as text
+const PREFIX_LEGACY_CALCULATIONS: &[u8] = b"calculations";
+/// The number of legacy entries already migrated, of users whose migration is in progress
+const PREFIX_LEGACY_MIGRATION_CURSORS: &[u8] = b"legacy_migration_cursors";
+const PREFIX_USER_SETTINGS: &[u8] = b"user_settings";
+const PREFIX_HISTORY_HEADS: &[u8] = b"history_heads";
+const PREFIX_REGISTERS: &[u8] = b"registers";
+pub const PREFIX_REVOKED_PERMITS: &str = "revoked_permits";
+
+/// The number of memory registers each user may hold
+pub const MAX_REGISTERS: usize = 16;
+/// The longest name a memory register may have
+pub const MAX_REGISTER_NAME_LENGTH: usize = 32;
+/// The most legacy entries migrated each time a calculation is recorded, which bounds the gas the
+/// migration of a long history adds to it
+pub const LEGACY_MIGRATION_CHUNK_SIZE: u32 = 16;
+
+/// The version of the contract's storage layout
+pub const CONTRACT_VERSION: u32 = 2;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct State {
     pub prng_seed: Vec<u8>,
+    /// The default number of decimal places of decimal calculations
+    pub decimal_places: u32,
+    /// The address of the contract, which query permits must be issued for
+    pub contract_address: HumanAddr,
+    pub admin: HumanAddr,
+    pub contract_status: ContractStatusLevel,
+    /// The version of the storage layout the contract's data is in
+    pub version: u32,
+}
+
+/// The latest block the contract handled a message in. Queries don't know the current block, and
+/// use this one instead
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct LatestBlock {
+    pub height: u64,
+    /// The block time, in seconds since the epoch
+    pub time: u64,
 }
 
+/// The configuration of version 1 of the contract
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct CalculationHistory {
-    /// history has the user's calculations history
-    pub history: Vec<u8>,
+pub struct LegacyState {
+    pub prng_seed: Vec<u8>,
+}
+
+/// An operand or result of a calculation
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Number {
+    Unsigned(Uint128),
+    Signed(Int128),
+    Decimal(Decimal),
+    /// The result of a test, such as a primality test
+    Boolean(bool),
+    Big(Uint512),
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Number::Unsigned(n) => n.fmt(f),
+            Number::Signed(n) => n.fmt(f),
+            Number::Decimal(n) => n.fmt(f),
+            Number::Boolean(b) => b.fmt(f),
+            Number::Big(n) => n.fmt(f),
+        }
+    }
+}
+
+impl Number {
+    /// Converts the number to an unsigned integer, failing for negative and fractional numbers
+    pub fn to_uint128(&self) -> CalculatorResult<Uint128> {
+        match self {
+            Number::Unsigned(n) => Some(*n),
+            Number::Signed(n) if !n.i128().is_negative() => Some(Uint128(n.i128() as u128)),
+            Number::Decimal(n) if n.decimal_places() == 0 => Some(Uint128(n.atomics())),
+            Number::Big(n) => n.to_uint128(),
+            _ => None,
+        }
+        .context(InvalidInput {
+            message: format!("{} is not an unsigned integer", self),
+        })
+    }
+}
+
+impl From<Uint512> for Number {
+    fn from(n: Uint512) -> Self {
+        Number::Big(n)
+    }
+}
+
+impl From<bool> for Number {
+    fn from(b: bool) -> Self {
+        Number::Boolean(b)
+    }
+}
+
+/// A prime factor of a number, along with its multiplicity
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Factor {
+    pub prime: Uint128,
+    pub exponent: u32,
+}
+
+impl fmt::Display for Factor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.exponent == 1 {
+            write!(f, "{}", self.prime)
+        } else {
+            write!(f, "{}^{}", self.prime, self.exponent)
+        }
+    }
+}
+
+/// Summary statistics of a list of numbers. The variance and standard deviation are those of the
+/// whole population, dividing by the number of values. The fractional statistics are BigDecimals,
+/// as the variance of 128-bit numbers can take 256 bits before its fractional digits
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Statistics {
+    pub count: u32,
+    pub sum: Uint128,
+    pub min: Uint128,
+    pub max: Uint128,
+    pub mean: BigDecimal,
+    pub median: BigDecimal,
+    /// The most frequent value, or the smallest of them if there are several
+    pub mode: Uint128,
+    pub variance: BigDecimal,
+    pub std_dev: BigDecimal,
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "sum {}, min {}, max {}, mean {}, median {}, mode {}, variance {}, std dev {}",
+            self.sum,
+            self.min,
+            self.max,
+            self.mean,
+            self.median,
+            self.mode,
+            self.variance,
+            self.std_dev
+        )
+    }
+}
+
+impl From<Uint128> for Number {
+    fn from(n: Uint128) -> Self {
+        Number::Unsigned(n)
+    }
+}
+
+impl From<Int128> for Number {
+    fn from(n: Int128) -> Self {
+        Number::Signed(n)
+    }
+}
+
+impl From<Decimal> for Number {
+    fn from(n: Decimal) -> Self {
+        Number::Decimal(n)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CalculationOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Sqrt,
+    /// The root of the first operand of the degree of the second one
+    NthRoot,
+    Pow,
+    Mod,
+    Log2,
+    Log10,
+    /// The logarithm of the first operand in the base of the second one
+    Log,
+    Gcd,
+    Lcm,
+    /// A number of basis points (the second operand) of the first operand
+    Percent,
+    /// The first operand minus a fee of a number of basis points (the second operand) of it
+    ApplyFee,
+    /// The product of the first two operands divided by the third one
+    MulDiv,
+    IsPrime,
+    /// The factors of the operand, which are the result of the calculation
+    Factorize {
+        factors: Vec<Factor>,
+    },
+    Evaluate {
+        expression: String,
+    },
+    /// Summary statistics of the operands, which are the result of the calculation
+    Stats {
+        statistics: Option<Box<Statistics>>,
+    },
 }
 
-impl CalculationHistory {
-    pub fn into_humanized(self) -> StdResult<String> {
-        Ok(String::from_utf8(self.history).unwrap())
+impl CalculationOp {
+    /// The symbol the operation is written with in the history display strings
+    pub fn symbol(&self) -> &str {
+        match self {
+            CalculationOp::Add => "+",
+            CalculationOp::Sub => "-",
+            CalculationOp::Mul => "*",
+            CalculationOp::Div => "/",
+            CalculationOp::Sqrt => "√",
+            CalculationOp::NthRoot => "root",
+            CalculationOp::Pow => "^",
+            CalculationOp::Mod => "mod",
+            CalculationOp::Log2 => "log2",
+            CalculationOp::Log10 => "log10",
+            CalculationOp::Log => "log",
+            CalculationOp::Gcd => "gcd",
+            CalculationOp::Lcm => "lcm",
+            CalculationOp::Percent => "bps of",
+            CalculationOp::ApplyFee => "bps fee",
+            CalculationOp::MulDiv => "*/",
+            CalculationOp::IsPrime => "isprime",
+            CalculationOp::Factorize { .. } => "factorize",
+            CalculationOp::Evaluate { .. } => "",
+            CalculationOp::Stats { .. } => "stats",
+        }
+    }
+
+    /// The name of the operation, as it is serialized
+    pub fn name(&self) -> &'static str {
+        match self {
+            CalculationOp::Add => "add",
+            CalculationOp::Sub => "sub",
+            CalculationOp::Mul => "mul",
+            CalculationOp::Div => "div",
+            CalculationOp::Sqrt => "sqrt",
+            CalculationOp::NthRoot => "nth_root",
+            CalculationOp::Pow => "pow",
+            CalculationOp::Mod => "mod",
+            CalculationOp::Log2 => "log2",
+            CalculationOp::Log10 => "log10",
+            CalculationOp::Log => "log",
+            CalculationOp::Gcd => "gcd",
+            CalculationOp::Lcm => "lcm",
+            CalculationOp::Percent => "percent",
+            CalculationOp::ApplyFee => "apply_fee",
+            CalculationOp::MulDiv => "mul_div",
+            CalculationOp::IsPrime => "is_prime",
+            CalculationOp::Factorize { .. } => "factorize",
+            CalculationOp::Evaluate { .. } => "evaluate",
+            CalculationOp::Stats { .. } => "stats",
+        }
+    }
+}
+
+/// A single entry of a user's calculations history
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CalculationRecord {
+    pub op: CalculationOp,
+    pub operands: Vec<Number>,
+    pub result: Option<Number>,
+    pub block_height: u64,
+    /// The block time, in seconds since the epoch
+    pub block_time: u64,
+    pub status: ResponseStatus,
+    /// The reason a failed calculation failed
+    pub error: Option<String>,
+    /// How the calculation handled overflows, if the sender chose it
+    pub overflow_mode: Option<OverflowMode>,
+    /// The calculation as text, e.g. "3 + 5 = 8". This is not stored, and is only filled in when
+    /// the record is returned from a query
+    pub display: Option<String>,
+}
+
+impl CalculationRecord {
+    /// Converts an entry of a history recorded by version 1 of the contract, such as "3 + 5 = 8"
+    /// or "√16 = 4". Version 1 didn't record block heights and times, which are left at 0, and
+    /// entries it can't have written are kept as evaluated expressions
+    pub fn from_legacy(text: &str) -> Self {
+        let (calculation, result) = match text.rfind(" = ") {
+            Some(index) => (&text[..index], text[index + " = ".len()..].parse().ok()),
+            None => (text, None),
+        };
+        let number = |n: &str| n.parse().ok().map(|n| Number::Unsigned(Uint128(n)));
+
+        let parts: Vec<&str> = calculation.split(' ').collect();
+        let parsed = match parts[..] {
+            [n1, symbol, n2] => {
+                let op = match symbol {
+                    "+" => Some(CalculationOp::Add),
+                    "-" => Some(CalculationOp::Sub),
+                    "*" => Some(CalculationOp::Mul),
+                    "/" => Some(CalculationOp::Div),
+                    _ => None,
+                };
+                op.zip(number(n1))
+                    .zip(number(n2))
+                    .map(|((op, n1), n2)| (op, vec![n1, n2]))
+            }
+            [n] => n
+                .strip_prefix('√')
+                .and_then(number)
+                .map(|n| (CalculationOp::Sqrt, vec![n])),
+            _ => None,
+        };
+        let (op, operands) = parsed.unwrap_or_else(|| {
+            let expression = calculation.to_string();
+            (CalculationOp::Evaluate { expression }, vec![])
+        });
+
+        CalculationRecord {
+            op,
+            operands,
+            result: result.map(|n| Number::Unsigned(Uint128(n))),
+            block_height: 0,
+            block_time: 0,
+            status: ResponseStatus::Success,
+            error: None,
+            overflow_mode: None,
+            display: None,
+        }
+    }
+
+    pub fn to_display_string(&self) -> String {
+        let result = match (self.result, &self.error, &self.op) {
+            (Some(result), _, _) => result.to_string(),
+            (None, Some(error), _) => format!("? ({})", error),
+            (None, None, CalculationOp::Factorize { factors }) => factors
+                .iter()
+                .map(Factor::to_string)
+                .collect::<Vec<_>>()
+                .join(" * "),
+            (
+                None,
+                None,
+                CalculationOp::Stats {
+                    statistics: Some(statistics),
+                },
+            ) => statistics.to_string(),
+            (None, None, _) => "?".to_string(),
+        };
+
+        let result = match self.overflow_mode {
+            Some(overflow_mode) if overflow_mode != OverflowMode::Checked => {
+                format!("{} ({})", result, overflow_mode)
+            }
+            _ => result,
+        };
+
+        match &self.op {
+            CalculationOp::Evaluate { expression } => get_expression_string(expression, result),
+            // Calculations whose operands couldn't be resolved are recorded without operands
+            CalculationOp::MulDiv => {
+                let operand = |i: usize| {
+                    self.operands
+                        .get(i)
+                        .map_or("?".to_string(), Number::to_string)
+                };
+                format!(
+                    "{} * {} / {} = {}",
+                    operand(0),
+                    operand(1),
+                    operand(2),
+                    result
+                )
+            }
+            CalculationOp::Stats { .. } => {
+                let operands: Vec<_> = self.operands.iter().map(Number::to_string).collect();
+                format!("stats({}) = {}", operands.join(", "), result)
+            }
+            op => {
+                let operand = |n: Option<&Number>| n.map_or("?".to_string(), Number::to_string);
+                get_calculation_string(
+                    operand(self.operands.first()),
+                    operand(self.operands.last()),
+                    op.symbol(),
+                    result,
+                )
+            }
+        }
+    }
+}
+
+/// Per-user preferences
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct UserSettings {
+    /// Whether failed calculations are recorded in the user's history
+    pub record_failures: bool,
+    /// The number of latest entries retained in the user's history, if it is limited
+    pub max_entries: Option<u32>,
+}
+
+pub fn load_user_settings<S: ReadonlyStorage>(storage: &S, key: &[u8]) -> StdResult<UserSettings> {
+    let storage = ReadonlyPrefixedStorage::new(PREFIX_USER_SETTINGS, storage);
+    Ok(may_load(&storage, key)?.unwrap_or_default())
+}
+
+pub fn save_user_settings<S: Storage>(
+    storage: &mut S,
+    key: &[u8],
+    settings: &UserSettings,
+) -> StdResult<()> {
+    let mut storage = PrefixedStorage::new(PREFIX_USER_SETTINGS, storage);
+    save(&mut storage, key, settings)
+}
+
+/// A named memory register of a user
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Register {
+    pub name: String,
+    pub value: Uint128,
+}
+
+/// Returns the memory registers of a user, in the order they were first written
+pub fn load_registers<S: ReadonlyStorage>(storage: &S, key: &[u8]) -> StdResult<Vec<Register>> {
+    let storage = ReadonlyPrefixedStorage::new(PREFIX_REGISTERS, storage);
+    Ok(may_load(&storage, key)?.unwrap_or_default())
+}
+
+pub fn save_registers<S: Storage>(
+    storage: &mut S,
+    key: &[u8],
+    registers: &[Register],
+) -> StdResult<()> {
+    let mut storage = PrefixedStorage::new(PREFIX_REGISTERS, storage);
+    if registers.is_empty() {
+        storage.remove(key);
+        Ok(())
+    } else {
+        save(&mut storage, key, &registers)
     }
 }
 
@@ -32,10 +467,367 @@ pub fn save<T: Serialize, S: Storage>(storage: &mut S, key: &[u8], value: &T) ->
     Ok(())
 }
 
-pub fn save_calculation<S: Storage>(storage: &mut S, key: &[u8], value: &str) -> StdResult<()> {
-    let mut storage = PrefixedStorage::multilevel(&[PREFIX_CALCULATIONS, key], storage);
-    let mut storage = AppendStoreMut::attach_or_create(&mut storage)?;
-    storage.push(&value.to_string())
+/// Appends a calculation to a user's history. Once the history holds as many entries as the user
+/// retains, its append store is used as a ring buffer, and the calculation replaces the oldest entry
+pub fn save_calculation<S: Storage>(
+    storage: &mut S,
+    key: &[u8],
+    value: &CalculationRecord,
+) -> StdResult<()> {
+    migrate_legacy_history(storage, key)?;
+    let max_entries = load_user_settings(storage, key)?.max_entries;
+    let head = load_history_head(storage, key)?;
+
+    let mut records = PrefixedStorage::multilevel(&[PREFIX_CALCULATION_RECORDS, key], storage);
+    let mut store = AppendStoreMut::attach_or_create(&mut records)?;
+    match max_entries {
+        Some(max_entries) if store.len() >= max_entries => {
+            store.set_at(head, value)?;
+            let head = (head + 1) % store.len();
+            save_history_head(storage, key, head)
+        }
+        _ => store.push(value),
+    }
+}
+
+/// Deletes all the entries of a user's history. Like every change of the history that drops
+/// entries, this only shortens the stores, whatever their length
+pub fn clear_calculations<S: Storage>(storage: &mut S, key: &[u8]) -> StdResult<()> {
+    let mut records = PrefixedStorage::multilevel(&[PREFIX_CALCULATION_RECORDS, key], storage);
+    set_store_length(&mut records, 0);
+    remove_legacy_history(storage, key)?;
+    save_history_head(storage, key, 0)
+}
+
+/// Deletes the entry at `index` of a user's history, counting from the oldest one. Returns
+/// whether there was such an entry
+pub fn delete_calculation<S: Storage>(storage: &mut S, key: &[u8], index: u32) -> StdResult<bool> {
+    // The migration of a legacy history reserves the positions of its entries in the records
+    migrate_legacy_history(storage, key)?;
+    let records = ReadonlyPrefixedStorage::multilevel(&[PREFIX_CALCULATION_RECORDS, key], storage);
+    let len = match AppendStore::<CalculationRecord, _>::attach(&records) {
+        Some(store) => store?.len(),
+        None => 0,
+    };
+    if index >= len {
+        return Ok(false);
+    }
+
+    // A history that wraps around the end of its store is limited by its retention, and is moved
+    // back to the start of the store
+    if load_history_head(storage, key)? != 0 {
+        let mut records = read_history(storage, key)?;
+        records.remove(index as usize);
+        rewrite_history(storage, key, &records)?;
+        return Ok(true);
+    }
+
+    // Only the entries after the deleted one are moved, down by one position. The entries not
+    // migrated yet are at the positions from the migration cursor to the length of the legacy
+    // history, and both move down too when they are after the deleted entry, so that each entry
+    // moves within the store it is in
+    let cursor = load_migration_cursor(storage, key)?;
+    let legacy = ReadonlyPrefixedStorage::multilevel(&[PREFIX_LEGACY_CALCULATIONS, key], storage);
+    let (cursor, legacy_len) = match (cursor, AppendStore::<String, _>::attach(&legacy)) {
+        (Some(cursor), Some(store)) => (cursor, store?.len()),
+        _ => (0, 0),
+    };
+    for position in index..len - 1 {
+        let next = position + 1;
+        let prefix = if cursor <= next && next < legacy_len {
+            PREFIX_LEGACY_CALCULATIONS
+        } else {
+            PREFIX_CALCULATION_RECORDS
+        };
+        let mut entries = PrefixedStorage::multilevel(&[prefix, key], storage);
+        if let Some(entry) = entries.get(&next.to_be_bytes()) {
+            entries.set(&position.to_be_bytes(), &entry);
+        }
+    }
+
+    let mut records = PrefixedStorage::multilevel(&[PREFIX_CALCULATION_RECORDS, key], storage);
+    records.remove(&(len - 1).to_be_bytes());
+    set_store_length(&mut records, len - 1);
+    if index < legacy_len {
+        let mut legacy = PrefixedStorage::multilevel(&[PREFIX_LEGACY_CALCULATIONS, key], storage);
+        legacy.remove(&(legacy_len - 1).to_be_bytes());
+        set_store_length(&mut legacy, legacy_len - 1);
+        let cursor = if index < cursor { cursor - 1 } else { cursor };
+        if cursor < legacy_len - 1 {
+            save_migration_cursor(storage, key, Some(cursor))?;
+        } else {
+            remove_legacy_history(storage, key)?;
+        }
+    }
+    Ok(true)
+}
+
+/// Sets the number of entries retained in a user's history, and drops the oldest entries beyond it
+pub fn set_history_retention<S: Storage>(
+    storage: &mut S,
+    key: &[u8],
+    max_entries: Option<u32>,
+) -> StdResult<()> {
+    let mut settings = load_user_settings(storage, key)?;
+    settings.max_entries = max_entries;
+    save_user_settings(storage, key, &settings)?;
+
+    // The retained entries are moved back to the start of the store, so that it can grow again.
+    // Nothing is read unless the history is trimmed or wraps around
+    let retained = {
+        let history = HistoryStorage::new(storage, key)?;
+        let store = match history.attach()? {
+            Some(store) => store,
+            None => return Ok(()),
+        };
+        let len = store.len();
+        let retained = max_entries.map_or(len, |max_entries| len.min(max_entries));
+        if retained == len && history.head == 0 {
+            return Ok(());
+        }
+        (len - retained..len)
+            .map(|index| store.get_at(index))
+            .collect::<StdResult<Vec<_>>>()?
+    };
+    rewrite_history(storage, key, &retained)
+}
+
+/// Returns the position in a user's append store of the oldest entry of their history, which is
+/// only moved from the start of the store by the ring buffer of a full history
+fn load_history_head<S: ReadonlyStorage>(storage: &S, key: &[u8]) -> StdResult<u32> {
+    let storage = ReadonlyPrefixedStorage::new(PREFIX_HISTORY_HEADS, storage);
+    Ok(may_load(&storage, key)?.unwrap_or_default())
+}
+
+fn save_history_head<S: Storage>(storage: &mut S, key: &[u8], head: u32) -> StdResult<()> {
+    let mut storage = PrefixedStorage::new(PREFIX_HISTORY_HEADS, storage);
+    if head == 0 {
+        storage.remove(key);
+        Ok(())
+    } else {
+        save(&mut storage, key, &head)
+    }
+}
+
+/// Returns all the entries of a user's history, oldest first
+fn read_history<S: ReadonlyStorage>(storage: &S, key: &[u8]) -> StdResult<Vec<CalculationRecord>> {
+    let storage = HistoryStorage::new(storage, key)?;
+    match storage.attach()? {
+        Some(store) => (0..store.len()).map(|index| store.get_at(index)).collect(),
+        None => Ok(vec![]),
+    }
+}
+
+/// Replaces the entries of a user's history with `records`, oldest first, starting at the start of
+/// its store, and drops whatever is left of the legacy history
+fn rewrite_history<S: Storage>(
+    storage: &mut S,
+    key: &[u8],
+    records: &[CalculationRecord],
+) -> StdResult<()> {
+    let mut history = PrefixedStorage::multilevel(&[PREFIX_CALCULATION_RECORDS, key], storage);
+    {
+        let mut store = AppendStoreMut::attach_or_create(&mut history)?;
+        let len = store.len();
+        for (position, record) in (0..).zip(records) {
+            if position < len {
+                store.set_at(position, record)?;
+            } else {
+                store.push(record)?;
+            }
+        }
+    }
+    set_store_length(&mut history, records.len() as u32);
+    remove_legacy_history(storage, key)?;
+    save_history_head(storage, key, 0)
+}
+
+/// Sets the length of an append store. The append store keeps its length at `len`, and each entry
+/// at the big-endian bytes of its position, and it only reads and writes the positions below its
+/// length. Entries beyond a shorter length are left unreadable until they are overwritten, which
+/// keeps dropping entries cheap however many there are
+fn set_store_length<S: Storage>(storage: &mut S, len: u32) {
+    storage.set(b"len", &len.to_be_bytes());
+}
+
+/// Moves the history a user recorded with version 1 of the contract, if any, to their records, at
+/// most `LEGACY_MIGRATION_CHUNK_SIZE` entries at a time. The positions of all the legacy entries
+/// are reserved at the start of the records when the migration starts, so that newer calculations
+/// are appended after them, and reads fall back to the legacy history for the ones not migrated yet
+fn migrate_legacy_history<S: Storage>(storage: &mut S, key: &[u8]) -> StdResult<()> {
+    let cursor = match load_migration_cursor(storage, key)? {
+        Some(cursor) => cursor,
+        None => {
+            // Users with records and no migration in progress have no legacy history left
+            let records =
+                ReadonlyPrefixedStorage::multilevel(&[PREFIX_CALCULATION_RECORDS, key], storage);
+            if AppendStore::<CalculationRecord, _>::attach(&records).is_some() {
+                return Ok(());
+            }
+            let legacy =
+                ReadonlyPrefixedStorage::multilevel(&[PREFIX_LEGACY_CALCULATIONS, key], storage);
+            let len = match AppendStore::<String, _>::attach(&legacy) {
+                Some(legacy) => legacy?.len(),
+                None => return Ok(()),
+            };
+            let mut records =
+                PrefixedStorage::multilevel(&[PREFIX_CALCULATION_RECORDS, key], storage);
+            set_store_length(&mut records, len);
+            0
+        }
+    };
+
+    let mut legacy = PrefixedStorage::multilevel(&[PREFIX_LEGACY_CALCULATIONS, key], storage);
+    let (len, texts) = match AppendStore::<String, _>::attach(&legacy) {
+        Some(store) => {
+            let store = store?;
+            let end = store
+                .len()
+                .min(cursor.saturating_add(LEGACY_MIGRATION_CHUNK_SIZE));
+            let texts = (cursor..end)
+                .map(|position| store.get_at(position))
+                .collect::<StdResult<Vec<_>>>()?;
+            (store.len(), texts)
+        }
+        None => (cursor, vec![]),
+    };
+    let end = cursor + texts.len() as u32;
+    for position in cursor..end {
+        legacy.remove(&position.to_be_bytes());
+    }
+
+    let mut records = PrefixedStorage::multilevel(&[PREFIX_CALCULATION_RECORDS, key], storage);
+    let mut records = AppendStoreMut::attach_or_create(&mut records)?;
+    for (position, text) in (cursor..).zip(&texts) {
+        records.set_at(position, &CalculationRecord::from_legacy(text))?;
+    }
+    if end < len {
+        save_migration_cursor(storage, key, Some(end))
+    } else {
+        remove_legacy_history(storage, key)
+    }
+}
+
+/// Drops the legacy entries of a user's history that aren't migrated yet, by removing the length of
+/// the legacy history
+fn remove_legacy_history<S: Storage>(storage: &mut S, key: &[u8]) -> StdResult<()> {
+    let mut legacy = PrefixedStorage::multilevel(&[PREFIX_LEGACY_CALCULATIONS, key], storage);
+    legacy.remove(b"len");
+    save_migration_cursor(storage, key, None)
+}
+
+fn load_migration_cursor<S: ReadonlyStorage>(storage: &S, key: &[u8]) -> StdResult<Option<u32>> {
+    let storage = ReadonlyPrefixedStorage::new(PREFIX_LEGACY_MIGRATION_CURSORS, storage);
+    may_load(&storage, key)
+}
+
+fn save_migration_cursor<S: Storage>(
+    storage: &mut S,
+    key: &[u8],
+    cursor: Option<u32>,
+) -> StdResult<()> {
+    let mut storage = PrefixedStorage::new(PREFIX_LEGACY_MIGRATION_CURSORS, storage);
+    match cursor {
+        Some(cursor) => save(&mut storage, key, &cursor),
+        None => {
+            storage.remove(key);
+            Ok(())
+        }
+    }
+}
+
+/// The storage of a user's calculations history
+struct HistoryStorage<'a, S: ReadonlyStorage> {
+    records: ReadonlyPrefixedStorage<'a, S>,
+    legacy: ReadonlyPrefixedStorage<'a, S>,
+    head: u32,
+    migration_cursor: Option<u32>,
+}
+
+impl<'a, S: ReadonlyStorage> HistoryStorage<'a, S> {
+    fn new(storage: &'a S, key: &[u8]) -> StdResult<Self> {
+        Ok(HistoryStorage {
+            records: ReadonlyPrefixedStorage::multilevel(
+                &[PREFIX_CALCULATION_RECORDS, key],
+                storage,
+            ),
+            legacy: ReadonlyPrefixedStorage::multilevel(
+                &[PREFIX_LEGACY_CALCULATIONS, key],
+                storage,
+            ),
+            head: load_history_head(storage, key)?,
+            migration_cursor: load_migration_cursor(storage, key)?,
+        })
+    }
+
+    /// Attaches to the records of the user, or to their legacy history if they have no records
+    fn attach(&self) -> StdResult<Option<History<'_, ReadonlyPrefixedStorage<'a, S>>>> {
+        if let Some(store) = AppendStore::attach(&self.records) {
+            let unmigrated = match self.migration_cursor {
+                Some(cursor) => AppendStore::attach(&self.legacy)
+                    .transpose()?
+                    .map(|legacy| (legacy, cursor)),
+                None => None,
+            };
+            return store.map(|store| {
+                Some(History::Records {
+                    store,
+                    head: self.head,
+                    unmigrated,
+                })
+            });
+        }
+        AppendStore::attach(&self.legacy)
+            .transpose()
+            .map(|store| store.map(History::Legacy))
+    }
+}
+
+/// A user's calculations history, indexed from the oldest entry. Histories recorded by version 1
+/// of the contract are read from their text until they are migrated
+enum History<'a, S: ReadonlyStorage> {
+    Records {
+        store: AppendStore<'a, CalculationRecord, S>,
+        head: u32,
+        /// The legacy history and the position of its first entry not migrated yet, while the
+        /// migration is in progress
+        unmigrated: Option<(AppendStore<'a, String, S>, u32)>,
+    },
+    Legacy(AppendStore<'a, String, S>),
+}
+
+impl<'a, S: ReadonlyStorage> History<'a, S> {
+    fn len(&self) -> u32 {
+        match self {
+            History::Records { store, .. } => store.len(),
+            History::Legacy(store) => store.len(),
+        }
+    }
+
+    fn get_at(&self, index: u32) -> StdResult<CalculationRecord> {
+        match self {
+            History::Records {
+                store,
+                head,
+                unmigrated,
+            } => {
+                // The ring buffer of a full history wraps around the end of the store
+                let position = (u64::from(*head) + u64::from(index)) % u64::from(store.len());
+                let position = position as u32;
+                match unmigrated {
+                    Some((legacy, cursor)) if position >= *cursor && position < legacy.len() => {
+                        legacy
+                            .get_at(position)
+                            .map(|text| CalculationRecord::from_legacy(&text))
+                    }
+                    _ => store.get_at(position),
+                }
+            }
+            History::Legacy(store) => store
+                .get_at(index)
+                .map(|text| CalculationRecord::from_legacy(&text)),
+        }
+    }
 }
 
 pub fn load<T: DeserializeOwned, S: ReadonlyStorage>(storage: &S, key: &[u8]) -> StdResult<T> {
@@ -56,45 +848,176 @@ pub fn may_load<T: DeserializeOwned, S: ReadonlyStorage>(
     }
 }
 
+/// A page of a user's calculations history
+pub struct HistoryPage {
+    pub records: Vec<CalculationRecord>,
+    pub status: String,
+    /// The number of entries in the history
+    pub total: u32,
+    /// The index to continue reading from with a cursor, if there are more entries to read
+    pub next: Option<u32>,
+}
+
 pub fn get_transfers<S: ReadonlyStorage>(
     storage: &S,
     for_address: &CanonicalAddr,
     page: u32,
     page_size: u32,
-) -> StdResult<(Vec<String>, String)> {
-    let store = ReadonlyPrefixedStorage::multilevel(
-        &[PREFIX_CALCULATIONS, for_address.as_slice()],
-        storage,
-    );
+    include_failures: bool,
+    cursor: Option<HistoryCursor>,
+) -> StdResult<HistoryPage> {
+    let storage = HistoryStorage::new(storage, for_address.as_slice())?;
 
     // Try to access the storage of transfers for the account.
     // If it doesn't exist yet, return an empty list of transfers.
-    let store = AppendStore::<CalculationHistory, _, _>::attach(&store);
-    let store = if let Some(result) = store {
-        result?
+    let store = if let Some(store) = storage.attach()? {
+        store
     } else {
-        return Ok((vec![], "No calculations history".to_string()));
+        return Ok(HistoryPage {
+            records: vec![],
+            status: "No calculations history".to_string(),
+            total: 0,
+            next: None,
+        });
     };
+    let is_included =
+        |record: &CalculationRecord| include_failures || record.status == ResponseStatus::Success;
 
-    // Take `page_size` txs starting from the latest tx, potentially skipping `page * page_size`
-    // txs from the start.
-    let calculations_history_iter = store
-        .iter()
-        .rev()
-        .skip((page * page_size) as _)
-        .take(page_size as _);
-
-    // The `and_then` here flattens the `StdResult<StdResult<RichTx>>` to an `StdResult<RichTx>`
-    let calculations_history: StdResult<Vec<String>> = calculations_history_iter
-        .map(|history| {
-            history
-                .map(|history| history.into_humanized())
-                .and_then(|x| x)
-        })
-        .collect();
+    let (calculations_history, next) = match cursor {
+        // Take `page_size` txs starting from the latest tx, potentially skipping `page * page_size`
+        // txs from the start.
+        None => {
+            let calculations_history_iter = (0..store.len())
+                .rev()
+                .map(|index| store.get_at(index))
+                .filter(|record| match record {
+                    Ok(record) => is_included(record),
+                    Err(_) => true,
+                })
+                .skip((page * page_size) as _)
+                .take(page_size as _);
+
+            let calculations_history: StdResult<Vec<CalculationRecord>> =
+                calculations_history_iter.collect();
+            (calculations_history?, None)
+        }
+        // Read the txs one by one from the cursor, without going through the ones before it
+        Some(cursor) => {
+            let mut calculations_history = vec![];
+            let mut index = match cursor.direction {
+                Direction::Forward => Some(cursor.start).filter(|start| *start < store.len()),
+                Direction::Backward => store
+                    .len()
+                    .checked_sub(1)
+                    .map(|last| last.min(cursor.start)),
+            };
+            while let Some(current) = index {
+                if calculations_history.len() == page_size as usize {
+                    break;
+                }
+                let record = store.get_at(current)?;
+                if is_included(&record) {
+                    calculations_history.push(record);
+                }
+                index = match cursor.direction {
+                    Direction::Forward => Some(current + 1).filter(|next| *next < store.len()),
+                    Direction::Backward => current.checked_sub(1),
+                };
+            }
+            (calculations_history, index)
+        }
+    };
+
+    Ok(HistoryPage {
+        records: calculations_history,
+        status: "Calculations history present".to_string(),
+        total: store.len(),
+        next,
+    })
+}
+
+/// Returns the result of the latest successful calculation in the history of `for_address`
+pub fn get_last_result<S: ReadonlyStorage>(
+    storage: &S,
+    for_address: &CanonicalAddr,
+) -> StdResult<Option<Number>> {
+    let storage = HistoryStorage::new(storage, for_address.as_slice())?;
+    let store = match storage.attach()? {
+        Some(store) => store,
+        None => return Ok(None),
+    };
+
+    for index in (0..store.len()).rev() {
+        if let Some(result) = store.get_at(index)?.result {
+            return Ok(Some(result));
+        }
+    }
+    Ok(None)
+}
+
+/// How many calculations of an operation a history has
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OperationCount {
+    pub op: String,
+    pub count: u32,
+}
+
+/// Summary statistics of a user's history, which don't reveal the calculations themselves
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct HistoryCounts {
+    /// The number of entries in the history, including failures
+    pub total: u32,
+    pub failures: u32,
+    /// The number of entries of each operation in the history, in the order they first appear
+    pub operations: Vec<OperationCount>,
+}
+
+/// Counts the entries of the history of `for_address`
+pub fn get_history_counts<S: ReadonlyStorage>(
+    storage: &S,
+    for_address: &CanonicalAddr,
+) -> StdResult<HistoryCounts> {
+    let storage = HistoryStorage::new(storage, for_address.as_slice())?;
+    let store = match storage.attach()? {
+        Some(store) => store,
+        None => return Ok(HistoryCounts::default()),
+    };
+
+    let mut counts = HistoryCounts {
+        total: store.len(),
+        ..HistoryCounts::default()
+    };
+    for index in 0..store.len() {
+        let record = store.get_at(index)?;
+        if record.status != ResponseStatus::Success {
+            counts.failures += 1;
+        }
+        let name = record.op.name();
+        match counts.operations.iter_mut().find(|count| count.op == name) {
+            Some(count) => count.count += 1,
+            None => counts.operations.push(OperationCount {
+                op: name.to_string(),
+                count: 1,
+            }),
+        }
+    }
+    Ok(counts)
+}
 
-    Ok((
-        calculations_history.unwrap(),
-        "Calculations history present".to_string(),
-    ))
+/// Returns the entry at `index` of the history of `for_address`, counting from the oldest one
+pub fn get_calculation<S: ReadonlyStorage>(
+    storage: &S,
+    for_address: &CanonicalAddr,
+    index: u32,
+) -> StdResult<Option<CalculationRecord>> {
+    let storage = HistoryStorage::new(storage, for_address.as_slice())?;
+    let store = match storage.attach()? {
+        Some(store) => store,
+        None => return Ok(None),
+    };
+
+    if index >= store.len() {
+        return Ok(None);
+    }
+    store.get_at(index).map(Some)
 }
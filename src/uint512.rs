@@ -0,0 +1,178 @@
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::{de, ser, Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use cosmwasm_std::{StdError, Uint128};
+
+use crate::big_uint::{U1024, U512};
+use crate::error::{CalculatorResult, Overflow};
+use snafu::ensure;
+
+/// The most decimal digits a Uint512 may have. This keeps the cost of big integer calculations
+/// predictable, and leaves room for intermediate results within 512 bits
+pub const MAX_UINT512_DIGITS: usize = 150;
+
+/// An unsigned integer of up to MAX_UINT512_DIGITS digits, serialized as a decimal string
+/// (e.g. "1000000000000000000000000000000000000000") the same way `Uint128` is.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Uint512(U512);
+
+impl Uint512 {
+    /// Creates a Uint512 from a 512-bit integer, failing if it has too many digits
+    pub(crate) fn new(value: U512) -> CalculatorResult<Self> {
+        ensure!(
+            value < U512::exp10(MAX_UINT512_DIGITS),
+            Overflow {
+                message: format!("Big integers are limited to {} digits", MAX_UINT512_DIGITS),
+            }
+        );
+        Ok(Uint512(value))
+    }
+
+    /// Creates a Uint512 from a 1024-bit integer wrapped around modulo 10^MAX_UINT512_DIGITS, the
+    /// way fixed-size integers wrap around their bounds
+    pub(crate) fn wrapping(value: U1024) -> Self {
+        Uint512((value % U1024::from(U512::exp10(MAX_UINT512_DIGITS))).low_u512())
+    }
+
+    pub const fn zero() -> Self {
+        Uint512(U512::zero())
+    }
+
+    /// The largest Uint512, made of MAX_UINT512_DIGITS nines
+    pub fn max() -> Self {
+        Uint512(U512::exp10(MAX_UINT512_DIGITS) - 1)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+
+    /// Returns a copy of the internal data
+    pub(crate) fn u512(&self) -> U512 {
+        self.0
+    }
+
+    /// Converts the number to a Uint128, if it fits in one
+    pub fn to_uint128(&self) -> Option<Uint128> {
+        if self.0.bits() > 128 {
+            return None;
+        }
+        Some(Uint128(self.0.low_u128()))
+    }
+}
+
+impl From<u128> for Uint512 {
+    fn from(val: u128) -> Self {
+        Uint512(U512::from(val))
+    }
+}
+
+impl From<Uint128> for Uint512 {
+    fn from(val: Uint128) -> Self {
+        Uint512(U512::from(val.u128()))
+    }
+}
+
+impl FromStr for Uint512 {
+    type Err = StdError;
+
+    fn from_str(val: &str) -> Result<Self, Self::Err> {
+        if val.is_empty() || !val.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(StdError::generic_err(format!(
+                "Parsing big integer: invalid number '{}'",
+                val
+            )));
+        }
+        if val.trim_start_matches('0').len() > MAX_UINT512_DIGITS {
+            return Err(StdError::generic_err(format!(
+                "Parsing big integer: numbers are limited to {} digits",
+                MAX_UINT512_DIGITS
+            )));
+        }
+        U512::from_dec_str(val)
+            .map(Uint512)
+            .map_err(|e| StdError::generic_err(format!("Parsing big integer: {:?}", e)))
+    }
+}
+
+impl fmt::Display for Uint512 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl JsonSchema for Uint512 {
+    fn schema_name() -> String {
+        "Uint512".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        String::json_schema(gen)
+    }
+}
+
+/// Serializes as a decimal string
+impl Serialize for Uint512 {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// Deserializes from a decimal string
+impl<'de> Deserialize<'de> for Uint512 {
+    fn deserialize<D>(deserializer: D) -> Result<Uint512, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(Uint512Visitor)
+    }
+}
+
+struct Uint512Visitor;
+
+impl<'de> de::Visitor<'de> for Uint512Visitor {
+    type Value = Uint512;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("string-encoded big integer")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Uint512::from_str(v).map_err(|e| E::custom(format!("Error parsing '{}': {}", v, e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::{from_slice, to_vec};
+
+    #[test]
+    fn test_uint512_json() {
+        let digits = "1".repeat(MAX_UINT512_DIGITS);
+        let json = format!("\"{}\"", digits);
+        let n: Uint512 = from_slice(json.as_bytes()).unwrap();
+        assert_eq!(n.to_string(), digits);
+        assert_eq!(to_vec(&n).unwrap(), json.as_bytes());
+        assert_eq!(n.to_uint128(), None);
+        assert_eq!(
+            from_slice::<Uint512>(b"\"0042\"").unwrap().to_uint128(),
+            Some(Uint128(42))
+        );
+
+        let too_long = format!("\"{}\"", "1".repeat(MAX_UINT512_DIGITS + 1));
+        assert!(from_slice::<Uint512>(too_long.as_bytes()).is_err());
+        assert!(from_slice::<Uint512>(b"\"-1\"").is_err());
+        assert!(from_slice::<Uint512>(b"\"\"").is_err());
+    }
+}
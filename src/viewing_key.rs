@@ -5,14 +5,21 @@ use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{CanonicalAddr, Env, Storage};
+use cosmwasm_std::{CanonicalAddr, Env, StdResult, Storage};
 use secret_toolkit::crypto::{sha_256, Prng};
 
+use crate::state::{may_load, save};
 use crate::utils::{create_hashed_password, ct_slice_compare};
 
 pub const VIEWING_KEY_SIZE: usize = 32;
 pub const VIEWING_KEY_PREFIX: &str = "api_key_";
 pub const PREFIX_VIEWING_KEY: &[u8] = b"viewingkey";
+pub const PREFIX_VIEWING_KEY_INFO: &[u8] = b"viewingkeyinfo";
+pub const PREFIX_SCOPED_VIEWING_KEYS: &[u8] = b"scopedviewingkeys";
+/// The longest label a viewing key may have
+pub const MAX_VIEWING_KEY_LABEL_LENGTH: usize = 64;
+/// The most scoped viewing keys an address may have, besides its main key
+pub const MAX_SCOPED_VIEWING_KEYS: usize = 16;
 
 #[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub struct ViewingKey(pub String);
@@ -50,15 +57,127 @@ impl ViewingKey {
         self.0.as_bytes()
     }
 
-    pub fn write_viewing_key<S: Storage>(store: &mut S, owner: &CanonicalAddr, key: &ViewingKey) {
+    pub fn write_viewing_key<S: Storage>(
+        store: &mut S,
+        owner: &CanonicalAddr,
+        key: &ViewingKey,
+        info: &ViewingKeyInfo,
+    ) -> StdResult<()> {
         let mut user_key_store = PrefixedStorage::new(PREFIX_VIEWING_KEY, store);
         user_key_store.set(owner.as_slice(), &key.to_hashed());
+        let mut user_key_info_store = PrefixedStorage::new(PREFIX_VIEWING_KEY_INFO, store);
+        save(&mut user_key_info_store, owner.as_slice(), info)
     }
 
     pub fn read_viewing_key<S: Storage>(store: &S, owner: &CanonicalAddr) -> Option<Vec<u8>> {
         let user_key_store = ReadonlyPrefixedStorage::new(PREFIX_VIEWING_KEY, store);
         user_key_store.get(owner.as_slice())
     }
+
+    /// Returns the metadata of the viewing key of `owner`. Keys set before metadata was stored
+    /// have none
+    pub fn read_viewing_key_info<S: Storage>(
+        store: &S,
+        owner: &CanonicalAddr,
+    ) -> StdResult<Option<ViewingKeyInfo>> {
+        let user_key_info_store = ReadonlyPrefixedStorage::new(PREFIX_VIEWING_KEY_INFO, store);
+        may_load(&user_key_info_store, owner.as_slice())
+    }
+
+    pub fn remove_viewing_key<S: Storage>(store: &mut S, owner: &CanonicalAddr) {
+        PrefixedStorage::new(PREFIX_VIEWING_KEY, store).remove(owner.as_slice());
+        PrefixedStorage::new(PREFIX_VIEWING_KEY_INFO, store).remove(owner.as_slice());
+    }
+
+    pub fn read_scoped_viewing_keys<S: ReadonlyStorage>(
+        store: &S,
+        owner: &CanonicalAddr,
+    ) -> StdResult<Vec<ScopedViewingKey>> {
+        let scoped_keys_store = ReadonlyPrefixedStorage::new(PREFIX_SCOPED_VIEWING_KEYS, store);
+        Ok(may_load(&scoped_keys_store, owner.as_slice())?.unwrap_or_default())
+    }
+
+    pub fn write_scoped_viewing_keys<S: Storage>(
+        store: &mut S,
+        owner: &CanonicalAddr,
+        keys: &[ScopedViewingKey],
+    ) -> StdResult<()> {
+        let mut scoped_keys_store = PrefixedStorage::new(PREFIX_SCOPED_VIEWING_KEYS, store);
+        if keys.is_empty() {
+            scoped_keys_store.remove(owner.as_slice());
+            return Ok(());
+        }
+        save(&mut scoped_keys_store, owner.as_slice(), &keys.to_vec())
+    }
+
+    /// Returns the scoped key of `owner` this key matches, if any. Every key is checked, so the
+    /// time it takes doesn't tell which one matched
+    pub fn find_scoped_viewing_key<S: ReadonlyStorage>(
+        &self,
+        store: &S,
+        owner: &CanonicalAddr,
+    ) -> StdResult<Option<ScopedViewingKey>> {
+        let hashed = self.to_hashed();
+        let mut found = None;
+        for scoped_key in Self::read_scoped_viewing_keys(store, owner)? {
+            if ct_slice_compare(&hashed, &scoped_key.hashed_key) && found.is_none() {
+                found = Some(scoped_key);
+            }
+        }
+        Ok(found)
+    }
+}
+
+/// The queries a viewing key can be used for
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ViewingKeyScope {
+    /// The full history, including operands and results. Grants `counts` too
+    History,
+    /// Summary statistics of the history, without the calculations themselves
+    Counts,
+    /// The memory registers
+    Registers,
+}
+
+impl ViewingKeyScope {
+    pub fn grants(&self, scope: ViewingKeyScope) -> bool {
+        *self == scope || (*self == ViewingKeyScope::History && scope == ViewingKeyScope::Counts)
+    }
+}
+
+/// A labeled viewing key an address holds besides its main key, which can only be used for the
+/// queries of its scopes
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ScopedViewingKey {
+    pub hashed_key: Vec<u8>,
+    pub scopes: Vec<ViewingKeyScope>,
+    pub info: ViewingKeyInfo,
+}
+
+/// When and how a viewing key was set, and when it expires. Queries only know the latest block the
+/// contract handled a message other than a calculation in, so a key keeps working past its expiry
+/// until the contract handles such a message
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq)]
+pub struct ViewingKeyInfo {
+    pub label: Option<String>,
+    pub created_at_height: u64,
+    /// The block time the key was set at, in seconds since the epoch
+    pub created_at_time: u64,
+    /// The key can't be used from this block height on
+    pub expires_at_height: Option<u64>,
+    /// The key can't be used from this block time on, in seconds since the epoch
+    pub expires_at_time: Option<u64>,
+}
+
+impl ViewingKeyInfo {
+    pub fn is_expired(&self, height: u64, time: u64) -> bool {
+        self.expires_at_height
+            .map_or(false, |expires_at_height| height >= expires_at_height)
+            || self
+                .expires_at_time
+                .map_or(false, |expires_at_time| time >= expires_at_time)
+    }
 }
 
 impl fmt::Display for ViewingKey {